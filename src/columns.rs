@@ -0,0 +1,46 @@
+/// The date of the transaction.
+pub const DATE: &str = "Date";
+/// The date the transaction was posted, if different from the transaction date.
+pub const POSTED_DATE: &str = "PostedDate";
+/// The payee of the transaction.
+pub const PAYEE: &str = "Payee";
+/// The amount of the transaction.
+pub const AMOUNT: &str = "Amount";
+/// The category of the transaction.
+pub const CATEGORY: &str = "Category";
+/// The memo of the transaction.
+pub const MEMO: &str = "Memo";
+/// The check number of the transaction.
+pub const CHECK: &str = "Check#";
+/// The address of the payee.
+pub const ADDRESS: &str = "Address";
+/// The debit amount of the transaction (used if there is no Amount column).
+pub const DEBIT: &str = "Debit";
+/// The credit amount of the transaction (used if there is no Amount column).
+pub const CREDIT: &str = "Credit";
+
+/// The running balance of the account (used to derive the amount if requested,
+/// and written after the other columns if a running balance is requested).
+pub const BALANCE: &str = "Balance";
+
+/// The file from which the transaction was read (only written if requested).
+pub const SOURCE: &str = "Source";
+
+/// The columns written to the normalized CSV, in the order in which they are written.
+pub const OUTPUT: [&str; 6] = [DATE, PAYEE, CATEGORY, MEMO, AMOUNT, CHECK];
+
+/// The columns that must be present in every row.
+/// The Amount column may be replaced with the Debit and Credit columns.
+pub const REQUIRED: [&str; 3] = [DATE, PAYEE, AMOUNT];
+
+/// The columns that are used if they are present.
+pub const OPTIONAL: [&str; 8] = [
+    CATEGORY,
+    MEMO,
+    CHECK,
+    ADDRESS,
+    DEBIT,
+    CREDIT,
+    BALANCE,
+    POSTED_DATE,
+];
@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// The distinct ways in which tidymoney can fail.
+#[derive(Debug, Error)]
+pub enum TidymoneyError {
+    /// A required column was not present in the transaction data for an account.
+    #[error("The account '{account}' is missing the {column} column")]
+    MissingColumn { account: String, column: String },
+    /// A row gave both a debit and a credit, so the amount is ambiguous.
+    #[error("The account '{account}' has both a Debit ({debit:#?}) and Credit ({credit:#?}) in the same row")]
+    ConflictingAmounts {
+        account: String,
+        debit: String,
+        credit: String,
+    },
+    /// A value in a column could not be interpreted.
+    #[error("The account '{account}' has an invalid {column} value {value:#?}")]
+    InvalidValue {
+        account: String,
+        column: String,
+        value: String,
+    },
+    /// The amount_from pattern of an account did not match the column it searches.
+    #[error("The account '{account}' has a {column} value {value:#?} that does not match the amount_from pattern {pattern:#?}")]
+    UnmatchedAmount {
+        account: String,
+        column: String,
+        pattern: String,
+        value: String,
+    },
+    /// A date could not be parsed with the given format string.
+    #[error("Cannot parse the date {date:#?} with the format string {format:#?}")]
+    UnparseableDate { date: String, format: String },
+    /// Many dates in one file could not be parsed, which usually means the date_fmt is wrong.
+    #[error("The account '{account}' has {failures} of {rows} dates that cannot be parsed with the date_fmt {format:#?} (such as {sample:#?}) - the date_fmt is probably wrong")]
+    DateFormatMismatch {
+        account: String,
+        format: String,
+        sample: String,
+        failures: usize,
+        rows: usize,
+    },
+    /// No account in the rules file matches the headers of a CSV file.
+    #[error("No rules are defined for the account corresponding to file {0:#?}")]
+    NoMatchingAccount(PathBuf),
+    /// No account in the rules file has the given label.
+    #[error("No account is labeled {0:#?} in the rules file")]
+    UnknownAccount(String),
+    /// A transaction is dated too far from the current date, usually because of a wrong date_fmt.
+    #[error("The account '{account}' has a transaction dated {date}, which is more than {years} years from today - check its date_fmt")]
+    SkewedDate {
+        account: String,
+        date: String,
+        years: u32,
+    },
+    /// The rules file could not be parsed or does not make logical sense.
+    #[error("{0}")]
+    InvalidRule(String),
+    /// The rules file could not be read.
+    #[error("Cannot read the rules file {path:#?}: {source}")]
+    UnreadableRules {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A path could not be expanded, resolved or matched.
+    #[error("{0}")]
+    InvalidPath(String),
+    /// The timestamps could not be parsed.
+    #[error("The timestamps are not valid: {0}")]
+    InvalidTimestamps(String),
+    /// The normalized transactions or updated timestamps could not be produced.
+    #[error("Cannot produce the output: {0}")]
+    Output(String),
+    /// A file could not be read or written.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// A CSV file could not be read.
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+}
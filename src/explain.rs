@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::process::TransactionProcessor;
+use crate::timestamps::serialize_date;
+
+/// How the rules changed a single transaction.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Explanation {
+    /// The label of the account the transaction is from.
+    pub account: String,
+    /// The date of the transaction.
+    #[serde(serialize_with = "serialize_date")]
+    pub date: NaiveDate,
+    /// The payee as originally given in the raw data.
+    pub orig_payee: String,
+    /// The payee after the rules were applied.
+    pub payee: String,
+    /// The category after the rules were applied.
+    pub category: Option<String>,
+    /// The memo after the rules were applied.
+    pub memo: Option<String>,
+    /// The amount of the transaction.
+    pub amount: Decimal,
+    /// The payee rule that renamed the payee, if any.
+    pub payee_rule: Option<String>,
+    /// The category rule that set the category, if any.
+    pub category_rule: Option<String>,
+    /// The memo rule that set the memo, if any.
+    pub memo_rule: Option<String>,
+}
+
+impl Explanation {
+    /// Explain every processed transaction, ordered by account and then as read.
+    pub fn all(processors: &HashMap<String, TransactionProcessor>) -> Vec<Self> {
+        let mut labels: Vec<_> = processors.keys().collect();
+        labels.sort();
+        labels
+            .into_iter()
+            .flat_map(|label| processors[label].transactions())
+            .map(|transaction| Explanation {
+                account: transaction.label.to_owned(),
+                date: transaction.date,
+                orig_payee: transaction.orig_payee.to_owned(),
+                payee: transaction.payee.to_owned(),
+                category: transaction.category.to_owned(),
+                memo: transaction.memo.to_owned(),
+                amount: transaction.amount,
+                payee_rule: transaction.matched.payee.to_owned(),
+                category_rule: transaction.matched.category.to_owned(),
+                memo_rule: transaction.matched.memo.to_owned(),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        /// Describe a value along with the rule that set it.
+        fn describe(value: Option<&str>, rule: &Option<String>) -> String {
+            match (value, rule) {
+                (Some(value), Some(rule)) => format!("{value} (from the rule {rule})"),
+                (Some(value), None) => format!("{value} (unchanged)"),
+                (None, _) => "(none)".to_owned(),
+            }
+        }
+        writeln!(
+            f,
+            "{}: {} {} {}",
+            self.account, self.date, self.orig_payee, self.amount
+        )?;
+        writeln!(
+            f,
+            "    Payee: {}",
+            describe(Some(&self.payee), &self.payee_rule)
+        )?;
+        writeln!(
+            f,
+            "    Category: {}",
+            describe(self.category.as_deref(), &self.category_rule)
+        )?;
+        writeln!(
+            f,
+            "    Memo: {}",
+            describe(self.memo.as_deref(), &self.memo_rule)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use crate::process_csv_files;
+    use crate::rules::RuleFileData;
+
+    #[test]
+    fn test_explain() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rules = RuleFileData::new(format!(
+            indoc! { r#"
+            [payees]
+            Ace = {{Pattern = "ACE HARDWARE", Note = "Local store"}}
+
+            [categories]
+            Hardware = {{Payee = "Ace"}}
+
+            [memos]
+            Lunch = {{OrigPayee = "Wendy"}}
+
+            [[mappings.csv]]
+            label = "checking"
+            identify = ["Date", "Payee", "Amount"]
+
+            [paths]
+            storage = {:#?}
+            "# },
+            temp.path()
+        ))
+        .unwrap();
+        let csv = temp.path().join("checking.csv");
+        fs::write(
+            &csv,
+            indoc! { r#"
+            Date,Payee,Amount
+            2024-01-05,ACE HARDWARE,-15.43
+            2024-01-09,Wendy's,-12.54
+            "# },
+        )
+        .unwrap();
+
+        let processors = process_csv_files(&vec![csv], &rules).unwrap();
+        let result = serde_json::to_string_pretty(&Explanation::all(&processors)).unwrap();
+        let expected = indoc! {r#"
+        [
+          {
+            "account": "checking",
+            "date": "2024-01-05",
+            "orig_payee": "ACE HARDWARE",
+            "payee": "Ace",
+            "category": "Hardware",
+            "memo": null,
+            "amount": "-15.43",
+            "payee_rule": "Ace (Local store)",
+            "category_rule": "Hardware",
+            "memo_rule": null
+          },
+          {
+            "account": "checking",
+            "date": "2024-01-09",
+            "orig_payee": "Wendy's",
+            "payee": "Wendy's",
+            "category": null,
+            "memo": "Lunch",
+            "amount": "-12.54",
+            "payee_rule": null,
+            "category_rule": null,
+            "memo_rule": "Lunch"
+          }
+        ]"#};
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_explain_display() {
+        let explanation = Explanation {
+            account: "checking".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+            orig_payee: "ACE HARDWARE".to_string(),
+            payee: "Ace".to_string(),
+            category: Some("Hardware".to_string()),
+            memo: None,
+            amount: Decimal::new(-1543, 2),
+            payee_rule: Some("Ace".to_string()),
+            category_rule: None,
+            memo_rule: None,
+        };
+        assert_eq!(
+            explanation.to_string(),
+            indoc! {"
+            checking: 2024-01-05 ACE HARDWARE -15.43
+                Payee: Ace (from the rule Ace)
+                Category: Hardware (unchanged)
+                Memo: (none)
+            "}
+        );
+    }
+}
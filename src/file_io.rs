@@ -1,7 +1,112 @@
+use std::collections::HashSet;
 use std::convert::AsRef;
+use std::ffi::OsString;
 use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 
+use flate2::bufread::MultiGzDecoder;
+
+use crate::rules::{AuxillaryPaths, RuleFileData};
+use crate::timestamps::{timestamps_path, TimestampKeeper};
+use crate::TidymoneyError;
+
+/// The bytes at the start of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Open a file for reading, transparently decompressing it if it is gzipped.
+pub fn open_maybe_gzipped(path: impl AsRef<Path>) -> std::io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(fs::File::open(&path)?);
+    let has_gz_extension = path.as_ref().extension().is_some_and(|ext| ext == "gz");
+    if has_gz_extension || reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Whether or not the path looks like a (possibly gzipped) CSV file.
+fn is_csv_file(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = name.to_lowercase();
+    path.is_file() && (name.ends_with(".csv") || name.ends_with(".csv.gz"))
+}
+
+/// Expand any glob patterns in the given paths, then replace any directories
+/// with the CSV files they contain. Sub-directories are only searched if
+/// recursive is true.
+pub fn expand_input_paths(
+    paths: &[impl AsRef<Path>],
+    recursive: bool,
+) -> Result<Vec<PathBuf>, TidymoneyError> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        for path in expand_glob(path.as_ref())? {
+            if path.is_dir() {
+                expanded.extend(csv_files_in_dir(&path, recursive)?);
+            } else {
+                expanded.push(path);
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// Expand a path as a glob pattern, unless it exists as given or contains
+/// no pattern characters. A pattern must match at least one path.
+fn expand_glob(path: &Path) -> Result<Vec<PathBuf>, TidymoneyError> {
+    let pattern = path.to_string_lossy();
+    if path.exists() || !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let matches = glob::glob(&pattern)
+        .map_err(|e| {
+            TidymoneyError::InvalidPath(format!("The pattern {pattern:#?} is not valid: {e}"))
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(glob::GlobError::into_error)?;
+    if matches.is_empty() {
+        return Err(TidymoneyError::InvalidPath(format!(
+            "The pattern {pattern:#?} did not match any files"
+        )));
+    }
+    Ok(matches)
+}
+
+/// Collect the CSV files in a directory, sorted for reproducibility.
+fn csv_files_in_dir(dir: &Path, recursive: bool) -> std::io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    for entry in directory_entries(dir)? {
+        if entry.is_dir() {
+            if recursive {
+                found.extend(csv_files_in_dir(&entry, recursive)?);
+            }
+        } else if is_csv_file(&entry) {
+            found.push(entry);
+        }
+    }
+    Ok(found)
+}
+
+/// List the entries in a directory, sorted for reproducibility.
+pub fn directory_entries(dir: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Remove a file, or a directory and everything in it.
+pub fn remove_entry(path: impl AsRef<Path>) -> std::io::Result<()> {
+    if path.as_ref().is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
 /// Move a file from one location to another.
 fn move_file<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> std::io::Result<()> {
     match std::fs::rename(&from, &to) {
@@ -14,29 +119,117 @@ fn move_file<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> std::io::Result<
     }
 }
 
-/// Move transactions as downloaded into an "old" folder marked with a timestamp.
+/// Write a file such that readers never observe a partial file. The given
+/// function writes the contents to a temporary file in the same directory,
+/// which is then renamed into place.
+pub fn write_atomically<E: From<std::io::Error>>(
+    path: impl AsRef<Path>,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), E>,
+) -> Result<(), E> {
+    let path = path.as_ref();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{name}.tmp"));
+    let written = fs::File::create(&temp).map_err(E::from).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        Ok(out.flush()?)
+    });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+    Ok(move_file(&temp, path)?)
+}
+
+/// Move transactions as downloaded into the archive folder marked with a timestamp.
+/// Each mirror receives a copy before the files are moved into storage.
 pub fn store_raw_transactions(
-    storage: impl AsRef<Path>,
-    files: &Vec<impl AsRef<Path>>,
+    paths: &AuxillaryPaths,
+    files: &[impl AsRef<Path>],
     folder_base: impl AsRef<str>,
 ) -> std::io::Result<()> {
+    let names = archive_names(files);
+
+    // Copy the files into each mirror while they are still in place.
+    for mirror in &paths.mirrors {
+        let location = ensure_storage_path(mirror, &paths.archive_dir, folder_base.as_ref())?;
+        for (f, name) in files.iter().zip(&names) {
+            fs::copy(f, location.join(name))?;
+        }
+    }
+
     // Move the files from the old to the new locations.
-    let location = ensure_storage_path(storage, folder_base, false)?;
-    for f in files {
-        let name = f.as_ref().file_name().unwrap();
+    let location = ensure_storage_path(&paths.storage, &paths.archive_dir, folder_base)?;
+    for (f, name) in files.iter().zip(&names) {
         move_file(f, location.join(name))?;
     }
     Ok(())
 }
 
+/// Choose the name each file is archived under. Files with the same name
+/// (such as from different directories) are numbered so none is overwritten.
+fn archive_names(files: &[impl AsRef<Path>]) -> Vec<OsString> {
+    let mut taken = HashSet::new();
+    let mut names = Vec::new();
+    for f in files {
+        let name = f.as_ref().file_name().unwrap().to_owned();
+        let mut candidate = name.clone();
+        let mut number = 1;
+        while taken.contains(&candidate) {
+            number += 1;
+            candidate = numbered_name(&name.to_string_lossy(), number).into();
+        }
+        taken.insert(candidate.clone());
+        names.push(candidate);
+    }
+    names
+}
+
+/// Add a number to a file name before its extensions, e.g. "a.csv.gz" becomes "a-2.csv.gz".
+fn numbered_name(name: &str, number: usize) -> String {
+    // A leading dot marks a hidden file rather than an extension.
+    match name.char_indices().skip(1).find(|(_, c)| *c == '.') {
+        Some((index, _)) => format!("{}-{number}{}", &name[..index], &name[index..]),
+        None => format!("{name}-{number}"),
+    }
+}
+
+/// Read and validate the rules file at the given path, along with the
+/// timestamps it refers to. Also returns the path of the timestamps file so
+/// that it may be updated after a run.
+pub fn load(
+    config_path: impl AsRef<Path>,
+) -> Result<(RuleFileData, TimestampKeeper, PathBuf), TidymoneyError> {
+    let config_path = config_path.as_ref();
+    let rule_data =
+        fs::read_to_string(config_path).map_err(|source| TidymoneyError::UnreadableRules {
+            path: config_path.to_path_buf(),
+            source,
+        })?;
+    load_from_str(&rule_data, config_path)
+}
+
+/// Like load, but for rules file contents that have already been read from
+/// the given path (which need not exist, such as when reading standard input).
+pub fn load_from_str(
+    rule_data: &str,
+    config_path: impl AsRef<Path>,
+) -> Result<(RuleFileData, TimestampKeeper, PathBuf), TidymoneyError> {
+    let config_path = config_path.as_ref();
+    let rule_dir = config_path.parent().unwrap_or(Path::new(""));
+    let rules = RuleFileData::new_in_dir(rule_data, rule_dir)?;
+    let stamps_file = timestamps_path(config_path, rules.paths.timestamps.as_deref())?;
+    let stamps = TimestampKeeper::from_file(&stamps_file)?;
+    Ok((rules, stamps, stamps_file))
+}
+
 /// Construct the storage location, ensure it exists, and return it.
 pub fn ensure_storage_path(
     storage: impl AsRef<Path>,
+    folder: impl AsRef<str>,
     base: impl AsRef<str>,
-    new: bool,
 ) -> std::io::Result<PathBuf> {
-    let mid = if new { "new" } else { "old" };
-    let location = storage.as_ref().join(mid).join(base.as_ref());
+    let location = storage.as_ref().join(folder.as_ref()).join(base.as_ref());
     if !location.exists() {
         fs::create_dir_all(&location)?;
     }
@@ -47,10 +240,105 @@ pub fn ensure_storage_path(
 mod test {
     use super::*;
 
+    use std::io::Write;
     use std::path::PathBuf;
 
+    use indoc::indoc;
     use rstest::rstest;
-    use tempdir;
+
+    #[rstest]
+    #[case(false, vec!["a.csv", "b.CSV", "c.csv.gz", "given.txt"])]
+    #[case(true, vec!["a.csv", "b.CSV", "c.csv.gz", "nested/d.csv", "given.txt"])]
+    fn test_expand_input_paths(#[case] recursive: bool, #[case] expected: Vec<&str>) {
+        // Create a directory with a mix of file types and a nested directory.
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let dir = temp.path().join("statements");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for name in [
+            "a.csv",
+            "b.CSV",
+            "c.csv.gz",
+            "notes.txt",
+            "report.pdf",
+            "nested/d.csv",
+            "nested/e.xlsx",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        // Explicitly given files are kept even if they are not CSV files.
+        fs::write(temp.path().join("given.txt"), "").unwrap();
+        let given = vec![dir.clone(), temp.path().join("given.txt")];
+
+        let result = expand_input_paths(&given, recursive).unwrap();
+        let expected: Vec<PathBuf> = expected
+            .into_iter()
+            .map(|name| match name {
+                "given.txt" => temp.path().join(name),
+                _ => dir.join(name),
+            })
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_expand_input_paths_with_glob() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        for name in ["2023-12.csv", "2024-01.csv", "2024-02.csv", "2024-03.txt"] {
+            fs::write(temp.path().join(name), "").unwrap();
+        }
+
+        // Globs may be mixed with literal paths.
+        let given = vec![
+            temp.path().join("2024-*.csv"),
+            temp.path().join("2023-12.csv"),
+        ];
+        let result = expand_input_paths(&given, false).unwrap();
+        let expected: Vec<PathBuf> = ["2024-01.csv", "2024-02.csv", "2023-12.csv"]
+            .into_iter()
+            .map(|name| temp.path().join(name))
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_expand_input_paths_with_unmatched_glob() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let pattern = temp.path().join("2024-*.csv");
+        let result = expand_input_paths(&[&pattern], false).unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            format!("The pattern {:#?} did not match any files", pattern)
+        );
+        assert!(matches!(result, TidymoneyError::InvalidPath(_)));
+    }
+
+    #[rstest]
+    #[case("plain.csv", false)]
+    #[case("plain.csv.gz", true)]
+    #[case("disguised.csv", true)]
+    fn test_open_maybe_gzipped(#[case] name: &str, #[case] compress: bool) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let msg = "Date,Payee,Amount\n2024-01-05,ACE,-15.43\n";
+        let path = temp.path().join(name);
+        if compress {
+            let mut encoder = flate2::write::GzEncoder::new(
+                fs::File::create(&path).unwrap(),
+                flate2::Compression::default(),
+            );
+            encoder.write_all(msg.as_bytes()).unwrap();
+            encoder.finish().unwrap();
+        } else {
+            fs::write(&path, msg).unwrap();
+        }
+
+        let mut result = String::new();
+        open_maybe_gzipped(&path)
+            .unwrap()
+            .read_to_string(&mut result)
+            .unwrap();
+        assert_eq!(result, msg);
+    }
 
     #[test]
     fn test_move_file() {
@@ -71,11 +359,44 @@ mod test {
     }
 
     #[test]
-    fn test_store_raw_transactions() {
+    fn test_write_atomically() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("account.csv");
+        let msg = "Date,Payee\n".repeat(1000);
+
+        // Writing twice replaces the contents completely.
+        write_atomically(&path, |out| out.write_all(b"old contents")).unwrap();
+        write_atomically(&path, |out| out.write_all(msg.as_bytes())).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), msg);
+
+        // A failed write leaves the previous contents in place.
+        let result = write_atomically(&path, |out| {
+            out.write_all(b"partial")?;
+            Err(std::io::Error::other("failed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), msg);
+
+        // No temporary files remain.
+        let names: Vec<_> = fs::read_dir(temp.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["account.csv"]);
+    }
+
+    #[rstest]
+    #[case(None, "old")]
+    #[case(Some("downloaded"), "downloaded")]
+    fn test_store_raw_transactions(#[case] archive_dir: Option<&str>, #[case] expected: &str) {
         // Create a temp dir in which to perform the tests
         let temp = tempdir::TempDir::new("test").unwrap();
         let downloads = temp.path().join("downloads");
         fs::create_dir(&downloads).unwrap();
+        let mut paths = AuxillaryPaths::new(temp.path());
+        if let Some(archive_dir) = archive_dir {
+            paths.archive_dir = archive_dir.to_string();
+        }
 
         // Create files in the downloads directory and store in a vector.
         let mut files: Vec<PathBuf> = Vec::new();
@@ -86,7 +407,7 @@ mod test {
         }
 
         // Move the files.
-        store_raw_transactions(temp.path(), &files, "base1").unwrap();
+        store_raw_transactions(&paths, &files, "base1").unwrap();
 
         // Check that the original files do not exist.
         for path in files {
@@ -94,29 +415,165 @@ mod test {
         }
 
         // Check that the files have been moved.
-        let base1 = temp.path().join("old").join("base1");
+        let base1 = temp.path().join(expected).join("base1");
         for i in 1..5 {
             let path = base1.join(format!("file{i}.csv"));
             assert!(fs::exists(path).unwrap());
         }
     }
 
+    #[test]
+    fn test_store_raw_transactions_with_mirror() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = temp.path().join("storage");
+        let mirror = temp.path().join("mirror");
+        fs::create_dir(&storage).unwrap();
+        fs::create_dir(&mirror).unwrap();
+        let mut paths = AuxillaryPaths::new(&storage);
+        paths.mirrors = vec![mirror.clone()];
+        let file = temp.path().join("file.csv");
+        fs::write(&file, "text").unwrap();
+
+        store_raw_transactions(&paths, &[&file], "base1").unwrap();
+
+        // The original is moved into storage and copied into the mirror.
+        assert!(!file.exists());
+        assert!(storage.join("old/base1/file.csv").is_file());
+        assert!(mirror.join("old/base1/file.csv").is_file());
+    }
+
+    #[test]
+    fn test_store_raw_transactions_with_same_names() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = temp.path().join("storage");
+        let mirror = temp.path().join("mirror");
+        fs::create_dir(&storage).unwrap();
+        fs::create_dir(&mirror).unwrap();
+        let mut paths = AuxillaryPaths::new(&storage);
+        paths.mirrors = vec![mirror.clone()];
+        let mut files = Vec::new();
+        for dir in ["a", "b", "c"] {
+            let path = temp.path().join("downloads").join(dir).join("export.csv");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, dir).unwrap();
+            files.push(path);
+        }
+
+        store_raw_transactions(&paths, &files, "base1").unwrap();
+
+        // Each file is kept, numbered in the order given.
+        for root in [&storage, &mirror] {
+            let base1 = root.join("old/base1");
+            assert_eq!(
+                directory_entries(&base1).unwrap(),
+                vec![
+                    base1.join("export-2.csv"),
+                    base1.join("export-3.csv"),
+                    base1.join("export.csv"),
+                ]
+            );
+            assert_eq!(fs::read_to_string(base1.join("export.csv")).unwrap(), "a");
+            assert_eq!(fs::read_to_string(base1.join("export-2.csv")).unwrap(), "b");
+            assert_eq!(fs::read_to_string(base1.join("export-3.csv")).unwrap(), "c");
+        }
+    }
+
+    #[rstest]
+    #[case("export.csv", "export-2.csv")]
+    #[case("export.csv.gz", "export-2.csv.gz")]
+    #[case("export", "export-2")]
+    #[case(".export.csv", ".export-2.csv")]
+    fn test_numbered_name(#[case] given: &str, #[case] expected: &str) {
+        assert_eq!(numbered_name(given, 2), expected);
+    }
+
+    #[rstest]
+    #[case(None, None)]
+    #[case(
+        Some(r#"[{"account": "checking", "date": "2024-03-01"}]"#),
+        Some("2024-03-01")
+    )]
+    fn test_load(#[case] stamps: Option<&str>, #[case] expected: Option<&str>) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let config = temp.path().join("rules.toml");
+        let rules = format!(
+            indoc! { r#"
+            [payees]
+            Apple = "APPLE"
+
+            [[mappings.csv]]
+            label = "checking"
+            identify = ["Date", "Payee", "Amount"]
+
+            [paths]
+            storage = {:#?}
+            "# },
+            temp.path()
+        );
+        fs::write(&config, rules).unwrap();
+        if let Some(stamps) = stamps {
+            fs::write(temp.path().join("timestamps.json"), stamps).unwrap();
+        }
+
+        let (rules, stamps, stamps_file) = load(&config).unwrap();
+        assert_eq!(rules.paths.storage, temp.path());
+        assert_eq!(stamps_file, temp.path().join("timestamps.json"));
+        let expected = expected.unwrap_or("2000-01-01");
+        assert_eq!(stamps.get_date("checking").to_string(), expected);
+    }
+
+    #[test]
+    fn test_load_from_str() {
+        // The rules file itself need not exist.
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rules = format!(
+            indoc! { r#"
+            [payees]
+
+            [[mappings.csv]]
+            label = "checking"
+            identify = ["Date", "Payee", "Amount"]
+
+            [paths]
+            storage = {:#?}
+            "# },
+            temp.path()
+        );
+        let (_, stamps, stamps_file) =
+            load_from_str(&rules, temp.path().join("rules.toml")).unwrap();
+        assert_eq!(stamps_file, temp.path().join("timestamps.json"));
+        assert_eq!(stamps.get_date("checking").to_string(), "2000-01-01");
+    }
+
+    #[test]
+    fn test_load_missing_rules() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("rules.toml");
+        let result = load(&path).unwrap_err();
+        assert!(result.to_string().starts_with("Cannot read the rules file"));
+        assert!(matches!(
+            result,
+            TidymoneyError::UnreadableRules { path: given, .. } if given == path
+        ));
+    }
+
     #[rstest]
-    #[case(false, "old/the-base")]
-    #[case(true, "new/the-base")]
-    fn test_ensure_storage_path(#[case] old_new: bool, #[case] expected: impl AsRef<Path>) {
+    #[case("old", "old/the-base")]
+    #[case("new", "new/the-base")]
+    #[case("archive", "archive/the-base")]
+    fn test_ensure_storage_path(#[case] folder: &str, #[case] expected: impl AsRef<Path>) {
         let temp = tempdir::TempDir::new("test").unwrap();
         let expected = temp.path().join(expected.as_ref());
         assert!(!expected.is_dir()); // expected does not yet exist
 
-        let result = ensure_storage_path(temp.path(), "the-base", old_new).unwrap();
+        let result = ensure_storage_path(temp.path(), folder, "the-base").unwrap();
         assert_eq!(result, expected);
         assert!(expected.is_dir()); // expected now exists
 
         // Can be repeated (OK that it already exists).
         assert_eq!(
             expected,
-            ensure_storage_path(temp.path(), "the-base", old_new).unwrap()
+            ensure_storage_path(temp.path(), folder, "the-base").unwrap()
         );
     }
 }
@@ -1,22 +1,38 @@
+/// The canonical names of the columns read from each row of transaction data.
+pub mod columns;
+mod error;
+mod explain;
 mod file_io;
 mod process;
 mod rules;
+mod stats;
 mod timestamps;
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 
-use anyhow::{anyhow, Result};
 use chrono::NaiveDate;
 use rust_decimal::Decimal;
 use serde::Serialize;
 
+use crate::rules::MappingRulesCsv;
 use crate::timestamps::serialize_date;
 
-pub use crate::file_io::store_raw_transactions;
+pub use crate::error::TidymoneyError;
+pub use crate::explain::Explanation;
+pub use crate::file_io::{expand_input_paths, load, load_from_str, store_raw_transactions};
 pub use crate::process::{
-    account_for_dates_in_transactions, process_csv_files, write_transactions_to_file,
+    account_for_dates_in_transactions, count_occurrences, diff_transaction_files,
+    find_skewed_dates, identify_csv_file, preview_csv_files, process_csv_files,
+    process_csv_files_timed, process_in_memory, select_csv_files, split_empty_csv_files,
+    split_unmatched_csv_files, write_transactions_to_file, AccountSelection, DateOverrides,
+    InMemoryOutput, OutputDiff, SkewedDate, SkipCounts, StageTimes,
 };
-pub use crate::rules::{normalize_path, RuleFileData};
+pub use crate::rules::{
+    canonicalize_path, normalize_path, Aggregate, AuxillaryPaths, CheckAction, MatchedRules,
+    Migration, RuleFileData,
+};
+pub use crate::stats::{AccountStats, Stats};
 pub use crate::timestamps::{timestamps_path, TimestampKeeper, DATE_FORMAT};
 
 /// Container for bank data to be serialized into the normalized CSV.
@@ -30,66 +46,188 @@ pub struct NormalizedBankData {
     pub memo: Option<String>,
     pub amount: Decimal,
     #[serde(rename = "Check#")]
-    pub check: Option<u32>,
+    pub check: Option<String>,
     #[serde(skip_serializing)]
     pub orig_payee: String,
+    #[serde(skip_serializing)]
+    pub address: Option<String>,
+    #[serde(skip_serializing)]
+    pub posted_date: Option<NaiveDate>,
+    #[serde(skip_serializing)]
+    pub balance: Option<Decimal>,
+    #[serde(skip_serializing)]
+    pub running_balance: Option<Decimal>,
+    #[serde(skip_serializing)]
+    pub source: Option<PathBuf>,
+    #[serde(skip_serializing)]
+    pub label: String,
+    #[serde(skip_serializing)]
+    pub matched: MatchedRules,
+    #[serde(skip_serializing)]
+    pub blank_amount: bool,
 }
 
 impl NormalizedBankData {
     #[cfg(test)]
     fn new(mapping: HashMap<String, String>) -> Self {
-        Self::from_raw_data(mapping, false, DATE_FORMAT, "testing").unwrap()
+        Self::from_raw_data(mapping, &test::mapping("")).unwrap()
     }
 
-    /// Instantiate this struct from raw data from file.
+    /// Instantiate this struct from raw data from file, for the account with the given rules.
     pub fn from_raw_data(
         mapping: HashMap<String, String>,
-        negate: bool,
-        date_fmt: impl AsRef<str>,
-        label: impl AsRef<str>,
-    ) -> Result<Self> {
+        rules: &MappingRulesCsv,
+    ) -> Result<Self, TidymoneyError> {
+        // A row may be negated only if a column has a given value.
+        let negate = rules.negate
+            ^ rules
+                .negate_when()
+                .is_some_and(|(column, value)| mapping.get(column).is_some_and(|x| x == value));
+        let date_fmt = &rules.date_fmt;
+
         // Get required columns.
-        let label_str = label.as_ref();
+        let label_str = rules.label.as_str();
+        let missing = |column: &str| TidymoneyError::MissingColumn {
+            account: label_str.to_owned(),
+            column: column.to_owned(),
+        };
         let payee_str = mapping
-            .get("Payee")
-            .ok_or_else(|| anyhow!("The account '{label_str}' is missing the Payee column"))?;
+            .get(columns::PAYEE)
+            .ok_or_else(|| missing(columns::PAYEE))?;
         let date_str = mapping
-            .get("Date")
-            .ok_or_else(|| anyhow!("The account '{label_str}' is missing the Date column"))?;
-        let amount_str = mapping
-            .get("Amount")
-            .ok_or_else(|| anyhow!("The account '{label_str}' is missing the Amount column"))?;
+            .get(columns::DATE)
+            .ok_or_else(|| missing(columns::DATE))?;
+        let amount = match mapping.get(columns::AMOUNT) {
+            Some(amount_str) => interpret_dollar_amount(amount_str, negate),
+            None if mapping.contains_key(columns::DEBIT)
+                || mapping.contains_key(columns::CREDIT) =>
+            {
+                interpret_debit_and_credit(
+                    mapping.get(columns::DEBIT),
+                    mapping.get(columns::CREDIT),
+                    negate,
+                    label_str,
+                )?
+            }
+            None => return Err(missing(columns::AMOUNT)),
+        };
+        let is_blank = |column: &str| mapping.get(column).is_none_or(|x| x.trim().is_empty());
+        let blank_amount = match mapping.get(columns::AMOUNT) {
+            Some(amount_str) => amount_str.trim().is_empty(),
+            None => is_blank(columns::DEBIT) && is_blank(columns::CREDIT),
+        };
+        let adjust = |amount: Decimal| match rules.amount_scale {
+            _ if rules.amount_in_cents => cents_to_dollars(amount),
+            Some(scale) => scale_amount(amount, scale),
+            None => amount,
+        };
+        let amount = adjust(amount);
 
         // Calculate the values of all the fields and return.
-        return Ok(NormalizedBankData {
-            date: NaiveDate::parse_from_str(date_str, date_fmt.as_ref()).or(Err(anyhow!(
-                "Cannot parse the date {:#?} with the format string {:#?}",
-                date_str,
-                date_fmt.as_ref()
-            )))?,
+        let parse_date = |date: &str| {
+            NaiveDate::parse_from_str(date, date_fmt).map_err(|_| TidymoneyError::UnparseableDate {
+                date: date.to_owned(),
+                format: date_fmt.to_owned(),
+            })
+        };
+        Ok(NormalizedBankData {
+            date: parse_date(date_str)?,
             payee: payee_str.to_owned(),
-            category: mapping.get("Category").map(|x| x.to_owned()),
-            memo: mapping.get("Memo").map(|x| x.to_owned()),
-            amount: interpret_dollar_amount(amount_str, negate),
-            check: mapping.get("Check#").and_then(|x| x.parse().ok()),
+            category: mapping.get(columns::CATEGORY).map(|x| x.to_owned()),
+            memo: mapping.get(columns::MEMO).map(|x| x.to_owned()),
+            amount,
+            check: mapping
+                .get(columns::CHECK)
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .map(|x| x.to_owned()),
             orig_payee: payee_str.to_owned(),
-        });
+            address: mapping.get(columns::ADDRESS).map(|x| x.to_owned()),
+            // The posted date is only read if it is used, so that an account
+            // with an unused PostedDate column need not match the date_fmt.
+            posted_date: mapping
+                .get(columns::POSTED_DATE)
+                .map(|x| x.trim())
+                .filter(|x| rules.filter_on_posted_date && !x.is_empty())
+                .map(parse_date)
+                .transpose()?,
+            balance: mapping
+                .get(columns::BALANCE)
+                .map(|x| x.trim())
+                .filter(|x| !x.is_empty())
+                .and_then(parse_amount)
+                .map(|x| if rules.negate { -x } else { x })
+                .map(adjust),
+            running_balance: None,
+            source: None,
+            label: label_str.to_owned(),
+            matched: MatchedRules::default(),
+            blank_amount,
+        })
     }
 
-    /// Determine if this transaction needs to be skipped.
-    pub fn skipme(&self, start_date: &NaiveDate, end_date: &NaiveDate) -> bool {
-        self.amount == Decimal::ZERO || self.date < *start_date || self.date > *end_date
+    /// Determine if this transaction needs to be skipped, and if so why.
+    /// The dates are compared to the posted date (if there is one) when requested.
+    pub fn skip_reason(
+        &self,
+        start_date: &NaiveDate,
+        end_date: &NaiveDate,
+        use_posted_date: bool,
+    ) -> Option<SkipReason> {
+        let date = match self.posted_date {
+            Some(posted_date) if use_posted_date => posted_date,
+            _ => self.date,
+        };
+        if self.amount == Decimal::ZERO {
+            Some(SkipReason::ZeroAmount)
+        } else if date < *start_date {
+            Some(SkipReason::BeforeStart)
+        } else if date > *end_date {
+            Some(SkipReason::AfterEnd)
+        } else {
+            None
+        }
     }
 }
 
+/// The reasons a transaction may be skipped rather than written.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SkipReason {
+    /// The transaction is for no money.
+    ZeroAmount,
+    /// The transaction has no amount at all.
+    BlankAmount,
+    /// The transaction matches an ignore rule.
+    Ignored,
+    /// The transaction is before the start date (usually because it was already seen).
+    BeforeStart,
+    /// The transaction is after the end date.
+    AfterEnd,
+}
+
+/// Convert a dollar amount in string form into a Decimal object, if possible.
+pub(crate) fn parse_amount(amount: &str) -> Option<Decimal> {
+    // Some banks explicitly mark positive values with a leading plus sign.
+    let amount = amount.strip_prefix('+').unwrap_or(amount);
+
+    // Others mark negative values with a trailing minus sign (e.g. 12.34-).
+    let (amount, trailing_minus) = match amount.strip_suffix('-') {
+        Some(stripped) if !stripped.starts_with('-') => (stripped, true),
+        _ => (amount, false),
+    };
+
+    Decimal::from_str_exact(amount)
+        .ok()
+        .map(|amt| if trailing_minus { -amt } else { amt })
+}
+
 /// Convert a dollar amount in string form into a Decimal object.
 ///
 /// Some banks express this in negated values, and if that is the case
 /// the negate option can be used to re-interpret as positive.
 fn interpret_dollar_amount(amount: impl AsRef<str>, negate: bool) -> Decimal {
-    // Convert the given value to a decimal,
-    // defaulting to zero if it cannot be converted.
-    let amt = Decimal::from_str_exact(amount.as_ref()).unwrap_or_default();
+    // Default to zero if the value cannot be converted.
+    let amt = parse_amount(amount.as_ref()).unwrap_or_default();
 
     // Return a negated version of the value if necessary.
     if negate {
@@ -99,6 +237,60 @@ fn interpret_dollar_amount(amount: impl AsRef<str>, negate: bool) -> Decimal {
     }
 }
 
+/// Convert an amount given in cents into dollars.
+fn cents_to_dollars(amount: Decimal) -> Decimal {
+    // Shifting the scale keeps the trailing zeros (e.g. 100 becomes 1.00).
+    let mut dollars = amount;
+    match dollars.set_scale(amount.scale() + 2) {
+        Ok(()) => dollars,
+        Err(_) => amount / Decimal::ONE_HUNDRED,
+    }
+}
+
+/// Multiply an amount by the given scale, keeping at least cents precision.
+fn scale_amount(amount: Decimal, scale: Decimal) -> Decimal {
+    let mut scaled = amount * scale;
+    if scaled.scale() < 2 {
+        scaled.rescale(2);
+    }
+    scaled
+}
+
+/// Combine separate debit and credit dollar amounts into a single Decimal object.
+///
+/// Debits are always negative and credits are always positive (before negating).
+/// Only one of the two may be given - if neither is given the amount is zero.
+fn interpret_debit_and_credit(
+    debit: Option<impl AsRef<str>>,
+    credit: Option<impl AsRef<str>>,
+    negate: bool,
+    label: &str,
+) -> Result<Decimal, TidymoneyError> {
+    // Treat blank values as though they were not given at all.
+    let debit = debit.filter(|x| !x.as_ref().trim().is_empty());
+    let credit = credit.filter(|x| !x.as_ref().trim().is_empty());
+
+    let amt = match (debit, credit) {
+        (Some(d), Some(c)) => {
+            return Err(TidymoneyError::ConflictingAmounts {
+                account: label.to_owned(),
+                debit: d.as_ref().to_owned(),
+                credit: c.as_ref().to_owned(),
+            })
+        }
+        (Some(d), None) => -interpret_dollar_amount(d, false).abs(),
+        (None, Some(c)) => interpret_dollar_amount(c, false).abs(),
+        (None, None) => Decimal::ZERO,
+    };
+
+    // Return a negated version of the value if necessary.
+    if negate {
+        Ok(-amt)
+    } else {
+        Ok(amt)
+    }
+}
+
 /// Test helper function for converting vectors to hashmaps.
 pub fn as_hashmap(data: Vec<(impl Into<String>, impl Into<String>)>) -> HashMap<String, String> {
     data.into_iter()
@@ -113,11 +305,23 @@ mod test {
     use rstest::rstest;
     use rust_decimal_macros::dec;
 
+    /// The rules for an account labeled "testing", with the given extra keys.
+    pub(crate) fn mapping(extra: &str) -> MappingRulesCsv {
+        toml::from_str(&format!("label = \"testing\"\n{extra}")).unwrap()
+    }
+
     #[rstest]
     #[case("4.56", false, dec!(4.56))]
     #[case("-4.56", false, dec!(-4.56))]
     #[case("4.56", true, dec!(-4.56))]
     #[case("-4.56", true, dec!(4.56))]
+    #[case("+1200.00", false, dec!(1200.00))]
+    #[case("+1200.00", true, dec!(-1200.00))]
+    #[case("+0.00", false, dec!(0.00))]
+    #[case("12.34-", false, dec!(-12.34))]
+    #[case("12.34-", true, dec!(12.34))]
+    #[case("-12.34-", false, dec!(0.00))]
+    #[case("-", false, dec!(0.00))]
     #[case("gandalf", false, dec!(0.00))]
     #[case("gandalf", true, dec!(0.00))]
     fn test_interpret_dollar_amount(
@@ -129,6 +333,274 @@ mod test {
         assert_eq!(result, expected);
     }
 
+    #[rstest]
+    #[case("1234.56", Some(dec!(1234.56)))]
+    #[case("+1234.56", Some(dec!(1234.56)))]
+    #[case("1234.56-", Some(dec!(-1234.56)))]
+    #[case("-1234.56", Some(dec!(-1234.56)))]
+    #[case("N/A", None)]
+    fn test_from_raw_data_balance(#[case] balance: &str, #[case] expected: Option<Decimal>) {
+        let result = NormalizedBankData::new(as_hashmap(vec![
+            ("Date", "2024-01-01"),
+            ("Payee", "MOD"),
+            ("Amount", "-1.00"),
+            ("Balance", balance),
+        ]));
+        assert_eq!(result.balance, expected);
+    }
+
+    #[rstest]
+    #[case(Some("15.99"), None, false, dec!(-15.99))]
+    #[case(Some("-15.99"), None, false, dec!(-15.99))]
+    #[case(None, Some("15.99"), false, dec!(15.99))]
+    #[case(Some(""), Some("15.99"), false, dec!(15.99))]
+    #[case(Some("15.99"), Some(" "), false, dec!(-15.99))]
+    #[case(Some("15.99"), None, true, dec!(15.99))]
+    #[case(None, Some("15.99"), true, dec!(-15.99))]
+    #[case(None, None, false, dec!(0.00))]
+    fn test_interpret_debit_and_credit(
+        #[case] debit: Option<&str>,
+        #[case] credit: Option<&str>,
+        #[case] negate: bool,
+        #[case] expected: Decimal,
+    ) {
+        let result = interpret_debit_and_credit(debit, credit, negate, "testing").unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_interpret_debit_and_credit_both_given() {
+        let result = interpret_debit_and_credit(Some("1.00"), Some("2.00"), false, "testing");
+        assert!(matches!(
+            result.unwrap_err(),
+            TidymoneyError::ConflictingAmounts { account, debit, credit }
+                if account == "testing" && debit == "1.00" && credit == "2.00"
+        ));
+    }
+
+    #[test]
+    fn test_from_raw_data_with_debit_and_credit() {
+        let debit = NormalizedBankData::new(as_hashmap(vec![
+            ("Date", "2024-01-01"),
+            ("Payee", "MOD"),
+            ("Debit", "15.32"),
+            ("Credit", ""),
+        ]));
+        assert_eq!(debit.amount, dec!(-15.32));
+        let credit = NormalizedBankData::new(as_hashmap(vec![
+            ("Date", "2024-01-01"),
+            ("Payee", "MOD"),
+            ("Debit", ""),
+            ("Credit", "15.32"),
+        ]));
+        assert_eq!(credit.amount, dec!(15.32));
+        let both = NormalizedBankData::from_raw_data(
+            as_hashmap(vec![
+                ("Date", "2024-01-01"),
+                ("Payee", "MOD"),
+                ("Debit", "1.00"),
+                ("Credit", "2.00"),
+            ]),
+            &mapping(""),
+        );
+        assert!(both
+            .unwrap_err()
+            .to_string()
+            .starts_with("The account 'testing' has both a Debit"));
+    }
+
+    #[test]
+    fn test_from_raw_data_missing_amount() {
+        let result = NormalizedBankData::from_raw_data(
+            as_hashmap(vec![("Date", "2024-01-01"), ("Payee", "MOD")]),
+            &mapping(""),
+        );
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The account 'testing' is missing the Amount column"
+        );
+        assert!(matches!(
+            err,
+            TidymoneyError::MissingColumn { account, column }
+                if account == "testing" && column == "Amount"
+        ));
+    }
+
+    #[test]
+    fn test_output_column_constants_match_serialization() {
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            (columns::PAYEE, "ACE"),
+            (columns::DATE, "2024-01-05"),
+            (columns::AMOUNT, "-15.43"),
+        ]));
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(transaction).unwrap();
+        let result = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert!(result.starts_with(&(columns::OUTPUT.join(",") + "\n")));
+    }
+
+    #[test]
+    fn test_from_raw_data_reads_column_constants() {
+        // Every required and optional column is read.
+        let result = NormalizedBankData::new(as_hashmap(vec![
+            (columns::DATE, "2024-01-01"),
+            (columns::PAYEE, "MOD"),
+            (columns::AMOUNT, "-4.56"),
+            (columns::CATEGORY, "Food"),
+            (columns::MEMO, "Pizza"),
+            (columns::CHECK, "1234"),
+            (columns::ADDRESS, "Vancouver WA"),
+        ]));
+        assert_eq!(result.date, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        assert_eq!(result.payee, "MOD");
+        assert_eq!(result.amount, dec!(-4.56));
+        assert_eq!(result.category, Some("Food".to_string()));
+        assert_eq!(result.memo, Some("Pizza".to_string()));
+        assert_eq!(result.check, Some("1234".to_string()));
+        assert_eq!(result.address, Some("Vancouver WA".to_string()));
+        let credit = NormalizedBankData::new(as_hashmap(vec![
+            (columns::DATE, "2024-01-01"),
+            (columns::PAYEE, "MOD"),
+            (columns::CREDIT, "4.56"),
+        ]));
+        assert_eq!(credit.amount, dec!(4.56));
+
+        // Removing any required column is an error naming that column.
+        for required in columns::REQUIRED {
+            let row = vec![
+                (columns::DATE, "2024-01-01"),
+                (columns::PAYEE, "MOD"),
+                (columns::AMOUNT, "-4.56"),
+            ];
+            let row = row.into_iter().filter(|(k, _)| *k != required).collect();
+            let result = NormalizedBankData::from_raw_data(as_hashmap(row), &mapping(""));
+            assert!(matches!(
+                result.unwrap_err(),
+                TidymoneyError::MissingColumn { column, .. } if column == required
+            ));
+        }
+    }
+
+    #[rstest]
+    #[case("1599", false, dec!(15.99))]
+    #[case("-1599", false, dec!(-15.99))]
+    #[case("-1599", true, dec!(15.99))]
+    #[case("100", false, dec!(1.00))]
+    fn test_from_raw_data_amount_in_cents(
+        #[case] amount: &str,
+        #[case] negate: bool,
+        #[case] expected: Decimal,
+    ) {
+        let result = NormalizedBankData::from_raw_data(
+            as_hashmap(vec![
+                ("Date", "2024-01-01"),
+                ("Payee", "MOD"),
+                ("Amount", amount),
+            ]),
+            &mapping(&format!(
+                "debit_is_positive = {negate}\namount_in_cents = true"
+            )),
+        )
+        .unwrap();
+        assert_eq!(result.amount, expected);
+        assert_eq!(result.amount.to_string(), expected.to_string());
+    }
+
+    #[rstest]
+    #[case("5.2", dec!(1000), false, "5200.00")]
+    #[case("5.2", dec!(1000), true, "-5200.00")]
+    #[case("1.23456", dec!(1000), false, "1234.56000")]
+    #[case("1200", dec!(0.001), false, "1.200")]
+    fn test_from_raw_data_amount_scale(
+        #[case] amount: &str,
+        #[case] scale: Decimal,
+        #[case] negate: bool,
+        #[case] expected: &str,
+    ) {
+        let result = NormalizedBankData::from_raw_data(
+            as_hashmap(vec![
+                ("Date", "2024-01-01"),
+                ("Payee", "MOD"),
+                ("Amount", amount),
+            ]),
+            &mapping(&format!(
+                "debit_is_positive = {negate}\namount_scale = {scale}"
+            )),
+        )
+        .unwrap();
+        assert_eq!(result.amount.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case("Debit", false, dec!(-12.50))]
+    #[case("Credit", false, dec!(12.50))]
+    #[case("Debit", true, dec!(12.50))]
+    #[case("Credit", true, dec!(-12.50))]
+    fn test_from_raw_data_negate_when(
+        #[case] kind: &str,
+        #[case] negate: bool,
+        #[case] expected: Decimal,
+    ) {
+        let result = NormalizedBankData::from_raw_data(
+            as_hashmap(vec![
+                ("Date", "2024-01-01"),
+                ("Payee", "MOD"),
+                ("Amount", "12.50"),
+                ("Type", kind),
+            ]),
+            &mapping(&format!("debit_is_positive = {negate}\nnegate_when = {{column = \"Type\", value = \"Debit\"}}")),
+        )
+        .unwrap();
+        assert_eq!(result.amount, expected);
+    }
+
+    #[rstest]
+    #[case(vec![("Amount", "")], true)]
+    #[case(vec![("Amount", "  ")], true)]
+    #[case(vec![("Amount", "0.00")], false)]
+    #[case(vec![("Amount", "-1.00")], false)]
+    #[case(vec![("Debit", ""), ("Credit", "")], true)]
+    #[case(vec![("Debit", ""), ("Credit", "0.00")], false)]
+    fn test_from_raw_data_blank_amount(#[case] amount: Vec<(&str, &str)>, #[case] expected: bool) {
+        let mut given = vec![("Date", "2024-01-01"), ("Payee", "MOD")];
+        given.extend(amount);
+        let result = NormalizedBankData::new(as_hashmap(given));
+        assert_eq!(result.blank_amount, expected);
+    }
+
+    #[rstest]
+    #[case("123", Some("123"))]
+    #[case("CHK-0012", Some("CHK-0012"))]
+    #[case(" 0042 ", Some("0042"))]
+    #[case("", None)]
+    fn test_from_raw_data_check(#[case] check: &str, #[case] expected: Option<&str>) {
+        let result = NormalizedBankData::new(as_hashmap(vec![
+            ("Date", "2024-01-01"),
+            ("Payee", "MOD"),
+            ("Amount", "-1.00"),
+            ("Check#", check),
+        ]));
+        assert_eq!(result.check.as_deref(), expected);
+    }
+
+    #[test]
+    fn test_from_raw_data_unparseable_date() {
+        let result = NormalizedBankData::from_raw_data(
+            as_hashmap(vec![
+                ("Date", "01/02/2024"),
+                ("Payee", "MOD"),
+                ("Amount", "1.00"),
+            ]),
+            &mapping(""),
+        );
+        assert!(matches!(
+            result.unwrap_err(),
+            TidymoneyError::UnparseableDate { date, format }
+                if date == "01/02/2024" && format == DATE_FORMAT
+        ));
+    }
+
     #[test]
     fn test_csv_serialization() {
         // Use a vector buffer as an in-memory sink.
@@ -140,7 +612,7 @@ mod test {
             ("Payee", "MOD"),
             ("Category", "Dining"),
             ("Amount", "-15.32"),
-            ("Check#", "one"),
+            ("Check#", "CHK-0012"),
         ])))
         .unwrap();
         wtr.serialize(NormalizedBankData::new(as_hashmap(vec![
@@ -157,7 +629,7 @@ mod test {
 
         // Perfor the test itself.
         let expected = "Date,Payee,Category,Memo,Amount,Check#\n\
-                              2024-01-01,MOD,Dining,,-15.32,\n\
+                              2024-01-01,MOD,Dining,,-15.32,CHK-0012\n\
                               2024-02-01,ACE,Home:Maintenance,Nails,-6.02,123\n";
         let result = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
         assert_eq!(result, expected);
@@ -170,7 +642,7 @@ mod test {
             ("Payee", "MOD"),
             ("Amount", "0.00"),
         ],
-        true,
+        Some(SkipReason::ZeroAmount),
     )]
     #[case(
         vec![
@@ -178,7 +650,7 @@ mod test {
             ("Payee", "MOD"),
             ("Amount", "-15.32"),
         ],
-        true,
+        Some(SkipReason::BeforeStart),
     )]
     #[case(
         vec![
@@ -186,7 +658,7 @@ mod test {
             ("Payee", "MOD"),
             ("Amount", "-15.32"),
         ],
-        true,
+        Some(SkipReason::AfterEnd),
     )]
     #[case(
         vec![
@@ -194,12 +666,62 @@ mod test {
             ("Payee", "MOD"),
             ("Amount", "-15.32"),
         ],
-        false
+        None
     )]
-    fn test_skipme(#[case] given: Vec<(&str, &str)>, #[case] expected: bool) {
-        let start_date = NaiveDate::parse_from_str("2024-01-01", &DATE_FORMAT).unwrap();
-        let end_date = NaiveDate::parse_from_str("2024-02-01", &DATE_FORMAT).unwrap();
-        let result = NormalizedBankData::new(as_hashmap(given)).skipme(&start_date, &end_date);
+    fn test_skip_reason(#[case] given: Vec<(&str, &str)>, #[case] expected: Option<SkipReason>) {
+        let start_date = NaiveDate::parse_from_str("2024-01-01", DATE_FORMAT).unwrap();
+        let end_date = NaiveDate::parse_from_str("2024-02-01", DATE_FORMAT).unwrap();
+        let result =
+            NormalizedBankData::new(as_hashmap(given)).skip_reason(&start_date, &end_date, false);
         assert_eq!(result, expected);
     }
+
+    #[rstest]
+    #[case("2024-01-02", false, Some(SkipReason::BeforeStart))]
+    #[case("2024-01-02", true, None)]
+    #[case("", true, Some(SkipReason::BeforeStart))]
+    fn test_skip_reason_with_posted_date(
+        #[case] posted_date: &str,
+        #[case] use_posted_date: bool,
+        #[case] expected: Option<SkipReason>,
+    ) {
+        // The transaction was made before the start but posted after it.
+        let start_date = NaiveDate::parse_from_str("2024-01-01", DATE_FORMAT).unwrap();
+        let end_date = NaiveDate::parse_from_str("2024-02-01", DATE_FORMAT).unwrap();
+        let transaction = NormalizedBankData::from_raw_data(
+            as_hashmap(vec![
+                ("Date", "2023-12-30"),
+                ("PostedDate", posted_date),
+                ("Payee", "MOD"),
+                ("Amount", "-15.32"),
+            ]),
+            &mapping("filter_on_posted_date = true"),
+        )
+        .unwrap();
+        let result = transaction.skip_reason(&start_date, &end_date, use_posted_date);
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case("2024-01-02", false, Ok(None))]
+    #[case("N/A", false, Ok(None))]
+    #[case("2024-01-02", true, Ok(NaiveDate::from_ymd_opt(2024, 1, 2)))]
+    #[case("N/A", true, Err(()))]
+    fn test_from_raw_data_posted_date(
+        #[case] posted_date: &str,
+        #[case] filter_on_posted_date: bool,
+        #[case] expected: Result<Option<NaiveDate>, ()>,
+    ) {
+        // The posted date is only parsed if it is used.
+        let result = NormalizedBankData::from_raw_data(
+            as_hashmap(vec![
+                ("Date", "2024-01-01"),
+                ("PostedDate", posted_date),
+                ("Payee", "MOD"),
+                ("Amount", "-15.32"),
+            ]),
+            &mapping(&format!("filter_on_posted_date = {filter_on_posted_date}")),
+        );
+        assert_eq!(result.map(|x| x.posted_date).map_err(|_| ()), expected);
+    }
 }
@@ -1,19 +1,50 @@
+use std::fmt::Display;
 use std::fs;
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
 
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
+use chrono::NaiveDate;
+use clap::{ArgGroup, ColorChoice, Parser, Subcommand, ValueEnum};
 use config_finder::ConfigDirs;
 use indoc::indoc;
+use serde::Serialize;
 
 use tidymoney::{
-    account_for_dates_in_transactions, normalize_path, process_csv_files, store_raw_transactions,
-    timestamps_path, write_transactions_to_file, RuleFileData, TimestampKeeper, DATE_FORMAT,
+    account_for_dates_in_transactions, count_occurrences, diff_transaction_files,
+    expand_input_paths, find_skewed_dates, identify_csv_file, load_from_str, normalize_path,
+    preview_csv_files, process_csv_files, process_csv_files_timed, select_csv_files,
+    split_empty_csv_files, split_unmatched_csv_files, store_raw_transactions,
+    write_transactions_to_file, AccountSelection, Aggregate, CheckAction, DateOverrides,
+    Explanation, RuleFileData, StageTimes, Stats, DATE_FORMAT,
 };
 
+/// Exit code when a run succeeds but finds no new transactions.
+const EXIT_NO_NEW_TRANSACTIONS: u8 = 2;
+
+/// Exit code when a run succeeds but leaves some files unprocessed.
+const EXIT_PARTIAL_FAILURE: u8 = 3;
+
+/// The rules file name that means to read the rules from standard input.
+const STDIN: &str = "-";
+
+/// A rules file demonstrating every supported key.
+const FULL_TEMPLATE: &str = include_str!("full_rules.toml");
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Cli {
+    /// The rules.toml file to use instead of the default one,
+    /// or - to read it from standard input.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Do not print informational messages (errors are still reported).
+    #[arg(long, short, global = true)]
+    quiet: bool,
+    /// When to color the output. By default it is colored only for a terminal.
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
     #[command(subcommand)]
     command: Commands,
 }
@@ -21,21 +52,141 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     #[command(about = "Run the tidymoney logic")]
-    Run { files: Vec<String> },
+    #[command(group(ArgGroup::new("window").args(["since", "until"]).multiple(true)))]
+    Run {
+        /// The CSV files to process, directories containing them, or glob patterns.
+        files: Vec<String>,
+        /// Also search sub-directories of any given directories for CSV files.
+        #[arg(long, short)]
+        recursive: bool,
+        /// Create the storage directory from the rules file if it does not exist.
+        #[arg(long)]
+        create_storage: bool,
+        /// Only keep transactions on or after this date (YYYY-MM-DD),
+        /// ignoring the stored timestamps.
+        #[arg(long, value_parser = parse_date)]
+        since: Option<NaiveDate>,
+        /// Only keep transactions on or before this date (YYYY-MM-DD)
+        /// instead of today. Timestamps will not advance past this date.
+        #[arg(long, value_parser = parse_date)]
+        until: Option<NaiveDate>,
+        /// Only apply --since and --until to this account.
+        #[arg(long, requires = "window")]
+        account: Option<String>,
+        /// Remove any output from an earlier run on the same date before writing.
+        #[arg(long)]
+        fresh: bool,
+        /// Write one row per payee with the number and total of its transactions
+        /// instead of one row per transaction.
+        #[arg(long, value_name = "payee")]
+        aggregate: Option<Aggregate>,
+        /// Show how the output would differ from that of an earlier run on the
+        /// same date, without writing or moving any files.
+        #[arg(long)]
+        diff: bool,
+        /// Only process the CSV files for this account (may be repeated).
+        #[arg(long, value_name = "LABEL")]
+        only: Vec<String>,
+        /// Leave the CSV files for this account alone (may be repeated).
+        #[arg(long, value_name = "LABEL", conflicts_with = "only")]
+        exclude: Vec<String>,
+        /// What to do with CSV files that do not match any account.
+        #[arg(long, value_enum, default_value_t = OnUnmatched::Error)]
+        on_unmatched: OnUnmatched,
+        /// Leave CSV files without any transactions where they are instead of
+        /// moving them into storage.
+        #[arg(long)]
+        skip_empty: bool,
+        /// Use this directory instead of the storage directory from the rules
+        /// file for this run. It is created if it does not exist. The mirrors
+        /// are not written, the raw files are not moved, and the timestamps are
+        /// not updated.
+        #[arg(long, value_name = "PATH")]
+        output_dir: Option<PathBuf>,
+        /// Only process the first N rows of each file and print the result,
+        /// without writing or moving any files.
+        #[arg(long, value_name = "N", conflicts_with = "diff")]
+        limit: Option<usize>,
+        /// Print how long each stage of the run took.
+        #[arg(long)]
+        profile: bool,
+        /// Treat this date (YYYY-MM-DD) as today, for the output folder names
+        /// and which transactions are kept.
+        #[arg(long, value_parser = parse_date)]
+        now: Option<NaiveDate>,
+    },
+    #[command(about = "Check the rules.toml file for problems")]
+    Validate {
+        /// Print the result as JSON instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    #[command(about = "Summarize transactions without writing or moving any files")]
+    Stats {
+        /// The CSV files to summarize, directories containing them, or glob patterns.
+        files: Vec<String>,
+        /// Also search sub-directories of any given directories for CSV files.
+        #[arg(long, short)]
+        recursive: bool,
+        /// Print the result as JSON instead of text.
+        #[arg(long)]
+        json: bool,
+        /// Only summarize the first N rows of each file.
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+    },
+    #[command(
+        about = "Show which rules changed each transaction, without writing or moving any files"
+    )]
+    Explain {
+        /// The CSV files to explain, directories containing them, or glob patterns.
+        files: Vec<String>,
+        /// Also search sub-directories of any given directories for CSV files.
+        #[arg(long, short)]
+        recursive: bool,
+        /// Print the result as JSON instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    #[command(about = "Show the date of the last transaction seen for each account")]
+    ShowTimestamps {
+        /// Only show the date for this account.
+        #[arg(long)]
+        account: Option<String>,
+    },
+    #[command(about = "Show the account that each CSV file belongs to")]
+    Identify {
+        /// The CSV files to identify, directories containing them, or glob patterns.
+        files: Vec<String>,
+        /// Also search sub-directories of any given directories for CSV files.
+        #[arg(long, short)]
+        recursive: bool,
+    },
     #[command(about = "Edit the rules.toml file in $EDITOR")]
     EditConfig {},
     #[command(about = "Show the location of the rules.toml file")]
     ShowConfig {},
+    #[command(about = "Upgrade the rules.toml file to the current format")]
+    MigrateConfig {},
     #[command(about = "Create the rules.toml file")]
-    CreateConfig {},
+    CreateConfig {
+        /// Write a commented template demonstrating every supported key.
+        #[arg(long)]
+        full: bool,
+    },
 }
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     let cli = Cli::parse();
+    let config = cli.config.as_deref();
+    let quiet = cli.quiet;
+    let out = Painter::new(cli.color, &io::stdout());
+    let err = Painter::new(cli.color, &io::stderr());
 
     match cli.command {
-        Commands::CreateConfig {} => {
-            let rule_file = get_rule_file()?;
+        Commands::CreateConfig { full } => {
+            let rule_file = get_rule_file(config)?;
+            check_rule_file_not_stdin(&rule_file)?;
             if rule_file.is_file() {
                 return Err(anyhow!("The rule file {:#?} already exists.", rule_file));
             }
@@ -45,10 +196,13 @@ fn main() -> Result<()> {
             if !parent.is_dir() {
                 fs::create_dir_all(parent)?;
             }
-            fs::write(
-                rule_file,
+            let template = if full {
+                FULL_TEMPLATE
+            } else {
                 indoc! {
                 r#"
+                version = 1
+
                 [payees]
                 Description = "RULE FOR NAMING TRANSACTION"
 
@@ -64,56 +218,527 @@ fn main() -> Result<()> {
                 [paths]
                 storage = "/path/to/storage/directory"
                 "#
-                },
-            )?;
-            println!("Created {:#?}.\n", get_rule_file()?);
-            println!("You can use 'tidymoney edit-config' to edit this file.\n");
-            println!("See https://github.com/SethMMorton/tidymoney for instructions.");
+                }
+            };
+            fs::write(&rule_file, template)?;
+            if !quiet {
+                println!("Created {:#?}.\n", rule_file);
+                println!("You can use 'tidymoney edit-config' to edit this file.\n");
+                println!("See https://github.com/SethMMorton/tidymoney for instructions.");
+            }
         }
         Commands::ShowConfig {} => {
-            println!("{}", get_rule_file()?.to_str().unwrap());
+            println!("{}", get_rule_file(config)?.to_str().unwrap());
+        }
+        Commands::Validate { json } => {
+            let rule_file = get_rule_file(config)?;
+            check_rule_file_exists(&rule_file)?;
+            let report = match load_rules(&rule_file) {
+                Ok(_) => ValidationReport {
+                    ok: true,
+                    errors: vec![],
+                },
+                Err(e) => ValidationReport {
+                    ok: false,
+                    errors: vec![e.to_string()],
+                },
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else if report.ok {
+                if !quiet {
+                    let message = format!("The rules file {:#?} is valid.", rule_file);
+                    println!("{}", out.paint(message, Color::Green));
+                }
+            } else {
+                for error in &report.errors {
+                    println!("{}", out.paint(error, Color::Red));
+                }
+            }
+            if !report.ok {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Stats {
+            files,
+            recursive,
+            json,
+            limit,
+        } => {
+            let rule_file = get_rule_file(config)?;
+            check_rule_file_exists(&rule_file)?;
+            let rules = load_rules(&rule_file)?;
+            let files = expand_input_paths(&files, recursive)?;
+            let mut results = match limit {
+                Some(limit) => preview_csv_files(&files, &rules, limit)?,
+                None => process_csv_files(&files, &rules)?,
+            };
+            count_occurrences(&mut results);
+            let stats = Stats::new(&results, &rules);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print!("{}", paint_stats(&stats, out));
+            }
+        }
+        Commands::Explain {
+            files,
+            recursive,
+            json,
+        } => {
+            let rule_file = get_rule_file(config)?;
+            check_rule_file_exists(&rule_file)?;
+            let rules = load_rules(&rule_file)?;
+            let files = expand_input_paths(&files, recursive)?;
+            let mut results = process_csv_files(&files, &rules)?;
+            count_occurrences(&mut results);
+            let explanations = Explanation::all(&results);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&explanations)?);
+            } else {
+                for explanation in explanations {
+                    print!("{explanation}");
+                }
+            }
+        }
+        Commands::ShowTimestamps { account } => {
+            let rule_file = get_rule_file(config)?;
+            check_rule_file_exists(&rule_file)?;
+            let (rules, stamps, _) = load_from_str(&read_rule_file(&rule_file)?, &rule_file)?;
+            check_stdin_timestamps(&rule_file, &rules)?;
+            let mut dates = stamps.sorted_dates();
+            if let Some(account) = &account {
+                dates.retain(|(a, _)| a == account);
+                if dates.is_empty() {
+                    return Err(anyhow!(
+                        "No timestamp is stored for the account {account:#?}."
+                    ));
+                }
+            }
+            for (account, date) in dates {
+                println!("{account}: {}", date.format(DATE_FORMAT));
+            }
+        }
+        Commands::Identify { files, recursive } => {
+            let rule_file = get_rule_file(config)?;
+            check_rule_file_exists(&rule_file)?;
+            let rules = load_rules(&rule_file)?;
+            let mut all_matched = true;
+            for file in expand_input_paths(&files, recursive)? {
+                match identify_csv_file(&file, &rules)? {
+                    (_, Some(label)) => {
+                        println!("{}: {}", file.display(), out.paint(label, Color::Green))
+                    }
+                    (headers, None) => {
+                        all_matched = false;
+                        let message = format!("no match for headers {:?}", headers);
+                        println!("{}: {}", file.display(), out.paint(message, Color::Red));
+                    }
+                }
+            }
+            if !all_matched {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::MigrateConfig {} => {
+            let rule_file = get_rule_file(config)?;
+            check_rule_file_not_stdin(&rule_file)?;
+            check_rule_file_exists(&rule_file)?;
+            let migration = RuleFileData::migrate(fs::read_to_string(&rule_file)?)?;
+            if migration.changes.is_empty() {
+                if !quiet {
+                    println!("The rules file {:#?} is already up to date.", rule_file);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            let mut backup = rule_file.clone().into_os_string();
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+            fs::copy(&rule_file, &backup)?;
+            fs::write(&rule_file, migration.rules)?;
+            if !quiet {
+                for change in &migration.changes {
+                    println!("{}", change);
+                }
+                println!(
+                    "\nMigrated {:#?}, the original is saved as {:#?}.",
+                    rule_file, backup
+                );
+            }
+            if let Err(e) = load_rules(&rule_file) {
+                let message = format!("The migrated rules file is still not valid: {}", e);
+                eprintln!("{}", err.paint(message, Color::Yellow));
+            }
         }
         Commands::EditConfig {} => {
-            let rule_file = get_rule_file()?;
+            let rule_file = get_rule_file(config)?;
+            check_rule_file_not_stdin(&rule_file)?;
             check_rule_file_exists(&rule_file)?;
             edit::edit_file(rule_file)?;
         }
-        Commands::Run { files } => {
-            let rule_file = get_rule_file()?;
+        Commands::Run {
+            files,
+            recursive,
+            create_storage,
+            since,
+            until,
+            account,
+            fresh,
+            aggregate,
+            diff,
+            only,
+            exclude,
+            on_unmatched,
+            skip_empty,
+            output_dir,
+            limit,
+            profile,
+            now,
+        } => {
+            // Ensure the date overrides make sense before doing any work.
+            let overrides = DateOverrides {
+                since,
+                until,
+                account,
+            };
+            overrides.validate()?;
+
+            let rule_file = get_rule_file(config)?;
             check_rule_file_exists(&rule_file)?;
-            let stamps_file = timestamps_path(&rule_file)?;
 
             // Get the internal data from disk.
-            let rule_data = fs::read_to_string(&rule_file)?;
-            let stamps_data = fs::read_to_string(&stamps_file)?;
-            let rules = RuleFileData::new(&rule_data)?;
-            let mut stamps = TimestampKeeper::new(&stamps_data)?;
+            let rule_data = read_rule_file(&rule_file)?;
+            let rule_dir = rule_file.parent().unwrap_or(Path::new(""));
+            if create_storage {
+                let created = RuleFileData::create_storage(&rule_data, rule_dir)?;
+                if let Some(storage) = created.filter(|_| !quiet) {
+                    println!("Created the storage directory {:#?}", storage);
+                }
+            }
+            let (mut rules, mut stamps, stamps_file) = load_from_str(&rule_data, &rule_file)?;
+            check_stdin_timestamps(&rule_file, &rules)?;
+            if aggregate.is_some() {
+                rules.output.aggregate = aggregate;
+            }
+            let one_off = output_dir.is_some();
+            if let Some(output_dir) = output_dir {
+                rules.paths.storage = normalize_path(output_dir)?;
+                rules.paths.mirrors.clear();
+            }
 
-            // Process the transactions.
-            let mut results = process_csv_files(&files, &rules)?;
+            // Expand any glob patterns and directories into the CSV files they match.
+            // Stop before touching anything if there is nothing to process.
+            let files = expand_input_paths(&files, recursive)?;
+            if files.is_empty() {
+                return Err(anyhow!(
+                    "No CSV files were given to process - pass the files or directories containing them."
+                ));
+            }
+
+            // Leave the files for accounts that were not selected where they are.
+            let selection = AccountSelection { only, exclude };
+            selection.validate(&rules)?;
+            let files = select_csv_files(files, &rules, &selection)?;
+            if files.is_empty() {
+                return Err(anyhow!(
+                    "None of the given CSV files are for the selected accounts."
+                ));
+            }
+
+            // Files without any transactions are only reported, since a completely
+            // empty file cannot be matched to an account.
+            let (files, empty) = split_empty_csv_files(files, &rules)?;
+
+            // Leave the files that do not match any account where they are, if asked.
+            let (files, unmatched) = match on_unmatched {
+                OnUnmatched::Error => (files, vec![]),
+                OnUnmatched::Skip => split_unmatched_csv_files(files, &rules)?,
+            };
+            if files.is_empty() && empty.is_empty() {
+                return Err(anyhow!(
+                    "None of the given CSV files match an account in the rules file."
+                ));
+            }
+
+            // Only show how the first rows of each file are normalized, if asked.
+            if let Some(limit) = limit {
+                let results = preview_csv_files(&files, &rules, limit)?;
+                let mut labels: Vec<_> = results.keys().collect();
+                labels.sort();
+                for label in labels {
+                    println!("{label}:");
+                    print!("{}", results[label].get_transactions_as_csv()?);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            // Process the transactions, timing each stage if asked.
+            let mut times = profile.then(StageTimes::default);
+            let mut results = match &mut times {
+                Some(times) => process_csv_files_timed(&files, &rules, times)?,
+                None => process_csv_files(&files, &rules)?,
+            };
+
+            // Dates far from today usually mean that a date_fmt is wrong.
+            let now = now.unwrap_or_else(|| chrono::offset::Local::now().naive_local().date());
+            let years = rules.checks.max_date_skew_years;
+            let skewed = find_skewed_dates(&now, years, &results);
+            if let Some(first) = skewed.first() {
+                if rules.checks.date_skew == CheckAction::Error {
+                    return Err(first.to_error(years).into());
+                }
+                let message = format!(
+                    "Warning: These transactions are dated more than {years} years from today \
+                     - check the date_fmt of their accounts:"
+                );
+                eprintln!("{}", err.paint(message, Color::Yellow));
+                for transaction in &skewed {
+                    eprintln!("    {transaction}");
+                }
+            }
 
             // Apply the current time to transactions and the timestamp records.
-            let now = chrono::offset::Local::now().naive_local().date();
-            account_for_dates_in_transactions(&now, &mut results, &mut stamps);
+            let skipped = timed(&mut times, "filtering", || {
+                account_for_dates_in_transactions(&now, &overrides, &mut results, &mut stamps)
+            });
+            let mut skipped: Vec<_> = skipped
+                .into_iter()
+                .filter(|(_, c)| !quiet && c.total() > 0)
+                .collect();
+            skipped.sort_by(|x1, x2| x1.0.cmp(&x2.0));
+            for (label, counts) in skipped {
+                println!(
+                    "Skipped {} transactions for {label}: {counts}",
+                    counts.total()
+                );
+            }
 
-            // Write the new transactions to file.
+            // Write the new transactions to file, or only show how they would differ.
             let now_str = now.format(DATE_FORMAT).to_string();
-            write_transactions_to_file(&now_str, &rules.paths.storage, &results)?;
+            if diff {
+                for file in diff_transaction_files(&now_str, &rules.paths, &results)? {
+                    if file.is_empty() {
+                        println!("{}: no changes", file.path.display());
+                        continue;
+                    }
+                    println!("{}:", file.path.display());
+                    for row in &file.removed {
+                        println!("{}", out.paint(format!("- {row}"), Color::Red));
+                    }
+                    for row in &file.added {
+                        println!("{}", out.paint(format!("+ {row}"), Color::Green));
+                    }
+                }
+                warn_skipped(&unmatched, &empty, skip_empty, err);
+                print_times(times.as_ref());
+                return Ok(ExitCode::SUCCESS);
+            }
+            if one_off {
+                fs::create_dir_all(&rules.paths.storage)?;
+            }
+            let existing = timed(&mut times, "writing", || {
+                write_transactions_to_file(&now_str, &rules.paths, &results, fresh)
+            })?;
+            if fresh && !quiet && !existing.is_empty() {
+                println!(
+                    "Removed {} files from an earlier run on {now_str}",
+                    existing.len()
+                );
+            } else if !fresh && !existing.is_empty() {
+                let message = format!(
+                    "Warning: These files are from an earlier run on {now_str} and may be stale \
+                     (use --fresh to remove them):"
+                );
+                eprintln!("{}", err.paint(message, Color::Yellow));
+                for path in existing {
+                    eprintln!("    {}", path.display());
+                }
+            }
+
+            // Write save the old files in the storage location, unless this is
+            // a one-off export that leaves them for the next regular run.
+            if !one_off {
+                let archived = if skip_empty {
+                    files
+                } else {
+                    files.into_iter().chain(empty.iter().cloned()).collect()
+                };
+                timed(&mut times, "archiving", || {
+                    store_raw_transactions(&rules.paths, &archived, &now_str)
+                })?;
+            }
+
+            // Update the timestamps path, unless this is a one-off export.
+            if !one_off {
+                fs::write(&stamps_file, stamps.get_updated_stamps()?)?;
+            }
+
+            // Let the user do something with the new files.
+            if let Some(command) = &rules.hooks.post_run {
+                let output_dir = rules.paths.output_path(&now_str);
+                if let Err(error) = run_post_run_hook(command, &now_str, &output_dir) {
+                    if rules.hooks.post_run_fatal {
+                        return Err(error);
+                    }
+                    eprintln!("{}", err.paint(format!("Warning: {error}"), Color::Yellow));
+                }
+            }
 
-            // Write save the old files in the storage location.
-            store_raw_transactions(&rules.paths.storage, &files, &now_str)?;
+            warn_skipped(&unmatched, &empty, skip_empty, err);
+            print_times(times.as_ref());
 
-            // Update the timestamps path.
-            fs::write(&stamps_file, stamps.get_updated_stamps()?)?;
+            // Let the caller know if some files were left behind or nothing new was found.
+            if !unmatched.is_empty() {
+                return Ok(ExitCode::from(EXIT_PARTIAL_FAILURE));
+            }
+            if results.values().all(|transactions| transactions.is_empty()) {
+                return Ok(ExitCode::from(EXIT_NO_NEW_TRANSACTIONS));
+            }
         }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Run the given stage of a run, recording how long it took if profiling.
+fn timed<T>(times: &mut Option<StageTimes>, stage: &'static str, f: impl FnOnce() -> T) -> T {
+    match times {
+        Some(times) => times.time(stage, f),
+        None => f(),
+    }
+}
+
+/// Print how long each stage of a run took, if profiling.
+fn print_times(times: Option<&StageTimes>) {
+    if let Some(times) = times {
+        eprintln!("Time spent in each stage:");
+        for line in times.to_string().lines() {
+            eprintln!("    {line}");
+        }
+    }
+}
+
+/// What to do with CSV files that do not match any account.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OnUnmatched {
+    /// Leave the files where they are and report them once the run is done,
+    /// exiting with a distinct code.
+    Skip,
+    /// Stop the run before any files are written or moved.
+    Error,
+}
+
+/// Report the CSV files that were skipped because they match no account
+/// or have no transactions.
+fn warn_skipped(unmatched: &[PathBuf], empty: &[PathBuf], skip_empty: bool, err: Painter) {
+    let empty_message = if skip_empty {
+        "Warning: These files were left in place because they have no transactions:"
+    } else {
+        "Warning: These files have no transactions:"
+    };
+    warn_files(empty_message, empty, err);
+    warn_files(
+        "Warning: These files were skipped because they do not match any account:",
+        unmatched,
+        err,
+    );
+}
+
+/// Print a warning followed by the files it is about, if there are any.
+fn warn_files(message: &str, paths: &[PathBuf], err: Painter) {
+    if paths.is_empty() {
+        return;
+    }
+    eprintln!("{}", err.paint(message, Color::Yellow));
+    for path in paths {
+        eprintln!("    {}", path.display());
+    }
+}
+
+/// The colors used to highlight terminal output.
+#[derive(Clone, Copy)]
+enum Color {
+    Red = 31,
+    Green = 32,
+    Yellow = 33,
+}
+
+/// Colors text written to a stream, if color is wanted for that stream.
+#[derive(Clone, Copy)]
+struct Painter {
+    /// Whether or not to add color.
+    enabled: bool,
+}
+
+impl Painter {
+    /// Decide on color for the stream, automatically only using it for a terminal.
+    fn new(choice: ColorChoice, stream: &impl IsTerminal) -> Self {
+        let enabled = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                stream.is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|x| x.is_empty())
+            }
+        };
+        Painter { enabled }
+    }
+
+    /// Wrap the text in the ANSI codes for the color, if enabled.
+    fn paint(&self, text: impl Display, color: Color) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{text}\x1b[0m", color as u8)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Format the statistics summary, highlighting the payees without a matching rule.
+fn paint_stats(stats: &Stats, painter: Painter) -> String {
+    let mut unmatched = false;
+    let mut text = String::new();
+    for line in stats.to_string().lines() {
+        if !line.starts_with("        ") {
+            unmatched = line.trim() == "Payees without a matching rule:";
+        }
+        let line = if unmatched {
+            painter.paint(line, Color::Yellow)
+        } else {
+            line.to_owned()
+        };
+        text.push_str(&line);
+        text.push('\n');
+    }
+    text
+}
+
+/// The outcome of validating the rules file.
+#[derive(Serialize)]
+struct ValidationReport {
+    /// Whether or not the rules file is valid.
+    ok: bool,
+    /// The problems found with the rules file.
+    errors: Vec<String>,
+}
+
+/// Read and validate the rules file.
+fn load_rules(rule_file: &Path) -> Result<RuleFileData> {
+    let rule_data = read_rule_file(rule_file)?;
+    let rule_dir = rule_file.parent().unwrap_or(Path::new(""));
+    Ok(RuleFileData::new_in_dir(&rule_data, rule_dir)?)
+}
+
+/// Read the contents of the rules file, which may be standard input.
+fn read_rule_file(rule_file: &Path) -> Result<String> {
+    if rule_file == Path::new(STDIN) {
+        return Ok(io::read_to_string(io::stdin())?);
+    }
+    Ok(fs::read_to_string(rule_file)?)
 }
 
 // Ensure the rule file exists.
 fn check_rule_file_exists(rule_file: impl AsRef<Path>) -> Result<()> {
-    if !rule_file.as_ref().is_file() {
+    if rule_file.as_ref() != Path::new(STDIN) && !rule_file.as_ref().is_file() {
         return Err(anyhow!(
             "The file {:#?} does not exist - create it with 'tidymoney create-config'.",
             rule_file.as_ref()
@@ -122,16 +747,77 @@ fn check_rule_file_exists(rule_file: impl AsRef<Path>) -> Result<()> {
     Ok(())
 }
 
-/// Return the path to the rules.toml file.
-fn get_rule_file() -> Result<PathBuf> {
+// Ensure rules from standard input say where the timestamps are, rather than
+// keeping them wherever the command happens to be run from.
+fn check_stdin_timestamps(rule_file: impl AsRef<Path>, rules: &RuleFileData) -> Result<()> {
+    if rule_file.as_ref() == Path::new(STDIN) && rules.paths.timestamps.is_none() {
+        return Err(anyhow!(
+            "Rules read from standard input must give the timestamps path in the [paths] section."
+        ));
+    }
+    Ok(())
+}
+
+// Ensure the rule file is a real file and not standard input.
+fn check_rule_file_not_stdin(rule_file: impl AsRef<Path>) -> Result<()> {
+    if rule_file.as_ref() == Path::new(STDIN) {
+        return Err(anyhow!(
+            "This command needs a rules file, not standard input."
+        ));
+    }
+    Ok(())
+}
+
+/// Return the path to the rules.toml file, preferring the one given on the command line.
+fn get_rule_file(config: Option<&Path>) -> Result<PathBuf> {
+    match config {
+        Some(path) if path == Path::new(STDIN) => return Ok(path.to_path_buf()),
+        Some(path) => return Ok(normalize_path(path)?),
+        None => {}
+    }
     let mut conf_dirs = ConfigDirs::empty();
     let mut conf_files = conf_dirs
         .add_platform_config_dir()
         .search("tidymoney", "rules", "toml");
-    normalize_path(
+    Ok(normalize_path(
         conf_files
             .next()
             .ok_or(anyhow!("Cannot identify the path to the rules.toml file"))?
             .path(),
-    )
+    )?)
+}
+
+/// Run the post_run hook command through the shell, giving it the run date and output directory.
+fn run_post_run_hook(command: &str, run_date: &str, output_dir: &Path) -> Result<()> {
+    #[cfg(windows)]
+    let mut hook = {
+        let mut hook = Command::new("cmd");
+        hook.arg("/C").arg(command).arg(run_date).arg(output_dir);
+        hook
+    };
+    #[cfg(not(windows))]
+    let mut hook = {
+        let mut hook = Command::new("sh");
+        hook.arg("-c")
+            .arg(command)
+            .arg("tidymoney")
+            .arg(run_date)
+            .arg(output_dir);
+        hook
+    };
+    let status = hook
+        .env("TIDYMONEY_RUN_DATE", run_date)
+        .env("TIDYMONEY_OUTPUT_DIR", output_dir)
+        .status()
+        .map_err(|err| anyhow!("Cannot run the post_run hook {command:#?}: {err}"))?;
+    if !status.success() {
+        return Err(anyhow!("The post_run hook {command:#?} failed ({status})"));
+    }
+    Ok(())
+}
+
+/// Parse a date given on the command line.
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(date, DATE_FORMAT)
+        .map_err(|_| anyhow!("Cannot parse the date {date:#?}, expected YYYY-MM-DD"))
 }
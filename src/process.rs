@@ -1,14 +1,25 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
-use chrono::NaiveDate;
+use chrono::{Datelike, Months, NaiveDate};
+use rust_decimal::Decimal;
+use serde::Serialize;
 
-use crate::file_io::ensure_storage_path;
-use crate::rules::MappingRulesCsv;
-use crate::rules::RuleFileData;
-use crate::{NormalizedBankData, TimestampKeeper};
+use crate::columns;
+use crate::file_io::{
+    directory_entries, ensure_storage_path, open_maybe_gzipped, remove_entry, write_atomically,
+};
+use crate::rules::{
+    Aggregate, AuxillaryPaths, CheckAction, OutputFormat, RuleFileData, SortOrder, TieBreak,
+};
+use crate::rules::{BlankAmounts, MappingRulesCsv};
+use crate::{parse_amount, NormalizedBankData, SkipReason, TidymoneyError, TimestampKeeper};
 
 /// Processing and storage of transaction data.
 pub struct TransactionProcessor<'a> {
@@ -30,38 +41,580 @@ impl<'a> TransactionProcessor<'a> {
         }
     }
 
+    /// Process all rows read from a single file, possibly storing the data.
+    fn process_file(
+        &mut self,
+        rows: Vec<HashMap<String, String>>,
+        source: Option<&Path>,
+    ) -> Result<(), TidymoneyError> {
+        let rows: Vec<_> = rows
+            .into_iter()
+            .map(|row| self.mapping.remap(self.mapping.trim(row)))
+            .collect::<Result<_, _>>()?;
+
+        // The amount of each row may depend on the rows around it.
+        let rows = if self.mapping.amount_from_balance {
+            amounts_from_balance(rows, &self.mapping.date_fmt, &self.mapping.label)?
+        } else {
+            rows
+        };
+
+        // Keep going past unparseable dates so that a wrong date_fmt can be
+        // reported once instead of as an error for the first row.
+        let total = rows.len();
+        let mut failures = 0;
+        let mut first_failure = None;
+        for row in rows {
+            match self.store(row, source) {
+                Err(TidymoneyError::UnparseableDate { date, format }) => {
+                    failures += 1;
+                    first_failure.get_or_insert((date, format));
+                }
+                result => result?,
+            }
+        }
+        match first_failure {
+            None => Ok(()),
+            Some((sample, format)) if failures >= DATE_FORMAT_MISMATCH_THRESHOLD => {
+                Err(TidymoneyError::DateFormatMismatch {
+                    account: self.mapping.label.to_owned(),
+                    format,
+                    sample,
+                    failures,
+                    rows: total,
+                })
+            }
+            Some((date, format)) => Err(TidymoneyError::UnparseableDate { date, format }),
+        }
+    }
+
     /// Process a single transaction instance, possibly store the data.
-    fn process(&mut self, data: HashMap<String, String>) -> Result<()> {
-        // Convert the raw data into struct form.
-        let mut norm = NormalizedBankData::from_raw_data(
-            self.mapping.remap(data),
-            self.mapping.negate,
-            &self.mapping.date_fmt,
-            &self.mapping.label,
-        )?;
+    #[cfg(test)]
+    fn process(&mut self, data: HashMap<String, String>) -> Result<(), TidymoneyError> {
+        self.store(self.mapping.remap(self.mapping.trim(data))?, None)
+    }
 
-        // Update the contents of the transaction.
-        self.rules.update_transaction(&mut norm);
+    /// Store a single transaction instance whose columns have already been remapped.
+    fn store(
+        &mut self,
+        data: HashMap<String, String>,
+        source: Option<&Path>,
+    ) -> Result<(), TidymoneyError> {
+        // Convert the raw data into struct form and update its contents.
+        let mut norm = self.rules.normalize(self.mapping, data)?;
+        norm.source = source.map(|x| x.to_path_buf());
 
         // Save the transaction.
         self.transactions.push(norm);
         Ok(())
     }
 
-    /// Remove any transaction that should not remain according to rules.
-    pub fn drop_uneeded(&mut self, start_date: &NaiveDate, end_date: &NaiveDate) {
-        self.transactions
-            .retain(|trans| !trans.skipme(start_date, end_date));
+    /// The transactions currently held.
+    pub fn transactions(&self) -> &[NormalizedBankData] {
+        &self.transactions
+    }
+
+    /// The number of transactions currently held.
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Whether or not there are no transactions currently held.
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Remove any transaction that should not remain according to rules,
+    /// returning how many were removed for each reason.
+    pub fn drop_uneeded(&mut self, start_date: &NaiveDate, end_date: &NaiveDate) -> SkipCounts {
+        let mut counts = SkipCounts::default();
+        let use_posted_date = self.mapping.filter_on_posted_date;
+        let skip_blank = self.mapping.blank_amounts == BlankAmounts::Skip;
+        let rules = self.rules;
+        self.transactions.retain(|trans| {
+            if rules.is_ignored(trans) {
+                counts.add(SkipReason::Ignored);
+                return false;
+            }
+            match trans.skip_reason(start_date, end_date, use_posted_date) {
+                Some(SkipReason::ZeroAmount) if skip_blank && trans.blank_amount => {
+                    counts.add(SkipReason::BlankAmount);
+                    false
+                }
+                Some(reason) => {
+                    counts.add(reason);
+                    false
+                }
+                None => true,
+            }
+        });
+        counts
+    }
+
+    /// Apply the rules that depend on which occurrence of its payee each
+    /// transaction is. Transactions are counted for each payee in chronological
+    /// order, keeping the original order for the same date.
+    fn apply_occurrence_rules(&mut self) {
+        if !self.rules.has_occurrence_rules() {
+            return;
+        }
+        let mut order: Vec<usize> = (0..self.transactions.len()).collect();
+        order.sort_by_key(|&index| self.transactions[index].date);
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for index in order {
+            let transaction = &mut self.transactions[index];
+            let count = counts.entry(transaction.payee.to_owned()).or_default();
+            *count += 1;
+            self.rules.update_for_occurrence(transaction, *count);
+        }
+    }
+
+    /// Give each uncategorized transaction the category of the most recent
+    /// earlier transaction with the same payee, if requested. Transactions are
+    /// considered in chronological order, keeping the original order for the same date.
+    fn carry_forward_categories(&mut self) {
+        if !self.rules.output.carry_forward_categories {
+            return;
+        }
+        let mut order: Vec<usize> = (0..self.transactions.len()).collect();
+        order.sort_by_key(|&index| self.transactions[index].date);
+
+        let mut categories: HashMap<String, String> = HashMap::new();
+        for index in order {
+            let transaction = &mut self.transactions[index];
+            match transaction.category.as_deref().map(str::trim) {
+                None | Some("") => {
+                    if let Some(category) = categories.get(&transaction.payee) {
+                        transaction.category = Some(category.to_owned());
+                    }
+                }
+                Some(category) => {
+                    categories.insert(transaction.payee.to_owned(), category.to_owned());
+                }
+            }
+        }
+    }
+
+    /// Give each transaction the balance of the account after it, if requested.
+    ///
+    /// Transactions are considered in chronological order, keeping the original
+    /// order for the same date. The balance before the earliest transaction is
+    /// the account's starting_balance if given, otherwise it is worked back from
+    /// the earliest balance reported by the bank, otherwise it is zero.
+    fn compute_running_balances(&mut self) {
+        if !self.rules.output.running_balance {
+            return;
+        }
+        let mut order: Vec<usize> = (0..self.transactions.len()).collect();
+        order.sort_by_key(|&index| self.transactions[index].date);
+
+        let reported = || {
+            let mut total = Decimal::ZERO;
+            for &index in &order {
+                let transaction = &self.transactions[index];
+                total += transaction.amount;
+                if let Some(balance) = transaction.balance {
+                    return balance - total;
+                }
+            }
+            Decimal::ZERO
+        };
+        let mut balance = self.mapping.starting_balance.unwrap_or_else(reported);
+        for &index in &order {
+            let transaction = &mut self.transactions[index];
+            balance += transaction.amount;
+            transaction.running_balance = Some(balance);
+        }
+    }
+
+    /// Flag or remove pairs of transactions that cancel each other out, if
+    /// requested, returning how many were removed.
+    pub fn handle_reversals(&mut self) -> usize {
+        let Some(options) = self.rules.output.reversals else {
+            return 0;
+        };
+        let reversed: HashSet<usize> = reversal_pairs(&self.transactions, options.window_days)
+            .into_iter()
+            .flat_map(|(x, y)| [x, y])
+            .collect();
+
+        if options.remove {
+            let mut index = 0;
+            self.transactions.retain(|_| {
+                index += 1;
+                !reversed.contains(&(index - 1))
+            });
+            return reversed.len();
+        }
+        for index in reversed {
+            let transaction = &mut self.transactions[index];
+            transaction.memo = match transaction.memo.as_deref().map(str::trim) {
+                None | Some("") => Some(REVERSED_MEMO.to_owned()),
+                Some(memo) => Some(format!("{REVERSED_MEMO}: {memo}")),
+            };
+        }
+        0
+    }
+
+    /// Return the transactions in the order in which they should be written.
+    fn sorted_transactions(&self) -> Vec<&NormalizedBankData> {
+        // The sort is stable, so transactions that tie keep their original order.
+        let mut transactions: Vec<&NormalizedBankData> = self.transactions.iter().collect();
+        let tie_break =
+            |a: &NormalizedBankData, b: &NormalizedBankData| match self.rules.output.tie_break {
+                TieBreak::Input => Ordering::Equal,
+                TieBreak::Amount => a.amount.cmp(&b.amount),
+                TieBreak::Payee => a.payee.cmp(&b.payee),
+            };
+        match self.rules.output.sort_order {
+            Some(SortOrder::Ascending) => {
+                transactions.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| tie_break(a, b)))
+            }
+            Some(SortOrder::Descending) => {
+                transactions.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| tie_break(a, b)))
+            }
+            None => {}
+        }
+        transactions
     }
 
     /// Return a string containing the CSV representation of the transactions.
     pub fn get_transactions_as_csv(&self) -> Result<String> {
-        let mut wtr = csv::Writer::from_writer(vec![]);
-        for transaction in self.transactions.iter() {
-            wtr.serialize(transaction)?;
+        self.as_csv(self.sorted_transactions())
+    }
+
+    /// Return the name and contents of each file to write for this account,
+    /// which is one file per month of transactions if requested.
+    pub fn get_transaction_files(&self) -> Result<Vec<(String, String)>> {
+        self.transaction_files()
+            .into_iter()
+            .map(|(name, transactions)| Ok((name, self.as_csv(transactions)?)))
+            .collect()
+    }
+
+    /// Write each file for this account into the given directory, one row at a
+    /// time so that the contents of a file are never held in memory.
+    pub fn write_transaction_files(&self, dir: impl AsRef<Path>) -> Result<()> {
+        for (name, transactions) in self.transaction_files() {
+            write_atomically(dir.as_ref().join(name), |out| {
+                self.write_rows(transactions, out)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Return the name of each file to write for this account and the
+    /// transactions it contains, which is one file per month if requested.
+    fn transaction_files(&self) -> Vec<(String, Vec<&NormalizedBankData>)> {
+        let name = self.mapping.output_name();
+        let extension = self.rules.output.format.extension();
+        if !self.rules.output.split_by_month {
+            return vec![(format!("{name}.{extension}"), self.sorted_transactions())];
+        }
+
+        let mut months: BTreeMap<(i32, u32), Vec<&NormalizedBankData>> = BTreeMap::new();
+        for transaction in self.sorted_transactions() {
+            let month = (transaction.date.year(), transaction.date.month());
+            months.entry(month).or_default().push(transaction);
+        }
+        months
+            .into_iter()
+            .map(|((year, month), transactions)| {
+                (
+                    format!("{name}-{year:04}-{month:02}.{extension}"),
+                    transactions,
+                )
+            })
+            .collect()
+    }
+
+    /// Return a string containing the CSV representation of the given transactions,
+    /// or the JSON Lines representation if requested.
+    fn as_csv(&self, transactions: Vec<&NormalizedBankData>) -> Result<String> {
+        let mut out = Vec::new();
+        self.write_rows(transactions, &mut out)?;
+        Ok(String::from_utf8(out)?)
+    }
+
+    /// Write the CSV representation of the given transactions,
+    /// or the JSON Lines representation if requested.
+    fn write_rows(
+        &self,
+        transactions: Vec<&NormalizedBankData>,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        match (self.rules.output.aggregate, self.rules.output.format) {
+            (Some(Aggregate::Payee), OutputFormat::Ndjson) => {
+                write_json_lines(payee_totals(transactions), out)
+            }
+            (Some(Aggregate::Payee), _) => self.write_payee_totals_csv(transactions, out),
+            (None, OutputFormat::Standard) => self.write_standard_csv(transactions, out),
+            (None, OutputFormat::Ynab) => self.write_ynab_csv(transactions, out),
+            (None, OutputFormat::Ndjson) => self.write_json_lines(transactions, out),
+        }
+    }
+
+    /// Write the number and total of the given transactions for each payee,
+    /// with the lowest totals (i.e. the largest debits) first.
+    fn write_payee_totals_csv(
+        &self,
+        transactions: Vec<&NormalizedBankData>,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let mut wtr = csv::WriterBuilder::new()
+            .quote_style(self.rules.output.quote_style.as_csv())
+            .from_writer(out);
+        for row in payee_totals(transactions) {
+            wtr.serialize(row)?;
         }
         wtr.flush()?;
-        Ok(String::from_utf8(wtr.into_inner()?)?)
+        Ok(())
+    }
+
+    /// Write one JSON object per line for each of the given transactions,
+    /// with the balance and source if requested.
+    fn write_json_lines(
+        &self,
+        transactions: Vec<&NormalizedBankData>,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let include_balance = self.rules.output.running_balance;
+        let include_source = self.rules.output.include_source;
+        let rows = transactions.into_iter().map(|transaction| JsonRow {
+            transaction,
+            balance: transaction.running_balance.filter(|_| include_balance),
+            source: transaction
+                .source
+                .as_ref()
+                .filter(|_| include_source)
+                .map(|x| x.display().to_string()),
+        });
+        write_json_lines(rows, out)
+    }
+
+    /// Write the YNAB CSV representation of the given transactions.
+    fn write_ynab_csv(
+        &self,
+        transactions: Vec<&NormalizedBankData>,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let date_fmt = self.rules.output.ynab_date_fmt();
+        let mut wtr = csv::WriterBuilder::new()
+            .quote_style(self.rules.output.quote_style.as_csv())
+            .from_writer(out);
+        for transaction in transactions {
+            // Debits are written as a positive outflow and credits as an inflow.
+            let amount = transaction.amount;
+            wtr.serialize(YnabRow {
+                date: transaction.date.format(date_fmt).to_string(),
+                payee: &transaction.payee,
+                memo: transaction.memo.as_deref(),
+                outflow: (amount < Decimal::ZERO).then(|| -amount),
+                inflow: (amount > Decimal::ZERO).then_some(amount),
+            })?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Write the standard CSV representation of the given transactions.
+    fn write_standard_csv(
+        &self,
+        transactions: Vec<&NormalizedBankData>,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        // Headers cannot be derived when adding the balance or source columns,
+        // so write them by hand.
+        let include_balance = self.rules.output.running_balance;
+        let include_source = self.rules.output.include_source;
+        let mut wtr = csv::WriterBuilder::new()
+            .has_headers(!include_balance && !include_source)
+            .quote_style(self.rules.output.quote_style.as_csv())
+            .from_writer(out);
+        if (include_balance || include_source) && !transactions.is_empty() {
+            let extra = [
+                include_balance.then_some(columns::BALANCE),
+                include_source.then_some(columns::SOURCE),
+            ];
+            wtr.write_record(columns::OUTPUT.iter().chain(extra.iter().flatten()))?;
+        }
+        for transaction in transactions {
+            let balance = transaction.running_balance;
+            let source = transaction.source.as_ref().map(|x| x.display().to_string());
+            match (include_balance, include_source) {
+                (false, false) => wtr.serialize(transaction)?,
+                (true, false) => wtr.serialize((transaction, balance))?,
+                (false, true) => wtr.serialize((transaction, source))?,
+                (true, true) => wtr.serialize((transaction, balance, source))?,
+            }
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// A transaction in the column layout that YNAB imports.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct YnabRow<'a> {
+    date: String,
+    payee: &'a str,
+    memo: Option<&'a str>,
+    outflow: Option<Decimal>,
+    inflow: Option<Decimal>,
+}
+
+/// The number and total of the transactions for a payee.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PayeeTotalRow<'a> {
+    payee: &'a str,
+    count: usize,
+    total: Decimal,
+}
+
+/// A transaction as a JSON object, with the optional columns only if requested.
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct JsonRow<'a> {
+    #[serde(flatten)]
+    transaction: &'a NormalizedBankData,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    balance: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+}
+
+/// The number and total of the given transactions for each payee,
+/// with the lowest totals (i.e. the largest debits) first.
+fn payee_totals(transactions: Vec<&NormalizedBankData>) -> Vec<PayeeTotalRow<'_>> {
+    let mut payees: BTreeMap<&str, PayeeTotalRow> = BTreeMap::new();
+    for transaction in transactions {
+        let row = payees
+            .entry(&transaction.payee)
+            .or_insert_with(|| PayeeTotalRow {
+                payee: &transaction.payee,
+                count: 0,
+                total: Decimal::ZERO,
+            });
+        row.count += 1;
+        row.total += transaction.amount;
+    }
+
+    // The payees are already in name order, so ties keep that order.
+    let mut rows: Vec<_> = payees.into_values().collect();
+    rows.sort_by_key(|row| row.total);
+    rows
+}
+
+/// Write each of the given rows as a JSON object on its own line, one at a
+/// time so that no array of every row is built.
+fn write_json_lines(
+    rows: impl IntoIterator<Item = impl Serialize>,
+    out: &mut dyn Write,
+) -> Result<()> {
+    for row in rows {
+        serde_json::to_writer(&mut *out, &row)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// The memo given to transactions that are flagged as reversed.
+const REVERSED_MEMO: &str = "Reversed";
+
+/// The number of unparseable dates in one file after which the date_fmt is
+/// reported as probably wrong instead of reporting the first bad date.
+const DATE_FORMAT_MISMATCH_THRESHOLD: usize = 3;
+
+/// Find the pairs of transactions (by index) that cancel each other out.
+///
+/// Two transactions cancel if they have the same payee, non-zero amounts of
+/// equal magnitude and opposite sign, and dates at most window_days apart.
+/// Transactions are considered in chronological order (keeping the original
+/// order for the same date), and each is paired with the earliest unpaired
+/// transaction that it cancels, if any (i.e. first-in-first-out).
+fn reversal_pairs(transactions: &[NormalizedBankData], window_days: u32) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..transactions.len()).collect();
+    order.sort_by_key(|&index| transactions[index].date);
+
+    let mut unpaired: Vec<usize> = vec![];
+    let mut pairs = vec![];
+    for index in order {
+        let current = &transactions[index];
+        let reversed = unpaired.iter().position(|&earlier| {
+            let earlier = &transactions[earlier];
+            !current.amount.is_zero()
+                && earlier.amount == -current.amount
+                && earlier.payee == current.payee
+                && (current.date - earlier.date).num_days() <= i64::from(window_days)
+        });
+        match reversed {
+            Some(position) => pairs.push((unpaired.remove(position), index)),
+            None => unpaired.push(index),
+        }
+    }
+    pairs
+}
+
+/// The number of transactions skipped for each reason.
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct SkipCounts {
+    /// Transactions for no money.
+    pub zero_amount: usize,
+    /// Transactions without an amount, if counted separately.
+    pub blank_amount: usize,
+    /// Transactions before the start date.
+    pub before_start: usize,
+    /// Transactions after the end date.
+    pub after_end: usize,
+    /// Transactions cancelled out by another transaction.
+    pub reversed: usize,
+    /// Transactions matching an ignore rule.
+    pub ignored: usize,
+}
+
+impl SkipCounts {
+    /// Count a transaction skipped for the given reason.
+    fn add(&mut self, reason: SkipReason) {
+        match reason {
+            SkipReason::ZeroAmount => self.zero_amount += 1,
+            SkipReason::BlankAmount => self.blank_amount += 1,
+            SkipReason::BeforeStart => self.before_start += 1,
+            SkipReason::AfterEnd => self.after_end += 1,
+            SkipReason::Ignored => self.ignored += 1,
+        }
+    }
+
+    /// The total number of transactions skipped.
+    pub fn total(&self) -> usize {
+        self.zero_amount
+            + self.blank_amount
+            + self.before_start
+            + self.after_end
+            + self.reversed
+            + self.ignored
+    }
+}
+
+impl fmt::Display for SkipCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} with a zero amount, {} before the start date, {} after the end date",
+            self.zero_amount, self.before_start, self.after_end
+        )?;
+        if self.blank_amount > 0 {
+            write!(f, ", {} with a blank amount", self.blank_amount)?;
+        }
+        if self.reversed > 0 {
+            write!(f, ", {} reversed", self.reversed)?;
+        }
+        if self.ignored > 0 {
+            write!(f, ", {} ignored", self.ignored)?;
+        }
+        Ok(())
     }
 }
 
@@ -70,23 +623,59 @@ impl<'a> TransactionProcessor<'a> {
 pub fn process_csv_files<'a>(
     paths: &Vec<impl AsRef<Path>>,
     rules: &'a RuleFileData,
-) -> Result<HashMap<String, TransactionProcessor<'a>>> {
+) -> Result<HashMap<String, TransactionProcessor<'a>>, TidymoneyError> {
+    process_csv_files_up_to(paths, rules, None, None)
+}
+
+/// Like process_csv_files, but also add the time spent reading the files and
+/// applying the rules to the given stage times.
+pub fn process_csv_files_timed<'a>(
+    paths: &Vec<impl AsRef<Path>>,
+    rules: &'a RuleFileData,
+    times: &mut StageTimes,
+) -> Result<HashMap<String, TransactionProcessor<'a>>, TidymoneyError> {
+    process_csv_files_up_to(paths, rules, None, Some(times))
+}
+
+/// Like process_csv_files, but only process the first few data rows of each
+/// file, for a quick look at how a large file will be normalized.
+pub fn preview_csv_files<'a>(
+    paths: &Vec<impl AsRef<Path>>,
+    rules: &'a RuleFileData,
+    limit: usize,
+) -> Result<HashMap<String, TransactionProcessor<'a>>, TidymoneyError> {
+    process_csv_files_up_to(paths, rules, Some(limit), None)
+}
+
+/// Process the CSV files, stopping after the given number of data rows in each file.
+/// The time spent in each stage is only measured if stage times are given.
+fn process_csv_files_up_to<'a>(
+    paths: &Vec<impl AsRef<Path>>,
+    rules: &'a RuleFileData,
+    limit: Option<usize>,
+    mut times: Option<&mut StageTimes>,
+) -> Result<HashMap<String, TransactionProcessor<'a>>, TidymoneyError> {
     let mut processors = HashMap::new();
+    let limit = limit.unwrap_or(usize::MAX);
 
     // Iterate over each CSV file.
     for path in paths {
-        // Create the reader for this CSV file.
-        let mut reader = csv::Reader::from_path(path.as_ref())?;
+        let started = times.is_some().then(Instant::now);
 
-        // For this CSV, obtain the mapping rules for the account it represents.
-        let mapping = rules
-            .get_csv_mapping_rules(reader.headers()?)
-            .ok_or_else(|| {
-                anyhow!(
-                    "No rules are defined for the account corresponding to file {:#?}",
-                    path.as_ref()
-                )
-            })?;
+        // Create the reader for this CSV file and obtain the mapping rules
+        // for the account it represents.
+        let CsvFile {
+            mut reader,
+            headers,
+            mapping,
+        } = open_csv_file(path, rules)?;
+
+        // A completely empty file has no transactions to contribute.
+        if mapping.is_none() && headers.is_empty() {
+            continue;
+        }
+        let mapping = mapping
+            .ok_or_else(|| TidymoneyError::NoMatchingAccount(path.as_ref().to_path_buf()))?;
 
         // If no processor has been created for this account type, create it now.
         if !processors.contains_key(&mapping.label) {
@@ -100,40 +689,2447 @@ pub fn process_csv_files<'a>(
         // .unwrap() is OK to use since we verified the label exists above.
         let processor = processors.get_mut(&mapping.label).unwrap();
 
-        // For each row in this CSV, process and store the transactions.
-        for row in reader.deserialize() {
-            processor.process(row?)?;
-        }
+        // Process and store the transactions from every row in this CSV.
+        let rows = if mapping.has_headers {
+            reader
+                .deserialize()
+                .take(limit)
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            reader
+                .records()
+                .take(limit)
+                .map(|record| record.map(|r| named_values(&headers, &r)))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let rows = rows.into_iter().map(without_blank_columns).collect();
+        record_stage(&mut times, STAGE_READING, started);
+
+        let started = times.is_some().then(Instant::now);
+        processor.process_file(rows, Some(path.as_ref()))?;
+        record_stage(&mut times, STAGE_RULES, started);
+    }
+
+    // Some updates depend on every transaction for an account.
+    let started = times.is_some().then(Instant::now);
+    for processor in processors.values_mut() {
+        processor.carry_forward_categories();
+        processor.compute_running_balances();
     }
+    record_stage(&mut times, STAGE_RULES, started);
 
     // Return the processors for the given CSV files.
     Ok(processors)
 }
 
+/// The stage in which the CSV files are read and parsed.
+const STAGE_READING: &str = "reading";
+
+/// The stage in which the rules are applied to the transactions.
+const STAGE_RULES: &str = "rules";
+
+/// The total time spent in each stage of a run, for profiling.
+#[derive(Debug, Default)]
+pub struct StageTimes {
+    /// Each stage and the time spent in it, in the order they were first recorded.
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl StageTimes {
+    /// Add time spent in the given stage.
+    pub fn add(&mut self, stage: &'static str, elapsed: Duration) {
+        match self.stages.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, total)) => *total += elapsed,
+            None => self.stages.push((stage, elapsed)),
+        }
+    }
+
+    /// Run the given function, adding the time it takes to the given stage.
+    pub fn time<T>(&mut self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.add(stage, started.elapsed());
+        result
+    }
+
+    /// Each stage and the time spent in it, in the order they were first recorded.
+    pub fn stages(&self) -> &[(&'static str, Duration)] {
+        &self.stages
+    }
+}
+
+impl fmt::Display for StageTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (stage, elapsed) in &self.stages {
+            writeln!(f, "{stage}: {elapsed:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Add the time since a stage started to the stage times, if they are kept.
+fn record_stage(
+    times: &mut Option<&mut StageTimes>,
+    stage: &'static str,
+    started: Option<Instant>,
+) {
+    if let (Some(times), Some(started)) = (times, started) {
+        times.add(stage, started.elapsed());
+    }
+}
+
+/// Read only the headers of a CSV file and return them along with the label
+/// of the account they match, if any. No transactions are processed.
+pub fn identify_csv_file(
+    path: impl AsRef<Path>,
+    rules: &RuleFileData,
+) -> Result<(Vec<String>, Option<String>), TidymoneyError> {
+    let file = open_csv_file(path, rules)?;
+    let label = file.mapping.map(|mapping| mapping.label.to_owned());
+    Ok((file.headers.iter().map(|x| x.to_owned()).collect(), label))
+}
+
+/// A CSV file opened for reading.
+struct CsvFile<'a> {
+    /// The reader for the rows of the file.
+    reader: csv::Reader<Box<dyn Read>>,
+    /// The name of each column of the file.
+    headers: csv::StringRecord,
+    /// The mapping rules for the account the file matches, if any.
+    mapping: Option<&'a MappingRulesCsv>,
+}
+
+/// Create the reader for a CSV file, decompressing it if needed, and find the
+/// account it matches. Files without a header row are identified by name and
+/// use the column names given for their account.
+fn open_csv_file(
+    path: impl AsRef<Path>,
+    rules: &RuleFileData,
+) -> Result<CsvFile<'_>, TidymoneyError> {
+    if let Some(mapping) = rules.get_headerless_mapping_rules(&path) {
+        return Ok(CsvFile {
+            reader: csv::ReaderBuilder::new()
+                .has_headers(false)
+                .from_reader(open_maybe_gzipped(path)?),
+            headers: csv::StringRecord::from(mapping.column_names().to_vec()),
+            mapping: Some(mapping),
+        });
+    }
+    let mut reader = csv::Reader::from_reader(open_maybe_gzipped(path)?);
+    let headers = reader.headers()?.clone();
+    let mapping = rules.get_csv_mapping_rules(&headers);
+    Ok(CsvFile {
+        reader,
+        headers,
+        mapping,
+    })
+}
+
+/// Pair each value of a row with the name of its column by position.
+fn named_values(headers: &csv::StringRecord, row: &csv::StringRecord) -> HashMap<String, String> {
+    headers
+        .iter()
+        .zip(row.iter())
+        .map(|(column, value)| (column.to_owned(), value.to_owned()))
+        .collect()
+}
+
+/// Which accounts to process in a run.
+#[derive(Debug, Default)]
+pub struct AccountSelection {
+    /// If not empty, only process these accounts.
+    pub only: Vec<String>,
+    /// Do not process these accounts.
+    pub exclude: Vec<String>,
+}
+
+impl AccountSelection {
+    /// Ensure every selected account is defined in the rules.
+    pub fn validate(&self, rules: &RuleFileData) -> Result<(), TidymoneyError> {
+        match self
+            .only
+            .iter()
+            .chain(&self.exclude)
+            .find(|x| !rules.has_account(x))
+        {
+            Some(label) => Err(TidymoneyError::UnknownAccount(label.to_owned())),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether or not the account with the given label is to be processed.
+    pub fn includes(&self, label: &str) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|x| x == label))
+            && !self.exclude.iter().any(|x| x == label)
+    }
+}
+
+/// Keep only the CSV files for the selected accounts. Files that do not match
+/// any account are kept so that they are reported when processed.
+pub fn select_csv_files<P: AsRef<Path>>(
+    paths: Vec<P>,
+    rules: &RuleFileData,
+    selection: &AccountSelection,
+) -> Result<Vec<P>, TidymoneyError> {
+    let mut selected = Vec::new();
+    for path in paths {
+        let (_, label) = identify_csv_file(&path, rules)?;
+        if label.is_none_or(|label| selection.includes(&label)) {
+            selected.push(path);
+        }
+    }
+    Ok(selected)
+}
+
+/// Split the CSV files into those that match an account and those that do not.
+pub fn split_unmatched_csv_files<P: AsRef<Path>>(
+    paths: Vec<P>,
+    rules: &RuleFileData,
+) -> Result<(Vec<P>, Vec<P>), TidymoneyError> {
+    let mut matched = Vec::new();
+    let mut unmatched = Vec::new();
+    for path in paths {
+        match identify_csv_file(&path, rules)? {
+            (_, Some(_)) => matched.push(path),
+            (_, None) => unmatched.push(path),
+        }
+    }
+    Ok((matched, unmatched))
+}
+
+/// Split the CSV files into those with transactions and those that are empty.
+/// A file is empty if it has no data rows, even if it has a header row.
+pub fn split_empty_csv_files<P: AsRef<Path>>(
+    paths: Vec<P>,
+    rules: &RuleFileData,
+) -> Result<(Vec<P>, Vec<P>), TidymoneyError> {
+    let mut nonempty = Vec::new();
+    let mut empty = Vec::new();
+    for path in paths {
+        let mut file = open_csv_file(&path, rules)?;
+        match file.reader.records().next().transpose()? {
+            Some(_) => nonempty.push(path),
+            None => empty.push(path),
+        }
+    }
+    Ok((nonempty, empty))
+}
+
+/// Remove the values of columns without a name, such as those created by a
+/// trailing comma at the end of each line.
+fn without_blank_columns(mut row: HashMap<String, String>) -> HashMap<String, String> {
+    row.retain(|column, _| !column.trim().is_empty());
+    row
+}
+
+/// Derive the Amount of each row from the change in the Balance column since
+/// the previous row. Rows are put into chronological order first (files listing
+/// the newest transactions first are reversed), and the first row is dropped
+/// because there is no previous balance from which to compute its amount.
+fn amounts_from_balance(
+    mut rows: Vec<HashMap<String, String>>,
+    date_fmt: &str,
+    label: &str,
+) -> Result<Vec<HashMap<String, String>>, TidymoneyError> {
+    let get = |row: &HashMap<String, String>, column: &str| {
+        row.get(column)
+            .cloned()
+            .ok_or_else(|| TidymoneyError::MissingColumn {
+                account: label.to_owned(),
+                column: column.to_owned(),
+            })
+    };
+    // Unparseable dates are left for the caller to count and report.
+    let date = |row: &HashMap<String, String>| {
+        NaiveDate::parse_from_str(row.get(columns::DATE)?, date_fmt).ok()
+    };
+    let balance = |row: &HashMap<String, String>| {
+        let value = get(row, columns::BALANCE)?;
+        parse_amount(value.trim()).ok_or_else(|| TidymoneyError::InvalidValue {
+            account: label.to_owned(),
+            column: columns::BALANCE.to_owned(),
+            value,
+        })
+    };
+
+    // Ensure the rows are oldest first.
+    if let (Some(first), Some(last)) = (rows.first(), rows.last()) {
+        if let (Some(first), Some(last)) = (date(first), date(last)) {
+            if first > last {
+                rows.reverse();
+            }
+        }
+    }
+
+    let mut derived = Vec::with_capacity(rows.len());
+    let mut previous = None;
+    for mut row in rows {
+        let current = balance(&row)?;
+        if let Some(previous) = previous {
+            let amount: Decimal = current - previous;
+            row.insert(columns::AMOUNT.to_owned(), amount.to_string());
+            derived.push(row);
+        }
+        previous = Some(current);
+    }
+    Ok(derived)
+}
+
+/// Overrides of the dates used to filter transactions.
+#[derive(Debug, Default)]
+pub struct DateOverrides {
+    /// Use this start date instead of the one stored in the timestamps.
+    pub since: Option<NaiveDate>,
+    /// Use this end date instead of the current date.
+    pub until: Option<NaiveDate>,
+    /// If given, only apply the overrides to this account.
+    pub account: Option<String>,
+}
+
+impl DateOverrides {
+    /// Ensure the overrides describe a valid window.
+    pub fn validate(&self) -> Result<()> {
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since > until {
+                return Err(anyhow!(
+                    "The start date {since} is after the end date {until}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether or not the overrides apply to the given account.
+    fn applies_to(&self, label: &str) -> bool {
+        self.account.as_ref().is_none_or(|a| a == label)
+    }
+
+    /// Determine the start date for an account.
+    fn start_date(&self, label: &str, stamps: &TimestampKeeper) -> NaiveDate {
+        match self.since {
+            Some(since) if self.applies_to(label) => since,
+            _ => stamps.get_date(label),
+        }
+    }
+
+    /// Determine the end date for an account.
+    fn end_date(&self, label: &str, now: &NaiveDate) -> NaiveDate {
+        match self.until {
+            Some(until) if self.applies_to(label) => until,
+            _ => *now,
+        }
+    }
+}
+
 /// Account for the current timestamp in all transactions.
 pub fn account_for_dates_in_transactions(
     now: &NaiveDate,
+    overrides: &DateOverrides,
     all_transactions: &mut HashMap<String, TransactionProcessor>,
     stamps: &mut TimestampKeeper,
-) {
+) -> HashMap<String, SkipCounts> {
+    let mut skipped = HashMap::new();
     for (label, transactions) in all_transactions.iter_mut() {
-        let start = stamps.get_date(label);
-        transactions.drop_uneeded(&start, now);
-        stamps.update_date(label, now);
+        // The timestamp never advances past the end of the window so that
+        // transactions after it will be picked up by the next run.
+        let start = overrides.start_date(label, stamps);
+        let end = overrides.end_date(label, now);
+        let mut counts = transactions.drop_uneeded(&start, &end);
+
+        // Only transactions that will be written are considered for reversal
+        // so that a refund is never dropped for a charge written previously.
+        counts.reversed = transactions.handle_reversals();
+        skipped.insert(label.to_owned(), counts);
+        stamps.update_date(label, &end);
     }
+
+    // Likewise, only transactions that will be written count as occurrences.
+    count_occurrences(all_transactions);
+    skipped
+}
+
+/// Apply the rules that depend on which occurrence of its payee each transaction
+/// is, counting among the transactions that remain for each account. This is
+/// done by account_for_dates_in_transactions, so is only needed when the
+/// transactions are not filtered by date.
+pub fn count_occurrences(all_transactions: &mut HashMap<String, TransactionProcessor>) {
+    for transactions in all_transactions.values_mut() {
+        transactions.apply_occurrence_rules();
+    }
+}
+
+/// A transaction dated suspiciously far from the current date.
+#[derive(Debug, PartialEq)]
+pub struct SkewedDate {
+    /// The label of the account the transaction is from.
+    pub label: String,
+    /// The date of the transaction.
+    pub date: NaiveDate,
+    /// The payee as originally given in the raw data.
+    pub orig_payee: String,
+}
+
+impl SkewedDate {
+    /// The error to give for this transaction.
+    pub fn to_error(&self, years: u32) -> TidymoneyError {
+        TidymoneyError::SkewedDate {
+            account: self.label.to_owned(),
+            date: self.date.to_string(),
+            years,
+        }
+    }
+}
+
+impl fmt::Display for SkewedDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} {}", self.label, self.date, self.orig_payee)
+    }
+}
+
+/// Find the transactions dated more than the given number of years before or
+/// after the current date, which usually means that a date_fmt is wrong.
+pub fn find_skewed_dates(
+    now: &NaiveDate,
+    max_years: u32,
+    all_transactions: &HashMap<String, TransactionProcessor>,
+) -> Vec<SkewedDate> {
+    let skew = Months::new(max_years.saturating_mul(12));
+    let earliest = now.checked_sub_months(skew).unwrap_or(NaiveDate::MIN);
+    let latest = now.checked_add_months(skew).unwrap_or(NaiveDate::MAX);
+    let mut skewed: Vec<_> = all_transactions
+        .values()
+        .flat_map(|processor| processor.transactions())
+        .filter(|transaction| transaction.date < earliest || transaction.date > latest)
+        .map(|transaction| SkewedDate {
+            label: transaction.label.to_owned(),
+            date: transaction.date,
+            orig_payee: transaction.orig_payee.to_owned(),
+        })
+        .collect();
+    skewed.sort_by(|x1, x2| (&x1.label, x1.date).cmp(&(&x2.label, x2.date)));
+    skewed
+}
+
+/// The normalized transactions and updated timestamps from an in-memory run.
+#[derive(Debug, PartialEq)]
+pub struct InMemoryOutput {
+    /// The normalized CSV data for each account, keyed by account label.
+    pub transactions: HashMap<String, String>,
+    /// The updated timestamps as a JSON string.
+    pub stamps: String,
+}
+
+/// Run the whole pipeline with rules and timestamps given as strings, returning
+/// the results instead of writing them or moving the raw CSV files.
+pub fn process_in_memory(
+    rule_data: impl AsRef<str>,
+    stamps_data: impl AsRef<str>,
+    paths: &Vec<impl AsRef<Path>>,
+    now: &NaiveDate,
+    overrides: &DateOverrides,
+) -> Result<InMemoryOutput, TidymoneyError> {
+    let rules = RuleFileData::new(rule_data)?;
+    let mut stamps = TimestampKeeper::new(stamps_data)
+        .map_err(|e| TidymoneyError::InvalidTimestamps(e.to_string()))?;
+    let mut results = process_csv_files(paths, &rules)?;
+    let years = rules.checks.max_date_skew_years;
+    if rules.checks.date_skew == CheckAction::Error {
+        if let Some(skewed) = find_skewed_dates(now, years, &results).first() {
+            return Err(skewed.to_error(years));
+        }
+    }
+    account_for_dates_in_transactions(now, overrides, &mut results, &mut stamps);
+    let transactions = results
+        .iter()
+        .map(|(label, processor)| Ok((label.to_owned(), processor.get_transactions_as_csv()?)))
+        .collect::<Result<_>>()
+        .map_err(|e| TidymoneyError::Output(e.to_string()))?;
+    let stamps = stamps
+        .get_updated_stamps()
+        .map_err(|e| TidymoneyError::Output(e.to_string()))?;
+    Ok(InMemoryOutput {
+        transactions,
+        stamps,
+    })
 }
 
 /// Write all transactions to the appropriate file.
+///
+/// Returns any files that were already in the output directories from an
+/// earlier run on the same date. If fresh is true these are removed first.
 pub fn write_transactions_to_file(
     now: impl AsRef<str>,
-    storage: impl AsRef<Path>,
+    paths: &AuxillaryPaths,
     all_transactions: &HashMap<String, TransactionProcessor>,
-) -> Result<()> {
-    // Write all transactions to file.
-    let base = ensure_storage_path(storage, now, true)?;
-    for (label, transactions) in all_transactions.iter() {
-        let location = base.join(label.to_owned() + ".csv");
-        fs::write(location, transactions.get_transactions_as_csv()?)?;
+    fresh: bool,
+) -> Result<Vec<PathBuf>, TidymoneyError> {
+    // Write all transactions to file in storage and then each mirror.
+    let mut existing = Vec::new();
+    for destination in paths.destinations() {
+        let base = ensure_storage_path(destination, &paths.output_dir, now.as_ref())?;
+        let found = directory_entries(&base)?;
+        if fresh {
+            for entry in &found {
+                remove_entry(entry)?;
+            }
+        }
+        existing.extend(found);
+        for transactions in all_transactions.values() {
+            transactions.write_transaction_files(&base).map_err(|e| {
+                match e.downcast::<std::io::Error>() {
+                    Ok(e) => TidymoneyError::Io(e),
+                    Err(e) => TidymoneyError::Output(e.to_string()),
+                }
+            })?;
+        }
+    }
+    Ok(existing)
+}
+
+/// The rows that would change in an output file if the transactions were written.
+#[derive(Debug, PartialEq)]
+pub struct OutputDiff {
+    /// The output file that would be written.
+    pub path: PathBuf,
+    /// The rows that are not in the existing file.
+    pub added: Vec<String>,
+    /// The rows of the existing file that would no longer be present.
+    pub removed: Vec<String>,
+}
+
+impl OutputDiff {
+    /// Whether or not the file would be unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Compare the files that would be written to storage with those already written
+/// there for the same date, without writing anything. The diffs are sorted by path.
+pub fn diff_transaction_files(
+    now: impl AsRef<str>,
+    paths: &AuxillaryPaths,
+    all_transactions: &HashMap<String, TransactionProcessor>,
+) -> Result<Vec<OutputDiff>, TidymoneyError> {
+    let base = paths.output_path(now);
+    let mut diffs = Vec::new();
+    for transactions in all_transactions.values() {
+        let files = transactions
+            .get_transaction_files()
+            .map_err(|e| TidymoneyError::Output(e.to_string()))?;
+        for (name, contents) in files {
+            let path = base.join(name);
+            let existing = if path.is_file() {
+                fs::read_to_string(&path)?
+            } else {
+                String::new()
+            };
+            let (added, removed) = line_diff(&existing, &contents);
+            diffs.push(OutputDiff {
+                path,
+                added,
+                removed,
+            });
+        }
+    }
+    diffs.sort_by(|x1, x2| x1.path.cmp(&x2.path));
+    Ok(diffs)
+}
+
+/// Find the lines added to and removed from the old text to make the new text,
+/// ignoring the order of the lines. Repeated lines are counted individually.
+fn line_diff(old: &str, new: &str) -> (Vec<String>, Vec<String>) {
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for line in old.lines() {
+        *counts.entry(line).or_default() += 1;
+    }
+    let mut added = Vec::new();
+    for line in new.lines() {
+        let count = counts.entry(line).or_default();
+        if *count > 0 {
+            *count -= 1;
+        } else {
+            added.push(line.to_owned());
+        }
+    }
+    let mut removed = Vec::new();
+    for line in old.lines() {
+        if let Some(count) = counts.get_mut(line).filter(|count| **count > 0) {
+            *count -= 1;
+            removed.push(line.to_owned());
+        }
+    }
+    (added, removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Write;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use rust_decimal_macros::dec;
+
+    use crate::as_hashmap;
+
+    /// Create rules for an account called "testing" from the given mapping
+    /// options (which must include identify) and additional sections
+    /// (which must include payees).
+    fn rules_with(mapping: &str, sections: &str) -> RuleFileData {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        RuleFileData::new(format!(
+            indoc! { r#"
+            [[mappings.csv]]
+            label = "testing"
+            {}
+
+            [paths]
+            storage = {:#?}
+
+            {}
+            "# },
+            mapping,
+            temp.path(),
+            sections
+        ))
+        .unwrap()
+    }
+
+    /// Process the given rows with the given rules and return them as CSV.
+    fn process_rows(rules: &RuleFileData, headers: Vec<&str>, rows: Vec<Vec<&str>>) -> String {
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(headers.clone()))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, rules);
+        for row in rows {
+            processor
+                .process(as_hashmap(headers.iter().copied().zip(row).collect()))
+                .unwrap();
+        }
+        processor.carry_forward_categories();
+        processor.compute_running_balances();
+        processor.apply_occurrence_rules();
+        processor.get_transactions_as_csv().unwrap()
+    }
+
+    /// Process some shuffled transactions and return them as CSV.
+    fn shuffled_csv(rules: &RuleFileData) -> String {
+        process_rows(
+            rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![
+                vec!["2024-03-02", "B", "-1.00"],
+                vec!["2024-01-05", "A", "-1.00"],
+                vec!["2024-03-02", "C", "-1.00"],
+                vec!["2024-02-11", "D", "-1.00"],
+            ],
+        )
+    }
+
+    #[rstest]
+    #[case("", vec!["2024-03-02,B", "2024-01-05,A", "2024-03-02,C", "2024-02-11,D"])]
+    #[case(
+        "sort_order = \"ascending\"",
+        vec!["2024-01-05,A", "2024-02-11,D", "2024-03-02,B", "2024-03-02,C"],
+    )]
+    #[case(
+        "sort_order = \"descending\"",
+        vec!["2024-03-02,B", "2024-03-02,C", "2024-02-11,D", "2024-01-05,A"],
+    )]
+    fn test_sort_order(#[case] output: &str, #[case] expected: Vec<&str>) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            &format!("[payees]\nApple = \"APPLE\"\n[output]\n{output}"),
+        );
+        let expected = expected.into_iter().fold(
+            "Date,Payee,Category,Memo,Amount,Check#\n".to_string(),
+            |acc, row| acc + row + ",,,-1.00,\n",
+        );
+        assert_eq!(shuffled_csv(&rules), expected);
+    }
+
+    #[rstest]
+    #[case("", vec!["2024-03-02,C,,,-5.00", "2024-03-02,A,,,-1.00", "2024-03-02,B,,,-10.00", "2024-01-05,D,,,-2.00"])]
+    #[case("tie_break = \"input\"", vec!["2024-03-02,C,,,-5.00", "2024-03-02,A,,,-1.00", "2024-03-02,B,,,-10.00", "2024-01-05,D,,,-2.00"])]
+    #[case("tie_break = \"amount\"", vec!["2024-03-02,B,,,-10.00", "2024-03-02,C,,,-5.00", "2024-03-02,A,,,-1.00", "2024-01-05,D,,,-2.00"])]
+    #[case("tie_break = \"payee\"", vec!["2024-03-02,A,,,-1.00", "2024-03-02,B,,,-10.00", "2024-03-02,C,,,-5.00", "2024-01-05,D,,,-2.00"])]
+    fn test_tie_break(#[case] output: &str, #[case] expected: Vec<&str>) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            &format!(
+                "[payees]\nApple = \"APPLE\"\n[output]\nsort_order = \"descending\"\n{output}"
+            ),
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![
+                vec!["2024-01-05", "D", "-2.00"],
+                vec!["2024-03-02", "C", "-5.00"],
+                vec!["2024-03-02", "A", "-1.00"],
+                vec!["2024-03-02", "B", "-10.00"],
+            ],
+        );
+        let expected = expected.into_iter().fold(
+            "Date,Payee,Category,Memo,Amount,Check#\n".to_string(),
+            |acc, row| acc + row + ",\n",
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case("", 0, vec!["2024-01-05,ACE,,,-20.00", "2024-01-07,ACE,,,20.00", "2024-01-10,BEST,,,-15.00", "2024-01-20,BEST,,,15.00"])]
+    #[case(
+        "[output.reversals]\nwindow_days = 3",
+        0,
+        vec!["2024-01-05,ACE,,Reversed,-20.00", "2024-01-07,ACE,,Reversed,20.00", "2024-01-10,BEST,,,-15.00", "2024-01-20,BEST,,,15.00"],
+    )]
+    #[case(
+        "[output.reversals]\nwindow_days = 3\nremove = true",
+        2,
+        vec!["2024-01-10,BEST,,,-15.00", "2024-01-20,BEST,,,15.00"],
+    )]
+    #[case(
+        "[output.reversals]\nwindow_days = 10",
+        0,
+        vec!["2024-01-05,ACE,,Reversed,-20.00", "2024-01-07,ACE,,Reversed,20.00", "2024-01-10,BEST,,Reversed,-15.00", "2024-01-20,BEST,,Reversed,15.00"],
+    )]
+    fn test_reversals(#[case] output: &str, #[case] removed: usize, #[case] expected: Vec<&str>) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            &format!("[payees]\nApple = \"APPLE\"\n{output}"),
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        for (date, payee, amount) in [
+            ("2024-01-05", "ACE", "-20.00"),
+            ("2024-01-07", "ACE", "20.00"),
+            ("2024-01-10", "BEST", "-15.00"),
+            ("2024-01-20", "BEST", "15.00"),
+        ] {
+            processor
+                .process(as_hashmap(vec![
+                    ("Date", date),
+                    ("Payee", payee),
+                    ("Amount", amount),
+                ]))
+                .unwrap();
+        }
+        assert_eq!(processor.handle_reversals(), removed);
+        let expected = expected.into_iter().fold(
+            "Date,Payee,Category,Memo,Amount,Check#\n".to_string(),
+            |acc, row| acc + row + ",\n",
+        );
+        assert_eq!(processor.get_transactions_as_csv().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(
+        "format = \"ynab\"",
+        indoc! {"
+        Date,Payee,Memo,Outflow,Inflow
+        01/05/2024,Ace,Hardware,15.43,
+        01/11/2024,Salary,,,550.00
+        "}
+    )]
+    #[case(
+        "format = \"ynab\"\nynab_date_fmt = \"%Y-%m-%d\"",
+        indoc! {"
+        Date,Payee,Memo,Outflow,Inflow
+        2024-01-05,Ace,Hardware,15.43,
+        2024-01-11,Salary,,,550.00
+        "}
+    )]
+    fn test_ynab_format(#[case] output: &str, #[case] expected: &str) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\", \"Memo\"]",
+            &format!("[payees]\nAce = \"ACE\"\nSalary = \"DIRDEP\"\n[output]\n{output}"),
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount", "Memo"],
+            vec![
+                vec!["2024-01-05", "ACE HARDWARE", "-15.43", "Hardware"],
+                vec!["2024-01-11", "ABC INC DIRDEP", "550.00", ""],
+            ],
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case(
+        "",
+        vec![
+            r#"{"Date":"2024-01-05","Payee":"Ace","Category":null,"Memo":"Hardware","Amount":"-15.43","Check#":null}"#,
+            r#"{"Date":"2024-01-11","Payee":"Salary","Category":null,"Memo":"","Amount":"550.00","Check#":null}"#,
+        ]
+    )]
+    #[case(
+        "running_balance = true",
+        vec![
+            r#"{"Date":"2024-01-05","Payee":"Ace","Category":null,"Memo":"Hardware","Amount":"-15.43","Check#":null,"Balance":"-15.43"}"#,
+            r#"{"Date":"2024-01-11","Payee":"Salary","Category":null,"Memo":"","Amount":"550.00","Check#":null,"Balance":"534.57"}"#,
+        ]
+    )]
+    #[case(
+        "aggregate = \"payee\"",
+        vec![
+            r#"{"Payee":"Ace","Count":1,"Total":"-15.43"}"#,
+            r#"{"Payee":"Salary","Count":1,"Total":"550.00"}"#,
+        ]
+    )]
+    fn test_ndjson_format(#[case] output: &str, #[case] expected: Vec<&str>) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\", \"Memo\"]",
+            &format!("[payees]\nAce = \"ACE\"\nSalary = \"DIRDEP\"\n[output]\nformat = \"ndjson\"\n{output}"),
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount", "Memo"],
+            vec![
+                vec!["2024-01-05", "ACE HARDWARE", "-15.43", "Hardware"],
+                vec!["2024-01-11", "ABC INC DIRDEP", "550.00", ""],
+            ],
+        );
+
+        // Each line is an independent JSON object.
+        let lines: Vec<_> = result.lines().collect();
+        assert_eq!(lines, expected);
+        for line in lines {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert!(value.is_object());
+        }
+    }
+
+    #[test]
+    fn test_ndjson_file_names() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"\n[output]\nformat = \"ndjson\"\nsplit_by_month = true",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        for date in ["2024-01-05", "2024-02-05"] {
+            processor
+                .process(as_hashmap(vec![
+                    ("Date", date),
+                    ("Payee", "ACE"),
+                    ("Amount", "-1.00"),
+                ]))
+                .unwrap();
+        }
+        let files = processor.get_transaction_files().unwrap();
+        let names: Vec<_> = files.iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            vec!["testing-2024-01.ndjson", "testing-2024-02.ndjson"]
+        );
+
+        // Writing the files row by row gives the same contents.
+        let temp = tempdir::TempDir::new("test").unwrap();
+        processor.write_transaction_files(temp.path()).unwrap();
+        for (name, contents) in files {
+            assert_eq!(
+                fs::read_to_string(temp.path().join(name)).unwrap(),
+                contents
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(
+        "",
+        indoc! {"
+        Date,Payee,Category,Memo,Amount,Check#
+        2024-01-05,\"Ace, Inc\",,Hardware,-15.43,
+        "}
+    )]
+    #[case(
+        "quote_style = \"always\"",
+        indoc! {r#"
+        "Date","Payee","Category","Memo","Amount","Check#"
+        "2024-01-05","Ace, Inc","","Hardware","-15.43",""
+        "#}
+    )]
+    #[case(
+        "quote_style = \"always\"\nformat = \"ynab\"",
+        indoc! {r#"
+        "Date","Payee","Memo","Outflow","Inflow"
+        "01/05/2024","Ace, Inc","Hardware","15.43",""
+        "#}
+    )]
+    fn test_quote_style(#[case] output: &str, #[case] expected: &str) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\", \"Memo\"]",
+            &format!("[payees]\n\"Ace, Inc\" = \"ACE\"\n[output]\n{output}"),
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount", "Memo"],
+            vec![vec!["2024-01-05", "ACE HARDWARE", "-15.43", "Hardware"]],
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_aggregate_by_payee() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"\nSalary = \"DIRDEP\"\n[output]\naggregate = \"payee\"",
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![
+                vec!["2024-01-05", "ACE HARDWARE", "-15.43"],
+                vec!["2024-01-07", "Wendy's", "-12.54"],
+                vec!["2024-01-11", "ABC INC DIRDEP", "550.00"],
+                vec!["2024-01-12", "ACE HARDWARE", "-4.57"],
+                vec!["2024-01-14", "Subway", "-12.54"],
+            ],
+        );
+        let expected = indoc! {"
+        Payee,Count,Total
+        Ace,2,-20.00
+        Subway,1,-12.54
+        Wendy's,1,-12.54
+        Salary,1,550.00
+        "};
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case("", "", ["-12.54", "-15.43", "534.57", "530.00"])]
+    #[case("starting_balance = 100.00", "", ["87.46", "84.57", "634.57", "630.00"])]
+    #[case("", "1534.57", ["987.46", "984.57", "1534.57", "1530.00"])]
+    #[case("starting_balance = 0", "1534.57", ["-12.54", "-15.43", "534.57", "530.00"])]
+    fn test_running_balance(
+        #[case] option: &str,
+        #[case] reported: &str,
+        #[case] balances: [&str; 4],
+    ) {
+        let rules = rules_with(
+            &format!("identify = [\"Date\", \"Payee\", \"Amount\", \"Balance\"]\n{option}"),
+            "[payees]\n[output]\nrunning_balance = true",
+        );
+
+        // The rows are read out of order, and only the third may give the bank's balance.
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount", "Balance"],
+            vec![
+                vec!["2024-01-12", "ACE", "-4.57", ""],
+                vec!["2024-01-05", "ACE", "-2.89", ""],
+                vec!["2024-01-11", "DIRDEP", "550.00", reported],
+                vec!["2024-01-04", "Wendy's", "-12.54", ""],
+            ],
+        );
+        let expected = format!(
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#,Balance
+            2024-01-12,ACE,,,-4.57,,{}
+            2024-01-05,ACE,,,-2.89,,{}
+            2024-01-11,DIRDEP,,,550.00,,{}
+            2024-01-04,Wendy's,,,-12.54,,{}
+            "},
+            balances[3], balances[1], balances[2], balances[0]
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_running_balance_with_debit_is_positive() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\", \"Balance\"]\ndebit_is_positive = true",
+            "[payees]\n[output]\nrunning_balance = true",
+        );
+
+        // The bank reports what is owed, so the balance is negated like the amounts.
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount", "Balance"],
+            vec![
+                vec!["2024-01-02", "ACE", "50.00", "150.00"],
+                vec!["2024-01-03", "PAYMENT", "-100.00", "50.00"],
+            ],
+        );
+        assert_eq!(
+            result,
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#,Balance
+            2024-01-02,ACE,,,-50.00,,-150.00
+            2024-01-03,PAYMENT,,,100.00,,-50.00
+            "}
+        );
+    }
+
+    #[test]
+    fn test_running_balance_and_source() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]\nstarting_balance = 20",
+            "[payees]\n[output]\nrunning_balance = true\ninclude_source = true",
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![vec!["2024-01-05", "ACE", "-2.89"]],
+        );
+        let expected = indoc! {"
+        Date,Payee,Category,Memo,Amount,Check#,Balance,Source
+        2024-01-05,ACE,,,-2.89,,17.11,
+        "};
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case(false, "")]
+    #[case(true, "Savings")]
+    fn test_carry_forward_categories(#[case] carry_forward: bool, #[case] inherited: &str) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            &format!(
+                indoc! { r#"
+                [payees]
+                Ace = "ACE"
+                Transfer = "XFER"
+
+                [categories]
+                Savings = {{Payee = "Transfer", MinDateInMonth = 10}}
+
+                [output]
+                carry_forward_categories = {}
+                "# },
+                carry_forward
+            ),
+        );
+
+        // The third transaction is the earliest, so it has nothing to inherit
+        // even though it was read after categorized transactions.
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![
+                vec!["2024-02-11", "XFER", "-100.00"],
+                vec!["2024-01-15", "XFER", "-100.00"],
+                vec!["2024-01-05", "XFER", "-100.00"],
+                vec!["2024-02-12", "ACE", "-10.00"],
+                vec!["2024-03-05", "XFER", "-100.00"],
+            ],
+        );
+        let expected = format!(
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#
+            2024-02-11,Transfer,Savings,,-100.00,
+            2024-01-15,Transfer,Savings,,-100.00,
+            2024-01-05,Transfer,,,-100.00,
+            2024-02-12,Ace,,,-10.00,
+            2024-03-05,Transfer,{},,-100.00,
+            "},
+            inherited
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_occurrence_rules() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            indoc! { r#"
+            [payees]
+            Ace = "ACE"
+            Gym = "GYM"
+
+            [categories]
+            Fitness = {Payee = "Gym"}
+            "Sign-up Fee" = {Payee = "Gym", Occurrence = {Nth = 1}}
+
+            [memos]
+            "Every other" = {Payee = "Ace", Occurrence = {Every = 2}}
+            "# },
+        );
+
+        // Only the earliest transaction from the gym is categorized specially,
+        // even though it was read last, and the count is kept for each payee.
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![
+                vec!["2024-02-05", "GYM", "-30.00"],
+                vec!["2024-01-06", "ACE", "-1.00"],
+                vec!["2024-03-05", "GYM", "-30.00"],
+                vec!["2024-01-07", "ACE", "-2.00"],
+                vec!["2024-01-08", "ACE", "-3.00"],
+                vec!["2024-01-05", "GYM", "-99.00"],
+            ],
+        );
+        let expected = indoc! {"
+        Date,Payee,Category,Memo,Amount,Check#
+        2024-02-05,Gym,Fitness,,-30.00,
+        2024-01-06,Ace,,,-1.00,
+        2024-03-05,Gym,Fitness,,-30.00,
+        2024-01-07,Ace,,Every other,-2.00,
+        2024-01-08,Ace,,,-3.00,
+        2024-01-05,Gym,Sign-up Fee,,-99.00,
+        "};
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_occurrences_are_counted_after_filtering() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            indoc! { r#"
+            [payees]
+            Gym = "GYM"
+
+            [categories]
+            Fitness = {Payee = "Gym"}
+            "Sign-up Fee" = {Payee = "Gym", Occurrence = {Nth = 1}}
+
+            [output]
+            sort_order = "ascending"
+            "# },
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        for date in ["2024-01-05", "2024-02-05", "2024-03-05"] {
+            processor
+                .process(as_hashmap(vec![
+                    ("Date", date),
+                    ("Payee", "GYM"),
+                    ("Amount", "-30.00"),
+                ]))
+                .unwrap();
+        }
+        let mut all = HashMap::from([("testing".to_string(), processor)]);
+
+        // The January transaction was already seen, so February is the first.
+        let mut stamps =
+            TimestampKeeper::new(r#"[{"account": "testing", "date": "2024-02-01"}]"#).unwrap();
+        let now = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        account_for_dates_in_transactions(&now, &DateOverrides::default(), &mut all, &mut stamps);
+        let expected = indoc! {"
+        Date,Payee,Category,Memo,Amount,Check#
+        2024-02-05,Gym,Sign-up Fee,,-30.00,
+        2024-03-05,Gym,Fitness,,-30.00,
+        "};
+        assert_eq!(all["testing"].get_transactions_as_csv().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_reversal_pairs_are_first_in_first_out() {
+        let transaction = |date: &str, payee: &str, amount: &str| {
+            NormalizedBankData::new(as_hashmap(vec![
+                ("Date", date),
+                ("Payee", payee),
+                ("Amount", amount),
+            ]))
+        };
+        let transactions = vec![
+            transaction("2024-01-04", "ACE", "10.00"),
+            transaction("2024-01-02", "ACE", "-10.00"),
+            transaction("2024-01-01", "ACE", "-10.00"),
+            transaction("2024-01-03", "BEST", "10.00"),
+            transaction("2024-01-05", "ACE", "10.00"),
+            transaction("2024-01-05", "ACE", "0.00"),
+            transaction("2024-01-06", "ACE", "0.00"),
+        ];
+
+        // The refunds pair with the oldest charges first, and zero amounts never pair.
+        assert_eq!(reversal_pairs(&transactions, 3), vec![(2, 0), (1, 4)]);
+        assert_eq!(reversal_pairs(&transactions, 2), vec![(1, 0)]);
+    }
+
+    #[rstest]
+    #[case(false, "2024-01-05,\" \"\"ACE HARDWARE\"\" \",,,0,\n")]
+    #[case(true, "2024-01-05,Ace,,,-15.43,\n")]
+    fn test_trim_values(#[case] trim_values: bool, #[case] expected: &str) {
+        let rules = rules_with(
+            &format!("identify = [\"Date\", \"Payee\", \"Amount\"]\ntrim_values = {trim_values}"),
+            "[payees]\nAce = \"^ACE HARDWARE$\"",
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![vec![
+                " 2024-01-05",
+                " \"ACE HARDWARE\" ",
+                "\u{201C}-15.43\u{201D}",
+            ]],
+        );
+        let expected = "Date,Payee,Category,Memo,Amount,Check#\n".to_string() + expected;
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case(DateOverrides::default(), vec!["2024-02-11", "2024-03-02"])]
+    #[case(
+        DateOverrides { since: NaiveDate::from_ymd_opt(2024, 3, 1), ..Default::default() },
+        vec!["2024-03-02"],
+    )]
+    #[case(
+        DateOverrides { since: NaiveDate::from_ymd_opt(2024, 1, 1), ..Default::default() },
+        vec!["2024-01-05", "2024-02-11", "2024-03-02"],
+    )]
+    #[case(
+        DateOverrides {
+            since: NaiveDate::from_ymd_opt(2024, 1, 1),
+            account: Some("testing".to_string()),
+            ..Default::default()
+        },
+        vec!["2024-01-05", "2024-02-11", "2024-03-02"],
+    )]
+    #[case(
+        DateOverrides {
+            since: NaiveDate::from_ymd_opt(2024, 1, 1),
+            account: Some("other".to_string()),
+            ..Default::default()
+        },
+        vec!["2024-02-11", "2024-03-02"],
+    )]
+    #[case(
+        DateOverrides { until: NaiveDate::from_ymd_opt(2024, 3, 1), ..Default::default() },
+        vec!["2024-02-11"],
+    )]
+    #[case(
+        DateOverrides {
+            since: NaiveDate::from_ymd_opt(2024, 1, 1),
+            until: NaiveDate::from_ymd_opt(2024, 2, 11),
+            account: None,
+        },
+        vec!["2024-01-05", "2024-02-11"],
+    )]
+    fn test_date_overrides(#[case] overrides: DateOverrides, #[case] expected: Vec<&str>) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]\n",
+            "[payees]\nApple = \"APPLE\"\n[output]\nsort_order = \"ascending\"",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        for date in ["2024-03-02", "2024-01-05", "2024-02-11"] {
+            processor
+                .process(as_hashmap(vec![
+                    ("Date", date),
+                    ("Payee", "A"),
+                    ("Amount", "-1.00"),
+                ]))
+                .unwrap();
+        }
+        let mut all = HashMap::from([("testing".to_string(), processor)]);
+
+        // The stored timestamp would drop everything before February.
+        let mut stamps =
+            TimestampKeeper::new(r#"[{"account": "testing", "date": "2024-02-01"}]"#).unwrap();
+        let now = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        account_for_dates_in_transactions(&now, &overrides, &mut all, &mut stamps);
+
+        let result: Vec<String> = all["testing"]
+            .sorted_transactions()
+            .iter()
+            .map(|t| t.date.format(crate::DATE_FORMAT).to_string())
+            .collect();
+        assert_eq!(result, expected);
+
+        // The timestamps are updated to the end of the window.
+        let end = overrides.until.unwrap_or(now);
+        assert_eq!(stamps.get_date("testing"), end);
+    }
+
+    #[test]
+    fn test_date_overrides_validate() {
+        let overrides = DateOverrides {
+            since: NaiveDate::from_ymd_opt(2024, 3, 1),
+            until: NaiveDate::from_ymd_opt(2024, 2, 1),
+            account: None,
+        };
+        assert_eq!(
+            overrides.validate().unwrap_err().to_string(),
+            "The start date 2024-03-01 is after the end date 2024-02-01"
+        );
+        assert!(DateOverrides::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_gzipped_input() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let data = "Date,Payee,Amount\n2024-01-05,ACE HARDWARE,-15.43\n2024-01-07,Wendy's,-12.54\n";
+
+        // Write the same data as both a plain and a gzipped file.
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let plain = temp.path().join("plain.csv");
+        let gzipped = temp.path().join("gzipped.csv.gz");
+        fs::write(&plain, data).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(
+            fs::File::create(&gzipped).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(data.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        // Both should produce the same normalized output.
+        let as_csv = |path| {
+            process_csv_files(&vec![path], &rules).unwrap()["testing"]
+                .get_transactions_as_csv()
+                .unwrap()
+        };
+        let expected = as_csv(plain);
+        assert_eq!(as_csv(gzipped), expected);
+        assert!(expected.contains("2024-01-05,Ace,,,-15.43,"));
+    }
+
+    #[rstest]
+    #[case(
+        "01/05/2024,ACE,-1.00\n2024-01-07,ACE,-2.00\n",
+        "Cannot parse the date \"01/05/2024\" with the format string \"%Y-%m-%d\""
+    )]
+    #[case(
+        "01/05/2024,ACE,-1.00\n01/07/2024,ACE,-2.00\n01/09/2024,ACE,-3.00\n2024-01-10,ACE,-4.00\n",
+        "The account 'testing' has 3 of 4 dates that cannot be parsed with the date_fmt \
+         \"%Y-%m-%d\" (such as \"01/05/2024\") - the date_fmt is probably wrong"
+    )]
+    fn test_date_format_mismatch(#[case] rows: &str, #[case] expected: &str) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("bank.csv");
+        fs::write(&path, format!("Date,Payee,Amount\n{rows}")).unwrap();
+
+        // A few bad dates are reported individually, but many are reported once.
+        let result = process_csv_files(&vec![&path], &rules);
+        assert_eq!(result.err().unwrap().to_string(), expected);
+    }
+
+    #[rstest]
+    #[case(1, vec!["Ace"])]
+    #[case(2, vec!["Ace", "Wendy's"])]
+    #[case(10, vec!["Ace", "Wendy's", "Ace"])]
+    fn test_preview_csv_files(#[case] limit: usize, #[case] expected: Vec<&str>) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("bank.csv");
+        fs::write(
+            &path,
+            "Date,Payee,Amount\n2024-01-05,ACE,-1.00\n2024-01-07,Wendy's,-2.00\n2024-01-09,ACE,-3.00\n",
+        )
+        .unwrap();
+
+        // Only the first rows of the file are processed.
+        let result = preview_csv_files(&vec![&path], &rules, limit).unwrap();
+        let payees: Vec<_> = result["testing"]
+            .transactions()
+            .iter()
+            .map(|t| t.payee.as_str())
+            .collect();
+        assert_eq!(payees, expected);
+    }
+
+    #[test]
+    fn test_process_csv_files_timed() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("bank.csv");
+        fs::write(
+            &path,
+            "Date,Payee,Amount\n2024-01-05,ACE,-1.00\n2024-01-07,Wendy's,-2.00\n",
+        )
+        .unwrap();
+
+        // Timing the stages does not change the result.
+        let mut times = StageTimes::default();
+        let timed = process_csv_files_timed(&vec![&path], &rules, &mut times).unwrap();
+        let untimed = process_csv_files(&vec![&path], &rules).unwrap();
+        let payees = |result: &HashMap<String, TransactionProcessor>| -> Vec<String> {
+            result["testing"]
+                .transactions()
+                .iter()
+                .map(|t| t.payee.clone())
+                .collect()
+        };
+        assert_eq!(payees(&timed), vec!["Ace", "Wendy's"]);
+        assert_eq!(payees(&timed), payees(&untimed));
+        let stages: Vec<_> = times.stages().iter().map(|(stage, _)| *stage).collect();
+        assert_eq!(stages, vec![STAGE_READING, STAGE_RULES]);
+
+        // Each stage is shown on its own line.
+        let shown: Vec<_> = times
+            .to_string()
+            .lines()
+            .map(|line| line.split(':').next().unwrap().to_owned())
+            .collect();
+        assert_eq!(shown, stages);
+    }
+
+    #[test]
+    fn test_headerless_input() {
+        let rules = rules_with(
+            indoc! {r#"
+            has_headers = false
+            columns = ["Posted", "Description", "Value"]
+            filename_pattern = '^export-\d+\.csv$'
+            translate = {Date = "Posted", Payee = "Description", Amount = "Value"}
+            "#},
+            "[payees]\nAce = \"ACE\"",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("export-0412.csv");
+        fs::write(
+            &path,
+            "2024-01-05,ACE HARDWARE,-15.43\n2024-01-07,Wendy's,-12.54\n",
+        )
+        .unwrap();
+
+        // The first row is data rather than a header.
+        let (headers, label) = identify_csv_file(&path, &rules).unwrap();
+        assert_eq!(headers, vec!["Posted", "Description", "Value"]);
+        assert_eq!(label.as_deref(), Some("testing"));
+        let result = process_csv_files(&vec![&path], &rules).unwrap();
+        let transactions = result["testing"].transactions();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].payee, "Ace");
+        assert_eq!(transactions[0].amount, dec!(-15.43));
+        assert_eq!(transactions[1].payee, "Wendy's");
+
+        // A file whose name does not match is identified by its headers, and so fails.
+        let other = temp.path().join("other.csv");
+        fs::copy(&path, &other).unwrap();
+        assert!(matches!(
+            process_csv_files(&vec![&other], &rules),
+            Err(TidymoneyError::NoMatchingAccount(_))
+        ));
+    }
+
+    #[test]
+    fn test_write_transactions_to_custom_output_dir() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nApple = \"APPLE\"",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let processors = HashMap::from([(
+            "testing".to_string(),
+            TransactionProcessor::new(mapping, &rules),
+        )]);
+
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let mut paths = AuxillaryPaths::new(temp.path());
+        paths.output_dir = "to-import".to_string();
+        write_transactions_to_file("2024-10-25", &paths, &processors, false).unwrap();
+
+        let expected = temp.path().join("to-import/2024-10-25/testing.csv");
+        assert!(expected.is_file());
+        assert!(!temp.path().join("new").exists());
+    }
+
+    #[rstest]
+    #[case(false, true)]
+    #[case(true, false)]
+    fn test_write_transactions_over_earlier_run(#[case] fresh: bool, #[case] kept: bool) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nApple = \"APPLE\"",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let processors = HashMap::from([(
+            "testing".to_string(),
+            TransactionProcessor::new(mapping, &rules),
+        )]);
+
+        // Nothing is reported for the first run of the day.
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let paths = AuxillaryPaths::new(temp.path());
+        let existing = write_transactions_to_file("2024-10-25", &paths, &processors, fresh);
+        assert!(existing.unwrap().is_empty());
+
+        // A later run reports the files already there, removing them if fresh.
+        let base = temp.path().join("new/2024-10-25");
+        fs::write(base.join("stale.csv"), "").unwrap();
+        let existing = write_transactions_to_file("2024-10-25", &paths, &processors, fresh);
+        assert_eq!(
+            existing.unwrap(),
+            vec![base.join("stale.csv"), base.join("testing.csv")]
+        );
+        assert_eq!(base.join("stale.csv").exists(), kept);
+        assert!(base.join("testing.csv").is_file());
+    }
+
+    #[rstest]
+    #[case(vec![], vec![], "testing", true)]
+    #[case(vec!["testing"], vec![], "testing", true)]
+    #[case(vec!["other"], vec![], "testing", false)]
+    #[case(vec![], vec!["testing"], "testing", false)]
+    #[case(vec![], vec!["other"], "testing", true)]
+    fn test_account_selection(
+        #[case] only: Vec<&str>,
+        #[case] exclude: Vec<&str>,
+        #[case] label: &str,
+        #[case] expected: bool,
+    ) {
+        let selection = AccountSelection {
+            only: only.into_iter().map(|x| x.to_string()).collect(),
+            exclude: exclude.into_iter().map(|x| x.to_string()).collect(),
+        };
+        assert_eq!(selection.includes(label), expected);
+    }
+
+    #[test]
+    fn test_account_selection_must_be_defined() {
+        let rules = rules_with("identify = [\"Date\", \"Payee\", \"Amount\"]", "[payees]");
+        let mut selection = AccountSelection {
+            only: vec!["testing".to_string()],
+            exclude: vec![],
+        };
+        assert!(selection.validate(&rules).is_ok());
+        selection.exclude.push("other".to_string());
+        assert_eq!(
+            selection.validate(&rules).unwrap_err().to_string(),
+            "No account is labeled \"other\" in the rules file"
+        );
+    }
+
+    #[test]
+    fn test_split_unmatched_csv_files() {
+        let rules = rules_with("identify = [\"Date\", \"Payee\", \"Amount\"]", "[payees]");
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let first = temp.path().join("first.csv");
+        let other = temp.path().join("other.csv");
+        let second = temp.path().join("second.csv");
+        fs::write(&first, "Date,Payee,Amount\n2024-01-05,ACE,-15.43\n").unwrap();
+        fs::write(&other, "When,Who,HowMuch\n2024-01-05,ACE,-15.43\n").unwrap();
+        fs::write(&second, "Date,Payee,Amount\n2024-01-07,Wendy's,-12.54\n").unwrap();
+
+        let (matched, unmatched) =
+            split_unmatched_csv_files(vec![&first, &other, &second], &rules).unwrap();
+        assert_eq!(matched, vec![&first, &second]);
+        assert_eq!(unmatched, vec![&other]);
+        assert_eq!(
+            process_csv_files(&matched, &rules).unwrap()["testing"].len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_split_empty_csv_files() {
+        let rules = rules_with("identify = [\"Date\", \"Payee\", \"Amount\"]", "[payees]");
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let full = temp.path().join("full.csv");
+        let headers_only = temp.path().join("headers-only.csv");
+        let blank = temp.path().join("blank.csv");
+        fs::write(&full, "Date,Payee,Amount\n2024-01-05,ACE,-15.43\n").unwrap();
+        fs::write(&headers_only, "Date,Payee,Amount\n").unwrap();
+        fs::write(&blank, "").unwrap();
+
+        let (nonempty, empty) =
+            split_empty_csv_files(vec![&full, &headers_only, &blank], &rules).unwrap();
+        assert_eq!(nonempty, vec![&full]);
+        assert_eq!(empty, vec![&headers_only, &blank]);
+
+        // Empty files may still be processed, contributing no transactions.
+        let result = process_csv_files(&vec![&headers_only, &blank], &rules).unwrap();
+        assert_eq!(result.len(), 1);
+        assert!(result["testing"].is_empty());
+    }
+
+    #[rstest]
+    #[case("H\na\nb\n", "H\na\nb\nc\n", vec!["c"], vec![])]
+    #[case("H\na\nb\n", "H\nb\n", vec![], vec!["a"])]
+    #[case("H\na\nb\n", "H\na\nb\n", vec![], vec![])]
+    #[case("H\na\na\n", "H\na\n", vec![], vec!["a"])]
+    #[case("", "H\na\n", vec!["H", "a"], vec![])]
+    fn test_line_diff(
+        #[case] old: &str,
+        #[case] new: &str,
+        #[case] added: Vec<&str>,
+        #[case] removed: Vec<&str>,
+    ) {
+        let (result_added, result_removed) = line_diff(old, new);
+        assert_eq!(result_added, added);
+        assert_eq!(result_removed, removed);
+    }
+
+    #[test]
+    fn test_diff_transaction_files() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nApple = \"APPLE\"",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let processors = |rows: Vec<(&str, &str)>| {
+            let mut processor = TransactionProcessor::new(mapping, &rules);
+            for (date, amount) in rows {
+                processor
+                    .process(as_hashmap(vec![
+                        ("Date", date),
+                        ("Payee", "APPLE.COM"),
+                        ("Amount", amount),
+                    ]))
+                    .unwrap();
+            }
+            HashMap::from([("testing".to_string(), processor)])
+        };
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let paths = AuxillaryPaths::new(temp.path());
+        let path = temp.path().join("new/2024-10-25/testing.csv");
+        let first = processors(vec![("2024-10-21", "-2.99"), ("2024-10-22", "-4.99")]);
+        write_transactions_to_file("2024-10-25", &paths, &first, false).unwrap();
+        let before = fs::read_to_string(&path).unwrap();
+
+        // A changed row is reported as removed and added, and nothing is written.
+        let second = processors(vec![("2024-10-21", "-2.99"), ("2024-10-23", "-4.99")]);
+        let result = diff_transaction_files("2024-10-25", &paths, &second).unwrap();
+        assert_eq!(
+            result,
+            vec![OutputDiff {
+                path: path.clone(),
+                added: vec!["2024-10-23,Apple,,,-4.99,".to_string()],
+                removed: vec!["2024-10-22,Apple,,,-4.99,".to_string()],
+            }]
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+
+        // The same transactions give no change.
+        let result = diff_transaction_files("2024-10-25", &paths, &first).unwrap();
+        assert!(result[0].is_empty());
+    }
+
+    #[test]
+    fn test_write_transactions_to_mirrors() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nApple = \"APPLE\"",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        processor
+            .process(as_hashmap(vec![
+                ("Date", "2024-10-21"),
+                ("Payee", "APPLE.COM"),
+                ("Amount", "-2.99"),
+            ]))
+            .unwrap();
+        let processors = HashMap::from([("testing".to_string(), processor)]);
+
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let local = temp.path().join("local");
+        let cloud = temp.path().join("cloud");
+        let mut paths = AuxillaryPaths::new(&local);
+        paths.mirrors = vec![cloud.clone()];
+        write_transactions_to_file("2024-10-25", &paths, &processors, false).unwrap();
+
+        let written = |root: &Path| fs::read_to_string(root.join("new/2024-10-25/testing.csv"));
+        assert_eq!(written(&local).unwrap(), written(&cloud).unwrap());
+        assert!(written(&local)
+            .unwrap()
+            .contains("2024-10-21,Apple,,,-2.99,"));
+    }
+
+    #[test]
+    fn test_no_matching_account() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nApple = \"APPLE\"",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("unknown.csv");
+        fs::write(&path, "When,Who,HowMuch\n2024-01-05,ACE,-15.43\n").unwrap();
+
+        let result = process_csv_files(&vec![&path], &rules);
+        assert!(matches!(
+            result.err().unwrap(),
+            TidymoneyError::NoMatchingAccount(given) if given == path
+        ));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nApple = \"APPLE\"",
+        );
+        let result = process_csv_files(&vec!["/does/not/exist.csv"], &rules);
+        assert!(matches!(result.err().unwrap(), TidymoneyError::Io(_)));
+    }
+
+    #[rstest]
+    #[case(
+        vec!["2024-01-01,Opening,100.00", "2024-01-02,ACE,84.57", "2024-01-05,Payroll,1084.57"],
+    )]
+    #[case(
+        vec!["2024-01-05,Payroll,1084.57", "2024-01-02,ACE,84.57", "2024-01-01,Opening,100.00"],
+    )]
+    fn test_amount_from_balance(#[case] rows: Vec<&str>) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Balance\"]\namount_from_balance = true",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Balance"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        let rows = rows
+            .into_iter()
+            .map(|row| {
+                as_hashmap(
+                    ["Date", "Payee", "Balance"]
+                        .into_iter()
+                        .zip(row.split(','))
+                        .collect(),
+                )
+            })
+            .collect();
+        processor.process_file(rows, None).unwrap();
+
+        // The first row has no previous balance so is dropped.
+        assert_eq!(
+            processor.get_transactions_as_csv().unwrap(),
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#
+            2024-01-02,Ace,,,-15.43,
+            2024-01-05,Payroll,,,1000.00,
+            "}
+        );
+    }
+
+    #[test]
+    fn test_amount_from_description() {
+        let rules = rules_with(
+            indoc! { r#"
+            identify = ["Date", "Description"]
+            translate = {Payee = "Description"}
+            amount_from = {column = "Description", pattern = 'AMT (-?\d+\.\d{2})'}
+            "# },
+            "[payees]\nAce = \"ACE\"",
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Description"],
+            vec![vec!["2024-01-05", "POS ACE HARDWARE AMT -12.34 REF 0042"]],
+        );
+        assert_eq!(
+            result,
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#
+            2024-01-05,Ace,,,-12.34,
+            "}
+        );
+    }
+
+    #[test]
+    fn test_amount_from_balance_date_format_mismatch() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Balance\"]\namount_from_balance = true",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("bank.csv");
+        fs::write(
+            &path,
+            "Date,Payee,Balance\n01/01/2024,ACE,100.00\n01/02/2024,ACE,90.00\n\
+             01/03/2024,ACE,80.00\n01/04/2024,ACE,70.00\n",
+        )
+        .unwrap();
+
+        // Many bad dates are reported once rather than failing on the first.
+        let result = process_csv_files(&vec![&path], &rules);
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "The account 'testing' has 3 of 3 dates that cannot be parsed with the date_fmt \
+             \"%Y-%m-%d\" (such as \"01/02/2024\") - the date_fmt is probably wrong"
+        );
+    }
+
+    #[test]
+    fn test_amount_from_balance_invalid() {
+        let rows = vec![
+            as_hashmap(vec![("Date", "2024-01-01"), ("Balance", "100.00")]),
+            as_hashmap(vec![("Date", "2024-01-02"), ("Balance", "N/A")]),
+        ];
+        let result = amounts_from_balance(rows, crate::DATE_FORMAT, "testing").unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            "The account 'testing' has an invalid Balance value \"N/A\""
+        );
+    }
+
+    #[test]
+    fn test_bank_category_is_remapped() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\", \"Category\"]",
+            indoc! { r#"
+            [payees]
+            Ace = "ACE"
+
+            [categories]
+            Shopping = {Category = "Merchandise"}
+            "# },
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount", "Category"],
+            vec![
+                vec!["2024-01-05", "ACE", "-15.43", "Merchandise"],
+                vec!["2024-01-06", "TARGET", "-8.12", "Merchandise"],
+                vec!["2024-01-07", "WENDY'S", "-12.54", "Restaurants"],
+            ],
+        );
+        assert_eq!(
+            result,
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#
+            2024-01-05,Ace,Shopping,,-15.43,
+            2024-01-06,TARGET,Shopping,,-8.12,
+            2024-01-07,WENDY'S,Restaurants,,-12.54,
+            "}
+        );
+    }
+
+    #[test]
+    fn test_only_if_uncategorized() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\", \"Category\"]",
+            indoc! { r#"
+            [payees]
+            Ace = "ACE"
+
+            [categories]
+            Hardware = {Payee = "Ace", OnlyIfUncategorized = true}
+            "# },
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount", "Category"],
+            vec![
+                vec!["2024-01-05", "ACE", "-15.43", "Garden"],
+                vec!["2024-01-07", "ACE", "-4.57", ""],
+            ],
+        );
+        assert_eq!(
+            result,
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#
+            2024-01-05,Ace,Garden,,-15.43,
+            2024-01-07,Ace,Hardware,,-4.57,
+            "}
+        );
+    }
+
+    #[test]
+    fn test_source_is_recorded() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"\n[output]\ninclude_source = true",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let first = temp.path().join("first.csv");
+        let second = temp.path().join("second.csv");
+        fs::write(&first, "Date,Payee,Amount\n2024-01-05,ACE,-15.43\n").unwrap();
+        fs::write(&second, "Date,Payee,Amount\n2024-01-07,Wendy's,-12.54\n").unwrap();
+
+        let processors = process_csv_files(&vec![&first, &second], &rules).unwrap();
+        let processor = &processors["testing"];
+        let sources: Vec<_> = processor
+            .transactions()
+            .iter()
+            .map(|t| t.source.as_deref())
+            .collect();
+        assert_eq!(sources, vec![Some(first.as_path()), Some(second.as_path())]);
+        assert_eq!(
+            processor.get_transactions_as_csv().unwrap(),
+            format!(
+                "Date,Payee,Category,Memo,Amount,Check#,Source\n\
+                 2024-01-05,Ace,,,-15.43,,{}\n\
+                 2024-01-07,Wendy's,,,-12.54,,{}\n",
+                first.display(),
+                second.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_unmatched_payees_are_title_cased() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nACE = \"ACE HARDWARE\"\n[output]\npayee_case = \"title\"",
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![
+                vec!["2024-01-05", "ACE HARDWARE", "-15.43"],
+                vec!["2024-01-07", "WENDY'S", "-12.54"],
+            ],
+        );
+        assert_eq!(
+            result,
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#
+            2024-01-05,ACE,,,-15.43,
+            2024-01-07,Wendy's,,,-12.54,
+            "}
+        );
+    }
+
+    #[test]
+    fn test_identify_csv_file() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let matching = temp.path().join("matching.csv");
+        fs::write(&matching, "Date,Payee,Amount\n2024-01-05,ACE,-15.43\n").unwrap();
+        let other = temp.path().join("other.csv");
+        fs::write(&other, "Date,Description,Amount\n").unwrap();
+
+        assert_eq!(
+            identify_csv_file(&matching, &rules).unwrap(),
+            (
+                vec![
+                    "Date".to_string(),
+                    "Payee".to_string(),
+                    "Amount".to_string()
+                ],
+                Some("testing".to_string())
+            )
+        );
+        assert_eq!(
+            identify_csv_file(&other, &rules).unwrap(),
+            (
+                vec![
+                    "Date".to_string(),
+                    "Description".to_string(),
+                    "Amount".to_string()
+                ],
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_process_in_memory() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rule_data = format!(
+            indoc! { r#"
+            [payees]
+            Ace = "ACE"
+
+            [[mappings.csv]]
+            label = "testing"
+            identify = ["Date", "Payee", "Amount"]
+
+            [paths]
+            storage = {:#?}
+            "# },
+            temp.path()
+        );
+        let stamps_data = r#"[{"account": "testing", "date": "2024-01-06"}]"#;
+        let csv = temp.path().join("testing.csv");
+        fs::write(
+            &csv,
+            "Date,Payee,Amount\n2024-01-05,ACE,-15.43\n2024-01-07,ACE,-4.57\n",
+        )
+        .unwrap();
+
+        let now = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let result = process_in_memory(
+            rule_data,
+            stamps_data,
+            &vec![&csv],
+            &now,
+            &DateOverrides::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            result.transactions,
+            HashMap::from([(
+                "testing".to_string(),
+                "Date,Payee,Category,Memo,Amount,Check#\n2024-01-07,Ace,,,-4.57,\n".to_string()
+            )])
+        );
+        assert!(result.stamps.contains("\"date\": \"2024-01-10\""));
+
+        // Nothing is written or moved.
+        assert!(csv.exists());
+        assert!(!temp.path().join("new").exists());
+    }
+
+    #[test]
+    fn test_process_in_memory_invalid_stamps() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rule_data = format!(
+            indoc! { r#"
+            [payees]
+
+            [[mappings.csv]]
+            label = "testing"
+            identify = ["Date", "Payee", "Amount"]
+
+            [paths]
+            storage = {:#?}
+            "# },
+            temp.path()
+        );
+        let now = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let result = process_in_memory(
+            rule_data,
+            "not json",
+            &Vec::<PathBuf>::new(),
+            &now,
+            &DateOverrides::default(),
+        );
+        assert!(matches!(
+            result.err().unwrap(),
+            TidymoneyError::InvalidTimestamps(_)
+        ));
+    }
+
+    #[rstest]
+    #[case("", "2024-01-05", 10, vec![])]
+    #[case("", "2014-01-11", 10, vec![])]
+    #[case("", "2014-01-09", 10, vec!["testing: 2014-01-09 ACE"])]
+    #[case("", "0205-01-05", 10, vec!["testing: 0205-01-05 ACE"])]
+    #[case("", "2035-01-05", 10, vec!["testing: 2035-01-05 ACE"])]
+    #[case("date_fmt = \"%Y-%d-%m\"", "2024-01-05", 10, vec![])]
+    #[case("date_fmt = \"%d/%m/%Y\"", "05/01/24", 10, vec!["testing: 0024-01-05 ACE"])]
+    #[case("", "2019-01-05", 2, vec!["testing: 2019-01-05 ACE"])]
+    #[case("", "0205-01-05", u32::MAX, vec![])]
+    fn test_find_skewed_dates(
+        #[case] mapping: &str,
+        #[case] date: &str,
+        #[case] years: u32,
+        #[case] expected: Vec<&str>,
+    ) {
+        let rules = rules_with(
+            &format!("identify = [\"Date\", \"Payee\", \"Amount\"]\n{mapping}"),
+            "[payees]",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let csv = temp.path().join("testing.csv");
+        fs::write(&csv, format!("Date,Payee,Amount\n{date},ACE,-15.43\n")).unwrap();
+        let results = process_csv_files(&vec![&csv], &rules).unwrap();
+
+        let now = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let result: Vec<_> = find_skewed_dates(&now, years, &results)
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_process_in_memory_skewed_date() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rule_data = format!(
+            indoc! { r#"
+            [payees]
+            Ace = "ACE"
+
+            [[mappings.csv]]
+            label = "testing"
+            identify = ["Date", "Payee", "Amount"]
+
+            [paths]
+            storage = {:#?}
+
+            [checks]
+            date_skew = "error"
+            "# },
+            temp.path()
+        );
+        let csv = temp.path().join("testing.csv");
+        fs::write(&csv, "Date,Payee,Amount\n0205-01-05,ACE,-15.43\n").unwrap();
+
+        let now = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let result = process_in_memory(rule_data, "[]", &vec![&csv], &now, &Default::default());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The account 'testing' has a transaction dated 0205-01-05, which is more than 10 \
+             years from today - check its date_fmt"
+        );
+    }
+
+    #[test]
+    fn test_write_transactions_split_by_month() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rules = RuleFileData::new(format!(
+            indoc! { r#"
+            [payees]
+            Ace = "ACE"
+
+            [[mappings.csv]]
+            label = "checking"
+            identify = ["Date", "Payee", "Amount"]
+
+            [[mappings.csv]]
+            label = "savings"
+            identify = ["Date", "Description", "Amount"]
+            translate = {{Payee = "Description"}}
+
+            [paths]
+            storage = {:#?}
+
+            [output]
+            split_by_month = true
+            "# },
+            temp.path()
+        ))
+        .unwrap();
+        let checking = temp.path().join("checking.csv");
+        let savings = temp.path().join("savings.csv");
+        fs::write(
+            &checking,
+            "Date,Payee,Amount\n2024-01-30,ACE,-15.43\n2024-02-02,ACE,-4.57\n2024-01-31,ACE,-1.00\n",
+        )
+        .unwrap();
+        fs::write(
+            &savings,
+            "Date,Description,Amount\n2024-01-15,Interest,0.12\n2024-02-15,Interest,0.13\n",
+        )
+        .unwrap();
+
+        let processors = process_csv_files(&vec![&checking, &savings], &rules).unwrap();
+        write_transactions_to_file("2024-03-01", &rules.paths, &processors, false).unwrap();
+
+        let base = temp.path().join("new").join("2024-03-01");
+        let mut written: Vec<_> = fs::read_dir(&base)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        written.sort();
+        assert_eq!(
+            written,
+            vec![
+                "checking-2024-01.csv",
+                "checking-2024-02.csv",
+                "savings-2024-01.csv",
+                "savings-2024-02.csv",
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(base.join("checking-2024-01.csv")).unwrap(),
+            indoc! {"
+            Date,Payee,Category,Memo,Amount,Check#
+            2024-01-30,Ace,,,-15.43,
+            2024-01-31,Ace,,,-1.00,
+            "}
+        );
+    }
+
+    #[rstest]
+    #[case(false, vec!["Checking Export.csv", "savings.csv"])]
+    #[case(true, vec!["Checking Export-2024-01.csv", "savings-2024-01.csv"])]
+    fn test_write_transactions_output_name(
+        #[case] split_by_month: bool,
+        #[case] expected: Vec<&str>,
+    ) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rules = RuleFileData::new(format!(
+            indoc! { r#"
+            [payees]
+            Ace = "ACE"
+
+            [[mappings.csv]]
+            label = "checking"
+            output_name = "Checking Export"
+            identify = ["Date", "Payee", "Amount"]
+
+            [[mappings.csv]]
+            label = "savings"
+            identify = ["Date", "Description", "Amount"]
+            translate = {{Payee = "Description"}}
+
+            [paths]
+            storage = {:#?}
+
+            [output]
+            split_by_month = {}
+            "# },
+            temp.path(),
+            split_by_month
+        ))
+        .unwrap();
+        let checking = temp.path().join("checking.csv");
+        let savings = temp.path().join("savings.csv");
+        fs::write(&checking, "Date,Payee,Amount\n2024-01-30,ACE,-15.43\n").unwrap();
+        fs::write(
+            &savings,
+            "Date,Description,Amount\n2024-01-15,Interest,0.12\n",
+        )
+        .unwrap();
+
+        let processors = process_csv_files(&vec![&checking, &savings], &rules).unwrap();
+        write_transactions_to_file("2024-03-01", &rules.paths, &processors, false).unwrap();
+
+        let base = temp.path().join("new").join("2024-03-01");
+        let mut written: Vec<_> = fs::read_dir(&base)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        written.sort();
+        assert_eq!(written, expected);
+    }
+
+    #[rstest]
+    #[case("Date,Payee,Amount,\n2024-01-05,ACE,-15.43,\n")]
+    #[case("Date,Payee,Amount,,\n2024-01-05,ACE,-15.43,,\n")]
+    fn test_trailing_empty_columns(#[case] data: &str) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let path = temp.path().join("trailing.csv");
+        fs::write(&path, data).unwrap();
+
+        let processors = process_csv_files(&vec![&path], &rules).unwrap();
+        assert_eq!(
+            processors["testing"].get_transactions_as_csv().unwrap(),
+            "Date,Payee,Category,Memo,Amount,Check#\n2024-01-05,Ace,,,-15.43,\n"
+        );
+    }
+
+    #[rstest]
+    #[case("", vec!["2024-01-05,Ace,,,-1.00", "2024-01-06,INTEREST,Income,,0.10"])]
+    #[case(
+        "default_category = \"Savings\"",
+        vec!["2024-01-05,Ace,Savings,,-1.00", "2024-01-06,INTEREST,Income,,0.10"],
+    )]
+    fn test_default_category(#[case] mapping: &str, #[case] expected: Vec<&str>) {
+        let rules = rules_with(
+            &format!("identify = [\"Date\", \"Payee\", \"Amount\"]\n{mapping}"),
+            "[payees]\nAce = \"ACE\"\n[categories]\nIncome = {OrigPayee = \"INTEREST\"}",
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Amount"],
+            vec![
+                vec!["2024-01-05", "ACE", "-1.00"],
+                vec!["2024-01-06", "INTEREST", "0.10"],
+            ],
+        );
+
+        // The account's default only applies if no rule sets the category.
+        let expected = expected.into_iter().fold(
+            "Date,Payee,Category,Memo,Amount,Check#\n".to_string(),
+            |acc, row| acc + row + ",\n",
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_default_category_loses_to_clearing_rule() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Category\", \"Amount\"]\n\
+             default_category = \"Savings\"",
+            "[payees]\nAce = \"ACE\"\n[categories]\n\"\" = {Category = \"Misc\"}",
+        );
+        let result = process_rows(
+            &rules,
+            vec!["Date", "Payee", "Category", "Amount"],
+            vec![
+                vec!["2024-01-05", "ACE", "Misc", "-1.00"],
+                vec!["2024-01-06", "ACE", "", "-2.00"],
+            ],
+        );
+        assert_eq!(
+            result,
+            indoc! {"
+                Date,Payee,Category,Memo,Amount,Check#
+                2024-01-05,Ace,,,-1.00,
+                2024-01-06,Ace,Savings,,-2.00,
+            "}
+        );
+    }
+
+    #[test]
+    fn test_default_category_is_recorded_as_matched() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]\ndefault_category = \"Savings\"",
+            "[payees]\nAce = \"ACE\"",
+        );
+        let transaction = rules
+            .normalize_row(
+                as_hashmap(vec![
+                    ("Date", "2024-01-05"),
+                    ("Payee", "ACE"),
+                    ("Amount", "-1.00"),
+                ]),
+                "testing",
+            )
+            .unwrap();
+        assert_eq!(
+            transaction.matched.category.as_deref(),
+            Some("default_category for testing")
+        );
+    }
+
+    #[rstest]
+    #[case("", 0, vec!["2024-01-05,Ace,,,-1.00", "2024-01-06,PING,,,-2.00", "2024-01-07,PINGO,,,-3.00"])]
+    #[case(
+        "[ignore]\nPing = {Pattern = \"PING\", WholeMatch = true}",
+        1,
+        vec!["2024-01-05,Ace,,,-1.00", "2024-01-07,PINGO,,,-3.00"],
+    )]
+    #[case(
+        "[ignore]\nPing = [\"^PING$\", \"PINGO\"]",
+        2,
+        vec!["2024-01-05,Ace,,,-1.00"],
+    )]
+    fn test_ignore_rules(
+        #[case] ignore: &str,
+        #[case] ignored: usize,
+        #[case] expected: Vec<&str>,
+    ) {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            &format!("[payees]\nAce = \"ACE\"\n{ignore}"),
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        for (date, payee, amount) in [
+            ("2024-01-05", "ACE", "-1.00"),
+            ("2024-01-06", "PING", "-2.00"),
+            ("2024-01-07", "PINGO", "-3.00"),
+        ] {
+            processor
+                .process(as_hashmap(vec![
+                    ("Date", date),
+                    ("Payee", payee),
+                    ("Amount", amount),
+                ]))
+                .unwrap();
+        }
+
+        // Matching transactions are removed whatever their date or amount.
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+        let counts = processor.drop_uneeded(&start, &end);
+        assert_eq!(counts.ignored, ignored);
+        assert_eq!(counts.total(), ignored);
+        let expected = expected.into_iter().fold(
+            "Date,Payee,Category,Memo,Amount,Check#\n".to_string(),
+            |acc, row| acc + row + ",\n",
+        );
+        assert_eq!(processor.get_transactions_as_csv().unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("", 2, 0)]
+    #[case("blank_amounts = \"zero\"", 2, 0)]
+    #[case("blank_amounts = \"skip\"", 1, 1)]
+    fn test_blank_amounts(
+        #[case] mapping: &str,
+        #[case] zero_amount: usize,
+        #[case] blank_amount: usize,
+    ) {
+        let rules = rules_with(
+            &format!("identify = [\"Date\", \"Payee\", \"Amount\"]\n{mapping}"),
+            "[payees]\nApple = \"APPLE\"",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        for amount in ["", "0.00", "-1.00"] {
+            processor
+                .process(as_hashmap(vec![
+                    ("Date", "2024-01-05"),
+                    ("Payee", "A"),
+                    ("Amount", amount),
+                ]))
+                .unwrap();
+        }
+
+        // A blank amount is only told apart from a zero amount if requested.
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2024, 2, 1).unwrap();
+        let counts = processor.drop_uneeded(&start, &end);
+        assert_eq!(counts.zero_amount, zero_amount);
+        assert_eq!(counts.blank_amount, blank_amount);
+        assert_eq!(counts.total(), 2);
+        assert_eq!(processor.len(), 1);
+    }
+
+    #[test]
+    fn test_skip_counts() {
+        let rules = rules_with(
+            "identify = [\"Date\", \"Payee\", \"Amount\"]",
+            "[payees]\nApple = \"APPLE\"",
+        );
+        let mapping = rules
+            .get_csv_mapping_rules(&csv::StringRecord::from(vec!["Date", "Payee", "Amount"]))
+            .unwrap();
+        let mut processor = TransactionProcessor::new(mapping, &rules);
+        for (date, amount) in [
+            ("2024-01-05", "-1.00"),
+            ("2024-01-06", "0.00"),
+            ("2024-02-11", "-1.00"),
+            ("2024-02-12", "-1.00"),
+            ("2024-03-20", "-1.00"),
+            ("2024-03-21", "0.00"),
+        ] {
+            processor
+                .process(as_hashmap(vec![
+                    ("Date", date),
+                    ("Payee", "A"),
+                    ("Amount", amount),
+                ]))
+                .unwrap();
+        }
+        let mut all = HashMap::from([("testing".to_string(), processor)]);
+
+        let mut stamps =
+            TimestampKeeper::new(r#"[{"account": "testing", "date": "2024-02-01"}]"#).unwrap();
+        let now = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        let skipped = account_for_dates_in_transactions(
+            &now,
+            &DateOverrides::default(),
+            &mut all,
+            &mut stamps,
+        );
+
+        // Zero amounts are counted as such regardless of their date.
+        let expected = SkipCounts {
+            zero_amount: 2,
+            blank_amount: 0,
+            before_start: 1,
+            after_end: 1,
+            reversed: 0,
+            ignored: 0,
+        };
+        assert_eq!(skipped, HashMap::from([("testing".to_string(), expected)]));
+        assert_eq!(expected.total(), 4);
+        assert_eq!(all["testing"].len(), 2);
     }
-    Ok(())
 }
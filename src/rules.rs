@@ -1,27 +1,64 @@
+mod amount_filter;
 mod category_and_memo;
+mod checks;
 mod date_filter;
 mod eqregex;
+mod fuzzy;
+mod hooks;
+mod include;
 mod mapping;
+mod migrate;
+mod note;
+mod occurrence;
+mod output;
 mod paths;
 mod payees;
 
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::rules::category_and_memo::{hashmap_cat_memo_rules, CategoryAndMemoRules};
-use crate::rules::paths::AuxillaryPaths;
+use crate::rules::include::parse_with_includes;
 use crate::rules::payees::{hashmap_payee_rules, PayeeRules};
-use crate::NormalizedBankData;
+use crate::{NormalizedBankData, TidymoneyError};
 
-pub use crate::rules::mapping::MappingRulesCsv;
-pub use crate::rules::paths::normalize_path;
+pub use crate::rules::checks::{CheckAction, Checks};
+pub use crate::rules::hooks::Hooks;
+pub use crate::rules::mapping::{BlankAmounts, MappingRulesCsv};
+pub use crate::rules::migrate::Migration;
+pub use crate::rules::output::{Aggregate, OutputFormat, OutputOptions, SortOrder, TieBreak};
+pub use crate::rules::paths::{canonicalize_path, normalize_path, AuxillaryPaths};
+
+/// The version of the rules file format understood by this version of tidymoney.
+pub const RULES_VERSION: u32 = 1;
+
+/// The version assumed for rules files that do not give one.
+fn current_version() -> u32 {
+    RULES_VERSION
+}
+
+/// The names of the rules that set the payee, category, and memo of a transaction.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct MatchedRules {
+    /// The payee rule that renamed the payee, if any.
+    pub payee: Option<String>,
+    /// The category rule that set the category, if any.
+    pub category: Option<String>,
+    /// The memo rule that set the memo, if any.
+    pub memo: Option<String>,
+}
 
 /// The aggregation of all rules found in the rules file.
 #[derive(Debug, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct RuleFileData {
+    /// The version of the rules file format.
+    #[serde(default = "current_version")]
+    pub version: u32,
     /// Rules to map a given payee name to the desired value.
     #[serde(deserialize_with = "hashmap_payee_rules")]
     payees: HashMap<String, Vec<PayeeRules>>,
@@ -31,24 +68,80 @@ pub struct RuleFileData {
     /// Rules to synthesize a memo for some transaction.
     #[serde(deserialize_with = "hashmap_cat_memo_rules", default)]
     memos: Option<HashMap<String, Vec<CategoryAndMemoRules>>>,
+    /// Rules for transactions to remove regardless of their date or amount.
+    #[serde(deserialize_with = "hashmap_payee_rules", default)]
+    ignore: HashMap<String, Vec<PayeeRules>>,
     /// Rules for how to identify and translate files for different account types.
     mappings: MappingTypes,
     /// Locations of paths used by the program.
     pub paths: AuxillaryPaths,
+    /// Options for how the normalized transactions are written.
+    #[serde(default)]
+    pub output: OutputOptions,
+    /// Commands to run at points during a run.
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// Sanity checks on the transactions read during a run.
+    #[serde(default)]
+    pub checks: Checks,
 }
 
 impl RuleFileData {
     // Create a new RuleFileData from raw string data.
-    pub fn new(raw_data: impl AsRef<str>) -> Result<Self> {
-        let rules: RuleFileData = toml::from_str(raw_data.as_ref())?;
-        rules.validate()?;
+    pub fn new(raw_data: impl AsRef<str>) -> Result<Self, TidymoneyError> {
+        Self::new_in_dir(raw_data, "")
+    }
+
+    /// Create a new RuleFileData from raw string data, resolving any included
+    /// files relative to the given directory.
+    pub fn new_in_dir(
+        raw_data: impl AsRef<str>,
+        dir: impl AsRef<Path>,
+    ) -> Result<Self, TidymoneyError> {
+        let invalid = |e: anyhow::Error| TidymoneyError::InvalidRule(e.to_string());
+        check_version(raw_data.as_ref()).map_err(invalid)?;
+        let rules: RuleFileData =
+            parse_with_includes(raw_data.as_ref(), dir.as_ref()).map_err(invalid)?;
+        rules.validate().map_err(invalid)?;
         Ok(rules)
     }
 
+    /// Upgrade raw rules file data to the current version of the rules format.
+    /// Any files given by include are not changed.
+    pub fn migrate(raw_data: impl AsRef<str>) -> Result<Migration, TidymoneyError> {
+        migrate::migrate(raw_data.as_ref()).map_err(|e| TidymoneyError::InvalidRule(e.to_string()))
+    }
+
+    /// Create the storage directory named in the raw rules data if it does not
+    /// yet exist, returning its path if it was created.
+    pub fn create_storage(
+        raw_data: impl AsRef<str>,
+        dir: impl AsRef<Path>,
+    ) -> Result<Option<PathBuf>, TidymoneyError> {
+        /// Only the paths are needed, so ignore everything else.
+        #[derive(Deserialize)]
+        struct PathsOnly {
+            paths: AuxillaryPaths,
+        }
+
+        let parsed: PathsOnly = parse_with_includes(raw_data.as_ref(), dir.as_ref())
+            .map_err(|e| TidymoneyError::InvalidRule(e.to_string()))?;
+        let storage = parsed.paths.storage;
+        if storage.exists() {
+            return Ok(None);
+        }
+        fs::create_dir_all(&storage)?;
+        Ok(Some(storage))
+    }
+
     /// Determine to which account the given headers correlate.
     pub fn get_csv_mapping_rules(&self, headers: &csv::StringRecord) -> Option<&MappingRulesCsv> {
         // Convert the headers object into a vector of strings so it can be compared.
-        let hdrs: Vec<String> = headers.iter().map(|x| x.to_owned()).collect();
+        // Some exports end each line with a comma, so ignore trailing empty headers.
+        let mut hdrs: Vec<String> = headers.iter().map(|x| x.to_owned()).collect();
+        while hdrs.last().is_some_and(|x| x.trim().is_empty()) {
+            hdrs.pop();
+        }
 
         // Identify the mapping rules that match the headers found.
         // If no rules were found, return None.
@@ -58,67 +151,249 @@ impl RuleFileData {
             .find(|&candidates| candidates.header_matches(&hdrs))
     }
 
+    /// Determine to which account without headers the given file correlates, by its name.
+    pub fn get_headerless_mapping_rules(&self, path: impl AsRef<Path>) -> Option<&MappingRulesCsv> {
+        self.mappings
+            .csv
+            .iter()
+            .find(|&candidates| candidates.file_name_matches(path.as_ref()))
+    }
+
+    /// Whether or not an account with the given label is defined.
+    pub fn has_account(&self, label: &str) -> bool {
+        self.mappings
+            .csv
+            .iter()
+            .any(|mapping| mapping.label == label)
+    }
+
+    /// Normalize a single row of raw data for the account with the given label.
+    pub fn normalize_row(
+        &self,
+        raw: HashMap<String, String>,
+        mapping_label: &str,
+    ) -> Result<NormalizedBankData, TidymoneyError> {
+        let mapping = self
+            .mappings
+            .csv
+            .iter()
+            .find(|mapping| mapping.label == mapping_label)
+            .ok_or_else(|| TidymoneyError::UnknownAccount(mapping_label.to_owned()))?;
+        self.normalize(mapping, mapping.remap(mapping.trim(raw))?)
+    }
+
+    /// Normalize a row whose columns have already been remapped for the given account.
+    pub(crate) fn normalize(
+        &self,
+        mapping: &MappingRulesCsv,
+        data: HashMap<String, String>,
+    ) -> Result<NormalizedBankData, TidymoneyError> {
+        let mut transaction = NormalizedBankData::from_raw_data(data, mapping)?;
+        transaction.matched = self.update_transaction(&mut transaction);
+
+        // The account's default only applies if no rule matched and the
+        // bank gave no category, so a rule that clears the category wins.
+        if let Some(default) = &mapping.default_category {
+            if transaction.matched.category.is_none()
+                && transaction
+                    .category
+                    .as_deref()
+                    .is_none_or(|x| x.trim().is_empty())
+            {
+                transaction.category = Some(default.to_owned());
+                transaction.matched.category =
+                    Some(format!("default_category for {}", mapping.label));
+            }
+        }
+        Ok(transaction)
+    }
+
     /// Run the transaction through the updating functions.
-    pub fn update_transaction(&self, transaction: &mut NormalizedBankData) {
-        self.update_payee(transaction);
-        self.update_category(transaction);
-        self.update_memo(transaction);
+    ///
+    /// The order matters - each step may match on the results of the steps
+    /// before it, so memos can match on the category assigned by a rule.
+    /// Returns the names of the rules that changed the transaction.
+    pub fn update_transaction(&self, transaction: &mut NormalizedBankData) -> MatchedRules {
+        let payee = self.update_payee(transaction);
+        self.normalize_payee(transaction);
+        let category = self.update_category(transaction);
+        let memo = self.update_memo(transaction);
+        MatchedRules {
+            payee,
+            category,
+            memo,
+        }
+    }
+
+    /// Whether any category or memo rule gives an Occurrence.
+    pub(crate) fn has_occurrence_rules(&self) -> bool {
+        self.categories
+            .iter()
+            .chain(self.memos.iter())
+            .flat_map(|rules| rules.values().flatten())
+            .any(|rule| rule.has_occurrence())
     }
 
-    /// Determine a better payee name if available.
-    fn update_payee(&self, transaction: &mut NormalizedBankData) {
+    /// Apply the category and memo rules that give an Occurrence to the
+    /// transaction, which is the given occurrence of its payee in the run.
+    ///
+    /// These are applied after all other rules, so they take precedence.
+    pub(crate) fn update_for_occurrence(
+        &self,
+        transaction: &mut NormalizedBankData,
+        ordinal: usize,
+    ) {
+        for (category, candidates) in self.categories.iter().flatten() {
+            if let Some(candidate) = candidates
+                .iter()
+                .find(|candidate| candidate.occurrence_matches(transaction, ordinal))
+            {
+                transaction.category = (!category.is_empty()).then(|| category.to_owned());
+                transaction.matched.category = Some(candidate.describe(category));
+            }
+        }
+        for (memo, candidates) in self.memos.iter().flatten() {
+            if let Some(candidate) = candidates
+                .iter()
+                .find(|candidate| candidate.occurrence_matches(transaction, ordinal))
+            {
+                transaction.memo = Some(memo.to_owned());
+                transaction.matched.memo = Some(candidate.describe(memo));
+            }
+        }
+    }
+
+    /// Determine if any payee rule matches the transaction.
+    pub fn has_payee_rule_for(&self, transaction: &NormalizedBankData) -> bool {
+        self.payees
+            .values()
+            .flatten()
+            .any(|candidate| candidate.transaction_matches(transaction))
+    }
+
+    /// Determine if any ignore rule matches the transaction.
+    pub fn is_ignored(&self, transaction: &NormalizedBankData) -> bool {
+        self.ignore
+            .values()
+            .flatten()
+            .any(|candidate| candidate.transaction_matches(transaction))
+    }
+
+    /// Determine a better payee name if available, returning the rule used.
+    fn update_payee(&self, transaction: &mut NormalizedBankData) -> Option<String> {
+        let mut matched = None;
         for (payee, candidates) in &self.payees {
             for candidate in candidates {
                 if candidate.transaction_matches(transaction) {
                     transaction.payee = payee.to_owned();
+                    matched = Some(candidate.describe(payee));
                     break;
                 }
             }
         }
+        matched
     }
 
-    /// Determine a better category if available.
-    fn update_category(&self, transaction: &mut NormalizedBankData) {
+    /// Change the case of the payee if it was not renamed by a rule.
+    fn normalize_payee(&self, transaction: &mut NormalizedBankData) {
+        if transaction.payee == transaction.orig_payee {
+            transaction.payee = self.output.payee_case.apply(&transaction.payee);
+        }
+    }
+
+    /// Determine a better category if available, returning the rule used.
+    /// A rule for the empty category removes any existing category.
+    fn update_category(&self, transaction: &mut NormalizedBankData) -> Option<String> {
+        let mut matched = None;
         if let Some(cat) = &self.categories {
             for (category, candidates) in cat {
                 for candidate in candidates {
                     if candidate.transaction_matches(transaction) {
-                        transaction.category = Some(category.to_owned());
+                        transaction.category = (!category.is_empty()).then(|| category.to_owned());
+                        matched = Some(candidate.describe(category));
                         break;
                     }
                 }
             }
         }
+        matched
     }
 
-    /// Determine a better memo if available.
-    fn update_memo(&self, transaction: &mut NormalizedBankData) {
-        if let Some(memos) = &self.memos {
-            for (memo, candidates) in memos {
-                for candidate in candidates {
-                    if candidate.transaction_matches(transaction) {
-                        transaction.memo = Some(memo.to_owned());
-                        break;
+    /// Determine a better memo if available, returning the rule used.
+    fn update_memo(&self, transaction: &mut NormalizedBankData) -> Option<String> {
+        let Some(separator) = &self.output.memo_separator else {
+            let mut matched = None;
+            if let Some(memos) = &self.memos {
+                for (memo, candidates) in memos {
+                    for candidate in candidates {
+                        if candidate.transaction_matches(transaction) {
+                            transaction.memo = Some(memo.to_owned());
+                            matched = Some(candidate.describe(memo));
+                            break;
+                        }
                     }
                 }
             }
+            return matched;
+        };
+
+        // Join every matching memo, in order of name so the result is repeatable.
+        let mut memos: Vec<_> = self.memos.iter().flatten().collect();
+        memos.sort_by(|x1, x2| x1.0.cmp(x2.0));
+        let (names, rules): (Vec<&str>, Vec<String>) = memos
+            .into_iter()
+            .filter_map(|(memo, candidates)| {
+                candidates
+                    .iter()
+                    .find(|candidate| candidate.transaction_matches(transaction))
+                    .map(|candidate| (memo.as_str(), candidate.describe(memo)))
+            })
+            .unzip();
+        if names.is_empty() {
+            return None;
         }
+        transaction.memo = Some(names.join(separator));
+        Some(rules.join(separator))
     }
 
     /// Ensure the read-in rules make logical sense.
     fn validate(&self) -> Result<()> {
         self.paths.validate()?;
-        for mapping in &self.mappings.csv {
+        self.output.validate()?;
+        self.checks.validate()?;
+        for (i, mapping) in self.mappings.csv.iter().enumerate() {
             mapping.validate()?;
+            if self.mappings.csv[..i]
+                .iter()
+                .any(|m| m.label == mapping.label)
+            {
+                return Err(anyhow!(
+                    "The account {:#?} is defined more than once.",
+                    mapping.label
+                ));
+            }
+            if let Some(other) = self.mappings.csv[..i]
+                .iter()
+                .find(|m| m.output_name() == mapping.output_name())
+            {
+                return Err(anyhow!(
+                    "The accounts {:#?} and {:#?} both write to the output_name {:#?}.",
+                    other.label,
+                    mapping.label,
+                    mapping.output_name()
+                ));
+            }
         }
 
-        // Verify that each of the rules is unique.
+        // Verify that each of the rules is unique. Disabled rules are ignored
+        // so that a rule can be switched off while trying out a replacement.
         #[allow(clippy::mutable_key_type)]
         let mut check: HashMap<&PayeeRules, &String> = HashMap::new();
         for (payee, rules) in self.payees.iter() {
-            for rule in rules.iter() {
-                if check.contains_key(&rule) {
-                    let other = check[rule];
+            for rule in rules.iter().filter(|rule| rule.enabled()) {
+                if let Some((other_rule, other)) = check.get_key_value(rule) {
+                    let other = other_rule.describe(other);
+                    let payee = rule.describe(payee);
                     let values = if other < payee {
                         (other, payee)
                     } else {
@@ -138,6 +413,13 @@ impl RuleFileData {
         for (name, payees) in &self.payees {
             for payee in payees {
                 payee.validate(name)?;
+                self.validate_account("payee", name, payee.account())?;
+            }
+        }
+        for (name, ignores) in &self.ignore {
+            for ignore in ignores {
+                ignore.validate(name)?;
+                self.validate_account("ignore rule", name, ignore.account())?;
             }
         }
 
@@ -146,6 +428,7 @@ impl RuleFileData {
             for (cat_name, categories) in c {
                 for category in categories {
                     category.validate("category", cat_name)?;
+                    self.validate_account("category", cat_name, category.account())?;
                 }
             }
         }
@@ -153,12 +436,23 @@ impl RuleFileData {
             for (memo_name, memos) in m {
                 for memo in memos {
                     memo.validate("memo", memo_name)?;
+                    self.validate_account("memo", memo_name, memo.account())?;
                 }
             }
         }
 
         Ok(())
     }
+
+    /// Ensure an account that a rule is restricted to is defined.
+    fn validate_account(&self, obj_type: &str, name: &str, account: Option<&str>) -> Result<()> {
+        match account {
+            Some(account) if !self.mappings.csv.iter().any(|m| m.label == account) => Err(anyhow!(
+                "The {obj_type} {name:#?} requires the account {account:#?}, which is not defined."
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
 /// Holds rules for different types of input formats.
@@ -169,14 +463,35 @@ struct MappingTypes {
     csv: Vec<MappingRulesCsv>,
 }
 
+/// Ensure the rules file is for a version of the format that is understood,
+/// before any unknown keys from another version cause confusing errors.
+fn check_version(raw_data: &str) -> Result<u32> {
+    let table: toml::Table = toml::from_str(raw_data)?;
+    let Some(version) = table.get("version") else {
+        return Ok(current_version());
+    };
+    match version.as_integer() {
+        Some(v) if v > RULES_VERSION.into() => Err(anyhow!(
+            "The rules file is for version {v} of the rules format, but this version of \
+             tidymoney only understands up to version {RULES_VERSION} - please upgrade tidymoney."
+        )),
+        Some(v) if v < 1 => Err(anyhow!(
+            "The rules file version {v} is not valid - versions start at 1."
+        )),
+        Some(v) => Ok(v as u32),
+        None => Err(anyhow!("The rules file version must be a whole number.")),
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use std::{fs, path::PathBuf, str::FromStr};
+    use std::str::FromStr;
 
     use super::*;
 
     use indoc::indoc;
     use pretty_assertions::assert_eq;
+    use rstest::rstest;
 
     use crate::as_hashmap;
 
@@ -248,6 +563,9 @@ mod test {
         identify = ["Date", " Time", " Amount", " Type", " Description"]
         translate = {Amount = " Amount", Payee = " Description"}
 
+        [output]
+        sort_order = "ascending"
+
         "# }
         .to_string()
             + &paths_section(&storage);
@@ -318,6 +636,7 @@ mod test {
                     ],
                 ),
             ])),
+            ignore: HashMap::new(),
             mappings: MappingTypes {
                 csv: vec![
                     MappingRulesCsv::new(
@@ -346,11 +665,201 @@ mod test {
                 ],
             },
             paths: AuxillaryPaths::new(storage),
+            output: toml::from_str("sort_order = \"ascending\"").unwrap(),
+            hooks: Hooks::default(),
+            checks: Checks::default(),
+            version: RULES_VERSION,
         };
         let result = RuleFileData::new(&given).unwrap();
         assert_eq!(result, expected);
     }
 
+    #[rstest]
+    #[case(vec!["Date", "Payee", "Amount"], true)]
+    #[case(vec!["Date", "Payee", "Amount", ""], true)]
+    #[case(vec!["Date", "Payee", "Amount", "", " "], true)]
+    #[case(vec!["Date", "Payee", "", "Amount"], false)]
+    #[case(vec!["Date", "Payee"], false)]
+    fn test_trailing_empty_headers_are_ignored(#[case] headers: Vec<&str>, #[case] expected: bool) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = format!(
+            "[payees]\n[[mappings.csv]]\nlabel = \"pnc\"\nidentify = [\"Date\", \"Payee\", \"Amount\"]\n{}",
+            paths_section(&storage)
+        );
+        let rules = RuleFileData::new(&given).unwrap();
+        let result = rules.get_csv_mapping_rules(&csv::StringRecord::from(headers));
+        assert_eq!(result.is_some(), expected);
+    }
+
+    #[test]
+    fn test_normalize_row() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = indoc! { r#"
+        [payees]
+        Apple = "APPLE"
+
+        [categories]
+        Electronics = {Payee = "Apple"}
+
+        [[mappings.csv]]
+        label = "pnc"
+        identify = ["Date", "Description", "Amount"]
+        translate = {Payee = "Description"}
+        debit_is_positive = true
+        trim_values = true
+
+        "# }
+        .to_string()
+            + &paths_section(&storage);
+        let rules = RuleFileData::new(&given).unwrap();
+        let raw = as_hashmap(vec![
+            ("Date", "2023-09-17"),
+            ("Description", " APPLE.COM/BILL "),
+            ("Amount", "2.99"),
+        ]);
+
+        let result = rules.normalize_row(raw.clone(), "pnc").unwrap();
+        assert_eq!(result.payee, "Apple");
+        assert_eq!(result.orig_payee, "APPLE.COM/BILL");
+        assert_eq!(result.category, Some("Electronics".into()));
+        assert_eq!(result.amount.to_string(), "-2.99");
+        assert_eq!(result.label, "pnc");
+
+        let result = rules.normalize_row(raw, "ally").unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            "No account is labeled \"ally\" in the rules file"
+        );
+    }
+
+    #[rstest]
+    #[case("", None)]
+    #[case("[output]\nmemo_separator = \"; \"", Some("; "))]
+    fn test_memo_separator(#[case] output: &str, #[case] separator: Option<&str>) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = indoc! { r#"
+        [payees]
+        Apple = "APPLE"
+
+        [memos]
+        Reimbursable = {Account = "pnc"}
+        Parking = {OrigPayee = "PARKING"}
+
+        [[mappings.csv]]
+        label = "pnc"
+        identify = ["Date", "Payee", "Amount"]
+
+        [[mappings.csv]]
+        label = "visa"
+        identify = ["Date", "Payee", "Amount"]
+
+        "# }
+        .to_string()
+            + &paths_section(&storage)
+            + output;
+        let rules = RuleFileData::new(&given).unwrap();
+        let memo = |payee: &str, label: &str| {
+            let row = as_hashmap(vec![
+                ("Date", "2023-09-17"),
+                ("Payee", payee),
+                ("Amount", "-2.99"),
+            ]);
+            rules.normalize_row(row, label).unwrap().memo
+        };
+
+        // Both memos match the first transaction, and are only joined if requested.
+        let both = memo("PARKING GARAGE", "pnc").unwrap();
+        match separator {
+            Some(separator) => assert_eq!(both, format!("Parking{separator}Reimbursable")),
+            None => assert!(both == "Parking" || both == "Reimbursable"),
+        }
+        assert_eq!(memo("WENDY'S", "pnc").as_deref(), Some("Reimbursable"));
+        assert_eq!(memo("WENDY'S", "visa"), None);
+    }
+
+    #[test]
+    fn test_empty_category_clears_category() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = indoc! { r#"
+        [payees]
+        Apple = "APPLE"
+
+        [categories]
+        "" = {Category = "Misc"}
+
+        [[mappings.csv]]
+        label = "pnc"
+        identify = ["Date", "Payee", "Amount", "Category"]
+
+        "# }
+        .to_string()
+            + &paths_section(&storage);
+        let rules = RuleFileData::new(&given).unwrap();
+        let row = |category: &str| {
+            as_hashmap(vec![
+                ("Date", "2023-09-17"),
+                ("Payee", "APPLE.COM/BILL"),
+                ("Amount", "-2.99"),
+                ("Category", category),
+            ])
+        };
+
+        let result = rules.normalize_row(row("Misc"), "pnc").unwrap();
+        assert_eq!(result.category, None);
+        let result = rules.normalize_row(row("Electronics"), "pnc").unwrap();
+        assert_eq!(result.category, Some("Electronics".into()));
+    }
+
+    #[rstest]
+    #[case("", Ok(RULES_VERSION))]
+    #[case("version = 1", Ok(1))]
+    #[case(
+        "version = 2",
+        Err("The rules file is for version 2 of the rules format, but this version of tidymoney only understands up to version 1 - please upgrade tidymoney.")
+    )]
+    #[case(
+        "version = 0",
+        Err("The rules file version 0 is not valid - versions start at 1.")
+    )]
+    #[case(
+        "version = \"1\"",
+        Err("The rules file version must be a whole number.")
+    )]
+    fn test_version(#[case] version: &str, #[case] expected: Result<u32, &str>) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+
+        // A too-new version is reported instead of any keys it does not know.
+        let given = format!(
+            indoc! { r#"
+            {}
+
+            [payees]
+            Apple = "APPLE"
+
+            [[mappings.csv]]
+            label = "pnc"
+            identify = ["Date", "Payee", "Amount"]
+            {}
+
+            "# },
+            version,
+            if expected.is_err() {
+                "new_option = true"
+            } else {
+                ""
+            }
+        ) + &paths_section(&storage);
+        let result = RuleFileData::new(&given)
+            .map(|rules| rules.version)
+            .map_err(|e| e.to_string());
+        assert_eq!(result, expected.map_err(|e| e.to_string()));
+    }
+
     #[test]
     fn test_rule_file_without_memo_and_category() {
         let temp = tempdir::TempDir::new("test").unwrap();
@@ -364,6 +873,7 @@ mod test {
             )]),
             categories: None,
             memos: None,
+            ignore: HashMap::new(),
             mappings: MappingTypes {
                 csv: vec![MappingRulesCsv::new(
                     "pnc".to_string(),
@@ -377,6 +887,10 @@ mod test {
                 )],
             },
             paths: AuxillaryPaths::new(storage),
+            output: OutputOptions::default(),
+            hooks: Hooks::default(),
+            checks: Checks::default(),
+            version: RULES_VERSION,
         };
         let result = RuleFileData::new(&given).unwrap();
         assert_eq!(result, expected);
@@ -416,6 +930,211 @@ mod test {
             .contains("Sandwich = \"Subway\"\n"));
     }
 
+    #[test]
+    fn test_repeated_patterns_report_notes() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+
+        let given = indoc! { r#"
+        [payees]
+        "Apple" = {Pattern = "PAYPAL", MinAmount = 50.00, Note = "iCloud storage"}
+        "Microsoft" = {Pattern = "PAYPAL", MinAmount = 50.00}
+
+        [[mappings.csv]]
+        label = "pnc"
+        identify = ["Date", "Reference Number", "Payee", "Address", "Amount"]
+
+        "# }
+        .to_string()
+            + &paths_section(&storage);
+        assert_eq!(
+            r#"The payees "Apple (iCloud storage)" and "Microsoft" both implement identical rules."#,
+            RuleFileData::new(&given).err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_disabled_rules_are_not_duplicates() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+
+        let given = indoc! { r#"
+        [payees]
+        "Apple" = {Pattern = "PAYPAL", MinAmount = 50.00, Enabled = false}
+        "Microsoft" = {Pattern = "PAYPAL", MinAmount = 50.00}
+
+        [[mappings.csv]]
+        label = "pnc"
+        identify = ["Date", "Reference Number", "Payee", "Address", "Amount"]
+
+        "# }
+        .to_string()
+            + &paths_section(&storage);
+        assert!(RuleFileData::new(&given).is_ok());
+    }
+
+    #[rstest]
+    #[case("[payees]\nApple = \"APPLE\"\n")] // missing mappings
+    #[case(
+        "[payees]\nApple = {MinAmount = 50.00}\n[[mappings.csv]]\nlabel = \"pnc\"\nidentify = []\n"
+    )]
+    fn test_invalid_rules_are_reported(#[case] given: &str) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = given.to_string() + &paths_section(&storage);
+        assert!(matches!(
+            RuleFileData::new(&given).err().unwrap(),
+            TidymoneyError::InvalidRule(_)
+        ));
+    }
+
+    #[rstest]
+    #[case("Apple = \"APPLE(\"")]
+    #[case("Apple = [\"APPLE\", \"APPLE(\"]")]
+    fn test_invalid_string_pattern_is_reported(#[case] payee: &str) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = format!(
+            "[payees]\n{payee}\n[[mappings.csv]]\nlabel = \"pnc\"\nidentify = []\n{}",
+            paths_section(&storage)
+        );
+        let result = RuleFileData::new(&given).err().unwrap();
+        assert!(matches!(result, TidymoneyError::InvalidRule(_)));
+        assert!(result
+            .to_string()
+            .contains("Could not parse the string \"APPLE(\" as a regular expression"));
+    }
+
+    #[test]
+    fn test_create_storage() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = temp.path().join("money").join("storage");
+        let given = "[payees]\n".to_string() + &paths_section(&storage);
+
+        // The storage directory is created only if it does not exist.
+        assert_eq!(
+            RuleFileData::create_storage(&given, "").unwrap(),
+            Some(storage.clone())
+        );
+        assert!(storage.is_dir());
+        assert_eq!(RuleFileData::create_storage(&given, "").unwrap(), None);
+    }
+
+    #[test]
+    fn test_included_files_are_validated() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        fs::write(
+            temp.path().join("payees.toml"),
+            "[payees]\nMicrosoft = {Pattern = \"PAYPAL\", MinAmount = 50.00}\n",
+        )
+        .unwrap();
+        fs::write(
+            temp.path().join("mappings.toml"),
+            "[[mappings.csv]]\nlabel = \"pnc\"\nidentify = [\"Date\", \"Payee\", \"Amount\"]\n",
+        )
+        .unwrap();
+        let base = |payees: &str| {
+            format!(
+                "include = [\"payees.toml\", \"mappings.toml\"]\n[payees]\n{payees}\n{}",
+                paths_section(&storage)
+            )
+        };
+
+        // Rules from all files are present.
+        let rules = RuleFileData::new_in_dir(base("Apple = \"APPLE\""), temp.path()).unwrap();
+        assert_eq!(rules.payees.len(), 2);
+        assert_eq!(rules.mappings.csv.len(), 1);
+
+        // Identical rules across files are caught.
+        let result = RuleFileData::new_in_dir(
+            base("Apple = {Pattern = \"PAYPAL\", MinAmount = 50.00}"),
+            temp.path(),
+        );
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "The payees \"Apple\" and \"Microsoft\" both implement identical rules."
+        );
+    }
+
+    #[rstest]
+    #[case("[payees]\nApple = {Pattern = \"APPLE\", Account = \"visa\"}", "payee")]
+    #[case(
+        "[payees]\n[categories]\nTech = {Payee = \"Apple\", Account = \"visa\"}",
+        "category"
+    )]
+    #[case("[payees]\n[memos]\nTech = {Account = \"visa\"}", "memo")]
+    #[case(
+        "[payees]\n[ignore]\nPing = {Pattern = \"PING\", Account = \"visa\"}",
+        "ignore rule"
+    )]
+    fn test_rule_accounts_must_be_defined(#[case] rules: &str, #[case] obj_type: &str) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = format!(
+            "{rules}\n[[mappings.csv]]\nlabel = \"pnc\"\nidentify = [\"Date\", \"Payee\", \"Amount\"]\n{}",
+            paths_section(&storage)
+        );
+        let result = RuleFileData::new(&given).unwrap_err().to_string();
+        assert!(result.starts_with(&format!("The {obj_type} ")));
+        assert!(result.ends_with("requires the account \"visa\", which is not defined."));
+
+        // The rules are fine once the account exists.
+        let given = given.replace("\"pnc\"", "\"visa\"");
+        assert!(RuleFileData::new(&given).is_ok());
+    }
+
+    #[test]
+    fn test_cannot_repeat_account_labels() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = indoc! { r#"
+        [payees]
+        Apple = "APPLE"
+
+        [[mappings.csv]]
+        label = "pnc"
+        identify = ["Date", "Payee", "Amount"]
+
+        [[mappings.csv]]
+        label = "pnc"
+        identify = ["Date", "Description", "Amount"]
+
+        "# }
+        .to_string()
+            + &paths_section(&storage);
+        assert_eq!(
+            RuleFileData::new(&given).err().unwrap().to_string(),
+            "The account \"pnc\" is defined more than once."
+        );
+    }
+
+    #[test]
+    fn test_cannot_repeat_output_names() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let storage = aux_paths(&temp);
+        let given = indoc! { r#"
+        [payees]
+        Apple = "APPLE"
+
+        [[mappings.csv]]
+        label = "pnc"
+        identify = ["Date", "Payee", "Amount"]
+
+        [[mappings.csv]]
+        label = "visa"
+        output_name = "pnc"
+        identify = ["Date", "Description", "Amount"]
+
+        "# }
+        .to_string()
+            + &paths_section(&storage);
+        assert_eq!(
+            RuleFileData::new(&given).err().unwrap().to_string(),
+            "The accounts \"pnc\" and \"visa\" both write to the output_name \"pnc\"."
+        );
+    }
+
     #[test]
     fn test_cannot_repeat_patterns() {
         let temp = tempdir::TempDir::new("test").unwrap();
@@ -444,4 +1163,52 @@ mod test {
             RuleFileData::new(&given).err().unwrap().to_string()
         );
     }
+
+    /// The keys accepted by the given rules type, as listed by the error for an unknown key.
+    fn accepted_keys<T: serde::de::DeserializeOwned + std::fmt::Debug>() -> Vec<String> {
+        let message = toml::from_str::<T>("not_a_key = 1")
+            .unwrap_err()
+            .to_string();
+        let (_, expected) = message.split_once("expected").unwrap();
+        let expected = expected.lines().next().unwrap();
+        expected
+            .split('`')
+            .skip(1)
+            .step_by(2)
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn test_full_template_shows_every_key() {
+        let template = include_str!("full_rules.toml");
+        // A key may be given on its own line or within an inline table.
+        let shown = |key: &str| {
+            template.lines().any(|line| {
+                let line = line.trim_start_matches('#');
+                let assigned = format!("{key} =");
+                line.starts_with(&assigned)
+                    || line.contains(&format!(" {assigned}"))
+                    || line.contains(&format!("{{{assigned}"))
+                    || line.starts_with(&format!("[{key}"))
+                    || line.starts_with(&format!("[[{key}"))
+                    || line.ends_with(&format!(".{key}]"))
+            })
+        };
+        for keys in [
+            accepted_keys::<RuleFileData>(),
+            accepted_keys::<PayeeRules>(),
+            accepted_keys::<CategoryAndMemoRules>(),
+            accepted_keys::<MappingRulesCsv>(),
+            accepted_keys::<OutputOptions>(),
+            accepted_keys::<AuxillaryPaths>(),
+            accepted_keys::<Hooks>(),
+            accepted_keys::<Checks>(),
+        ] {
+            assert!(keys.len() > 1);
+            for key in keys {
+                assert!(shown(&key), "The full template does not show {key:#?}");
+            }
+        }
+    }
 }
@@ -0,0 +1,298 @@
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+
+/// The amount-based filters that a rule may specify.
+pub struct AmountFilters {
+    /// The exact amount of the transaction.
+    pub amount: Option<Decimal>,
+    /// The lower range of the transaction amount.
+    pub min_amount: Option<Decimal>,
+    /// The upper range of the transaction amount.
+    pub max_amount: Option<Decimal>,
+    /// Whether the transaction amount must be strictly greater than the lower range.
+    pub min_exclusive: bool,
+    /// Whether the transaction amount must be strictly less than the upper range.
+    pub max_exclusive: bool,
+    /// How far (as a percentage of amount) the transaction may be from amount.
+    pub percent_tolerance: Option<Decimal>,
+    /// Whether to compare signed values instead of absolute values.
+    pub signed: bool,
+    /// Whether the transaction amount must be a whole number of dollars.
+    pub whole_dollar: bool,
+}
+
+impl AmountFilters {
+    /// Assess if the transaction amount is outside the range.
+    /// Unless signed comparisons are requested, all comparisons are
+    /// in absolute value for user ease.
+    pub fn is_outside_range(&self, amount: Decimal) -> bool {
+        let value = |x: Decimal| if self.signed { x } else { x.abs() };
+        let amt = value(amount);
+
+        // If the amount does not fall in the value ranges it is outside.
+        let below_min = self.min_amount.map(value).is_some_and(|min_amt| {
+            if self.min_exclusive {
+                amt <= min_amt
+            } else {
+                amt < min_amt
+            }
+        });
+        let above_max = self.max_amount.map(value).is_some_and(|max_amt| {
+            if self.max_exclusive {
+                amt >= max_amt
+            } else {
+                amt > max_amt
+            }
+        });
+        if below_min || above_max {
+            return true;
+        }
+
+        // If a whole number of dollars is required, any cents make it outside.
+        if self.whole_dollar && !amount.fract().is_zero() {
+            return true;
+        }
+
+        // If the amount is not equal to the target (or not within the
+        // tolerance band around the target) it is outside.
+        match (self.amount, self.percent_tolerance) {
+            (Some(target), Some(pct)) => {
+                let target = value(target);
+                (amt - target).abs() > target.abs() * pct / Decimal::ONE_HUNDRED
+            }
+            // Decimal equality ignores the scale, so 15.430 equals 15.43.
+            (Some(target), None) => value(target) != amt,
+            (None, _) => false,
+        }
+    }
+
+    /// Ensure the given filters are semantically correct.
+    pub fn validate(&self, obj_type: &str, name: &str) -> Result<()> {
+        if let Some(pct) = self.percent_tolerance {
+            if pct <= Decimal::ZERO {
+                return Err(anyhow!(
+                    "The {obj_type} {name:#?} specifies an AmountPercentTolerance that is not positive."
+                ));
+            }
+            if self.amount.is_none() {
+                return Err(anyhow!(
+                    "The {obj_type} {name:#?} specifies an AmountPercentTolerance without an Amount."
+                ));
+            }
+        }
+
+        if self.min_exclusive && self.min_amount.is_none() {
+            return Err(anyhow!(
+                "The {obj_type} {name:#?} specifies MinAmountExclusive without a MinAmount."
+            ));
+        }
+        if self.max_exclusive && self.max_amount.is_none() {
+            return Err(anyhow!(
+                "The {obj_type} {name:#?} specifies MaxAmountExclusive without a MaxAmount."
+            ));
+        }
+
+        if self.whole_dollar
+            && self.percent_tolerance.is_none()
+            && self.amount.is_some_and(|amount| !amount.fract().is_zero())
+        {
+            return Err(anyhow!(
+                "The {obj_type} {name:#?} specifies WholeDollar with an Amount that has cents."
+            ));
+        }
+
+        // Compare the same way as when matching so that the check is consistent.
+        let value = |x: Decimal| if self.signed { x } else { x.abs() };
+        let min_amt = self.min_amount.map(value);
+        let max_amt = self.max_amount.map(value);
+        if let (Some(min_amt), Some(max_amt)) = (min_amt, max_amt) {
+            if min_amt > max_amt {
+                return Err(anyhow!(
+                    "The {obj_type} {name:#?} specifies a MinAmount that is greater than its MaxAmount."
+                ));
+            }
+            if min_amt == max_amt && (self.min_exclusive || self.max_exclusive) {
+                return Err(anyhow!(
+                    "The {obj_type} {name:#?} specifies an exclusive MinAmount or MaxAmount that no amount can satisfy."
+                ));
+            }
+        }
+        if let Some(target) = self.amount.map(value) {
+            let tolerance =
+                target.abs() * self.percent_tolerance.unwrap_or_default() / Decimal::ONE_HUNDRED;
+            let (lowest, highest) = (target - tolerance, target + tolerance);
+            if min_amt.is_some_and(|min_amt| {
+                highest < min_amt || (self.min_exclusive && highest == min_amt)
+            }) || max_amt.is_some_and(|max_amt| {
+                lowest > max_amt || (self.max_exclusive && lowest == max_amt)
+            }) {
+                return Err(anyhow!(
+                    "The {obj_type} {name:#?} specifies an Amount that is excluded by its MinAmount or MaxAmount."
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+    use rust_decimal_macros::dec;
+
+    fn filters(
+        amount: Option<Decimal>,
+        min_amount: Option<Decimal>,
+        max_amount: Option<Decimal>,
+        percent_tolerance: Option<Decimal>,
+    ) -> AmountFilters {
+        AmountFilters {
+            amount,
+            min_amount,
+            max_amount,
+            min_exclusive: false,
+            max_exclusive: false,
+            percent_tolerance,
+            signed: false,
+            whole_dollar: false,
+        }
+    }
+
+    fn exclusive(
+        filters: AmountFilters,
+        min_exclusive: bool,
+        max_exclusive: bool,
+    ) -> AmountFilters {
+        AmountFilters {
+            min_exclusive,
+            max_exclusive,
+            ..filters
+        }
+    }
+
+    fn signed(filters: AmountFilters) -> AmountFilters {
+        AmountFilters {
+            signed: true,
+            ..filters
+        }
+    }
+
+    fn whole_dollar(filters: AmountFilters) -> AmountFilters {
+        AmountFilters {
+            whole_dollar: true,
+            ..filters
+        }
+    }
+
+    #[rstest]
+    #[case(filters(None, None, None, None), dec!(-15.43), false)]
+    #[case(filters(Some(dec!(15.43)), None, None, None), dec!(-15.43), false)]
+    #[case(filters(Some(dec!(15.00)), None, None, None), dec!(-15.43), true)]
+    #[case(filters(None, Some(dec!(10)), Some(dec!(20)), None), dec!(-15.43), false)]
+    #[case(filters(None, Some(dec!(10)), Some(dec!(15)), None), dec!(-15.43), true)]
+    #[case(filters(Some(dec!(100.00)), None, None, Some(dec!(3))), dec!(-102.50), false)]
+    #[case(filters(Some(dec!(100.00)), None, None, Some(dec!(3))), dec!(97.00), false)]
+    #[case(filters(Some(dec!(100.00)), None, None, Some(dec!(3))), dec!(-104.00), true)]
+    #[case(filters(Some(dec!(100.00)), None, None, Some(dec!(3))), dec!(96.99), true)]
+    // Absolute comparisons ignore the sign of both the rule and the transaction.
+    #[case(filters(None, Some(dec!(-50)), None, None), dec!(-75.00), false)]
+    #[case(filters(None, Some(dec!(-50)), None, None), dec!(-25.00), true)]
+    #[case(filters(None, Some(dec!(-50)), None, None), dec!(100.00), false)]
+    #[case(filters(Some(dec!(-20)), None, None, None), dec!(20.00), false)]
+    // Signed comparisons respect the sign of both the rule and the transaction.
+    #[case(signed(filters(None, Some(dec!(-50)), None, None)), dec!(-75.00), true)]
+    #[case(signed(filters(None, Some(dec!(-50)), None, None)), dec!(-25.00), false)]
+    #[case(signed(filters(None, Some(dec!(-50)), None, None)), dec!(100.00), false)]
+    #[case(signed(filters(None, None, Some(dec!(0)), None)), dec!(-25.00), false)]
+    #[case(signed(filters(None, None, Some(dec!(0)), None)), dec!(25.00), true)]
+    #[case(signed(filters(Some(dec!(-20)), None, None, None)), dec!(20.00), true)]
+    #[case(signed(filters(Some(dec!(-20)), None, None, None)), dec!(-20.00), false)]
+    #[case(signed(filters(Some(dec!(-100)), None, None, Some(dec!(3)))), dec!(-102.50), false)]
+    #[case(signed(filters(Some(dec!(-100)), None, None, Some(dec!(3)))), dec!(100.00), true)]
+    // Values on an inclusive boundary are inside, but on an exclusive boundary are outside.
+    #[case(filters(None, Some(dec!(50)), Some(dec!(100)), None), dec!(-50.00), false)]
+    #[case(filters(None, Some(dec!(50)), Some(dec!(100)), None), dec!(-100.00), false)]
+    #[case(exclusive(filters(None, Some(dec!(50)), Some(dec!(100)), None), true, false), dec!(-50.00), true)]
+    #[case(exclusive(filters(None, Some(dec!(50)), Some(dec!(100)), None), true, false), dec!(-100.00), false)]
+    #[case(exclusive(filters(None, Some(dec!(50)), Some(dec!(100)), None), false, true), dec!(-50.00), false)]
+    #[case(exclusive(filters(None, Some(dec!(50)), Some(dec!(100)), None), false, true), dec!(-100.00), true)]
+    #[case(exclusive(filters(None, Some(dec!(50)), Some(dec!(100)), None), true, true), dec!(-75.00), false)]
+    // A whole number of dollars may be required, whatever the sign or scale.
+    #[case(whole_dollar(filters(None, None, None, None)), dec!(-50.00), false)]
+    #[case(whole_dollar(filters(None, None, None, None)), dec!(50), false)]
+    #[case(whole_dollar(filters(None, None, None, None)), dec!(-49.99), true)]
+    #[case(whole_dollar(filters(None, None, None, None)), dec!(0.50), true)]
+    #[case(whole_dollar(filters(None, Some(dec!(10)), None, None)), dec!(-5.00), true)]
+    // Amounts with a different number of decimal places are compared by value.
+    #[case(filters(Some(dec!(15.430)), None, None, None), dec!(-15.43), false)]
+    #[case(filters(Some(dec!(15.43)), None, None, None), dec!(-15.4300), false)]
+    #[case(filters(Some(dec!(15)), None, None, None), dec!(-15.00), false)]
+    #[case(filters(Some(dec!(15.430)), None, None, None), dec!(-15.431), true)]
+    #[case(signed(filters(Some(dec!(-15.4)), None, None, None)), dec!(-15.40), false)]
+    #[case(filters(None, Some(dec!(15.430)), Some(dec!(15.43)), None), dec!(-15.4300), false)]
+    #[case(exclusive(filters(None, Some(dec!(15.4)), None, None), true, false), dec!(-15.40), true)]
+    fn test_is_outside_range(
+        #[case] given: AmountFilters,
+        #[case] amount: Decimal,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(given.is_outside_range(amount), expected);
+    }
+
+    #[rstest]
+    #[case(filters(Some(dec!(100)), None, None, Some(dec!(0))), "is not positive")]
+    #[case(filters(Some(dec!(100)), None, None, Some(dec!(-3))), "is not positive")]
+    #[case(filters(None, None, None, Some(dec!(3))), "without an Amount")]
+    #[case(filters(None, Some(dec!(50)), Some(dec!(20)), None), "greater than its MaxAmount")]
+    #[case(filters(None, Some(dec!(-50)), Some(dec!(20)), None), "greater than its MaxAmount")]
+    #[case(
+        signed(filters(None, Some(dec!(20)), Some(dec!(-50)), None)),
+        "greater than its MaxAmount"
+    )]
+    #[case(filters(Some(dec!(10)), Some(dec!(20)), None, None), "excluded by its MinAmount")]
+    #[case(filters(Some(dec!(60)), None, Some(dec!(50)), None), "excluded by its MinAmount")]
+    #[case(
+        filters(Some(dec!(100)), Some(dec!(20)), Some(dec!(50)), Some(dec!(10))),
+        "excluded by its MinAmount"
+    )]
+    #[case(exclusive(filters(None, None, Some(dec!(50)), None), true, false), "MinAmountExclusive without")]
+    #[case(exclusive(filters(None, Some(dec!(50)), None, None), false, true), "MaxAmountExclusive without")]
+    #[case(
+        exclusive(filters(None, Some(dec!(50)), Some(dec!(50)), None), true, false),
+        "no amount can satisfy"
+    )]
+    #[case(
+        exclusive(filters(None, Some(dec!(50)), Some(dec!(50)), None), false, true),
+        "no amount can satisfy"
+    )]
+    #[case(
+        exclusive(filters(Some(dec!(50)), Some(dec!(50)), None, None), true, false),
+        "excluded by its MinAmount"
+    )]
+    #[case(
+        exclusive(filters(Some(dec!(50)), None, Some(dec!(50)), None), false, true),
+        "excluded by its MinAmount"
+    )]
+    #[case(whole_dollar(filters(Some(dec!(49.99)), None, None, None)), "WholeDollar with an Amount")]
+    fn test_validate(#[case] given: AmountFilters, #[case] expected: &str) {
+        let result = given.validate("test", "test").unwrap_err();
+        assert!(result.to_string().contains(expected));
+    }
+
+    #[rstest]
+    #[case(filters(Some(dec!(100)), None, None, Some(dec!(3))))]
+    #[case(filters(None, Some(dec!(20)), Some(dec!(20)), None))]
+    #[case(filters(None, Some(dec!(-20)), Some(dec!(50)), None))]
+    #[case(signed(filters(None, Some(dec!(-50)), Some(dec!(20)), None)))]
+    #[case(filters(Some(dec!(30)), Some(dec!(20)), Some(dec!(50)), None))]
+    #[case(filters(Some(dec!(52)), Some(dec!(20)), Some(dec!(50)), Some(dec!(10))))]
+    #[case(exclusive(filters(None, Some(dec!(20)), Some(dec!(50)), None), true, true))]
+    #[case(exclusive(filters(Some(dec!(50)), Some(dec!(50)), None, Some(dec!(10))), true, false))]
+    #[case(whole_dollar(filters(Some(dec!(50.00)), None, None, None)))]
+    #[case(whole_dollar(filters(Some(dec!(49.99)), None, None, Some(dec!(1)))))]
+    fn test_validate_ok(#[case] given: AmountFilters) {
+        assert!(given.validate("test", "test").is_ok());
+    }
+}
@@ -4,13 +4,16 @@ use anyhow::{anyhow, Result};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Deserializer};
 
-use crate::rules::date_filter::{date_is_outside_range, validate_date_filters};
+use crate::rules::amount_filter::AmountFilters;
+use crate::rules::date_filter::{
+    date_is_outside_periods, date_is_outside_range, validate_date_filters, validate_periods,
+    Recurrence,
+};
 use crate::rules::eqregex::{deserialize_option_regex, EqRegex};
+use crate::rules::note::Note;
+use crate::rules::occurrence::Occurrence;
 use crate::NormalizedBankData;
 
-#[cfg(test)]
-use regex::Regex;
-
 /// Rules for specifying how to identify a category or memo for
 /// a given transaction.
 #[derive(Debug, Deserialize, PartialEq)]
@@ -18,20 +21,45 @@ use regex::Regex;
 pub struct CategoryAndMemoRules {
     /// The current name of this payee.
     payee: Option<String>,
+    /// The label of the account the transaction is from.
+    account: Option<String>,
     /// The current category of this payee.
     category: Option<String>,
+    /// Whether or not to ignore case when comparing the category.
+    #[serde(default)]
+    category_ignore_case: bool,
     /// The amount of the transaction.
     amount: Option<Decimal>,
+    /// How far (as a percentage of the amount) a transaction may be from the amount.
+    amount_percent_tolerance: Option<Decimal>,
     /// The lower range of the transaction amount.
     min_amount: Option<Decimal>,
     /// The upper range of the transaction amount.
     max_amount: Option<Decimal>,
+    /// Whether the amount must be strictly greater than the lower range.
+    #[serde(default)]
+    min_amount_exclusive: bool,
+    /// Whether the amount must be strictly less than the upper range.
+    #[serde(default)]
+    max_amount_exclusive: bool,
+    /// Whether to compare amounts with their sign rather than in absolute value.
+    #[serde(default)]
+    signed_amounts: bool,
+    /// Whether the amount must be a whole number of dollars (i.e. have no cents).
+    #[serde(default)]
+    whole_dollar: bool,
+    /// Whether or not to only match transactions that do not yet have a category.
+    #[serde(default)]
+    only_if_uncategorized: bool,
     /// Whether or not the amount can be income.
     #[serde(default = "true_value", rename = "IncomeOK")]
     income_ok: bool,
     /// The payee as originally given in the raw data.
     #[serde(default, deserialize_with = "deserialize_option_regex")]
     orig_payee: Option<EqRegex>,
+    /// A pattern that the memo already on the transaction must match.
+    #[serde(default, deserialize_with = "deserialize_option_regex")]
+    memo: Option<EqRegex>,
     /// The lowest date in the month that a transaction can have to identify as this payee.
     min_date_in_month: Option<u32>,
     /// The highest date in the month that a transaction can have to identify as this payee.
@@ -40,10 +68,26 @@ pub struct CategoryAndMemoRules {
     min_date_in_year: Option<(u32, u32)>,
     /// The highest date in the year that a transaction can have to identify as this payee.
     max_date_in_year: Option<(u32, u32)>,
+    /// The calendar quarters (1 to 4) that a transaction must be in to match.
+    #[serde(default)]
+    quarter: Vec<u32>,
+    /// The months (1 to 12) that a transaction must be in to match.
+    #[serde(default)]
+    months: Vec<u32>,
+    /// The cadence on which a transaction must recur to match.
+    recurrence: Option<Recurrence>,
+    /// Which occurrences of the payee within a run match.
+    occurrence: Option<Occurrence>,
+    /// A human-readable note describing this rule - not used for matching.
+    #[serde(default)]
+    note: Note,
+    /// Whether or not this rule is used - a disabled rule never matches.
+    #[serde(default = "true_value")]
+    enabled: bool,
 }
 
 /// The TRUTH!
-fn true_value() -> bool {
+pub fn true_value() -> bool {
     true
 }
 
@@ -51,62 +95,162 @@ impl CategoryAndMemoRules {
     /// Construct a new object - only needed for testing.
     #[cfg(test)]
     pub fn new(mapping: HashMap<String, String>) -> Self {
-        let payee = mapping.get("payee").and_then(|x| Some(x.to_owned()));
-        let category = mapping.get("category").and_then(|x| Some(x.to_owned()));
+        let payee = mapping.get("payee").map(|x| x.to_owned());
+        let account = mapping.get("account").map(|x| x.to_owned());
+        let category = mapping.get("category").map(|x| x.to_owned());
+        let category_ignore_case = mapping
+            .get("category_ignore_case")
+            .is_some_and(|x| x.to_lowercase() == "true");
         let amount = mapping
             .get("amount")
             .and_then(|x| Decimal::from_str_exact(x).ok());
+        let amount_percent_tolerance = mapping
+            .get("amount_percent_tolerance")
+            .and_then(|x| Decimal::from_str_exact(x).ok());
         let min_amount = mapping
             .get("min_amount")
             .and_then(|x| Decimal::from_str_exact(x).ok());
         let max_amount = mapping
             .get("max_amount")
             .and_then(|x| Decimal::from_str_exact(x).ok());
+        let min_amount_exclusive = mapping
+            .get("min_amount_exclusive")
+            .is_some_and(|x| x.to_lowercase() == "true");
+        let max_amount_exclusive = mapping
+            .get("max_amount_exclusive")
+            .is_some_and(|x| x.to_lowercase() == "true");
         let income_ok = mapping
             .get("income_ok")
             .is_none_or(|x| x.to_lowercase() == "true");
-        let orig_payee = mapping
-            .get("orig_payee")
-            .and_then(|x| Some(EqRegex(Regex::new(x).unwrap())));
-        let (min_date_in_month, max_date_in_month, min_date_in_year, max_date_in_year) =
+        let orig_payee = mapping.get("orig_payee").map(|x| EqRegex::new(x).unwrap());
+        let memo = mapping.get("memo").map(|x| EqRegex::new(x).unwrap());
+        let signed_amounts = mapping
+            .get("signed_amounts")
+            .is_some_and(|x| x.to_lowercase() == "true");
+        let whole_dollar = mapping
+            .get("whole_dollar")
+            .is_some_and(|x| x.to_lowercase() == "true");
+        let only_if_uncategorized = mapping
+            .get("only_if_uncategorized")
+            .is_some_and(|x| x.to_lowercase() == "true");
+        let note = Note(mapping.get("note").map(|x| x.to_owned()));
+        let enabled = mapping
+            .get("enabled")
+            .is_none_or(|x| x.to_lowercase() == "true");
+        let (quarter, months) = crate::rules::date_filter::process_period_mapping(&mapping);
+        let recurrence = crate::rules::date_filter::process_recurrence_mapping(&mapping);
+        let occurrence = match (mapping.get("nth"), mapping.get("every")) {
+            (Some(n), _) => Some(Occurrence::Nth(n.parse().unwrap())),
+            (None, Some(n)) => Some(Occurrence::Every(n.parse().unwrap())),
+            (None, None) => None,
+        };
+        let ((min_date_in_month, max_date_in_month), (min_date_in_year, max_date_in_year)) =
             crate::rules::date_filter::process_date_filter_mapping(mapping);
         CategoryAndMemoRules {
             payee,
+            account,
             category,
+            category_ignore_case,
             amount,
+            amount_percent_tolerance,
             min_amount,
             max_amount,
+            min_amount_exclusive,
+            max_amount_exclusive,
+            signed_amounts,
+            whole_dollar,
+            only_if_uncategorized,
             income_ok,
             orig_payee,
+            memo,
             min_date_in_month,
             max_date_in_month,
             min_date_in_year,
             max_date_in_year,
+            quarter,
+            months,
+            recurrence,
+            occurrence,
+            note,
+            enabled,
         }
     }
 
     /// Check if there is at least one item given for this object.
     fn check_at_least_one(&self) -> bool {
         self.payee.is_some()
+            || self.account.is_some()
             || self.category.is_some()
             || self.min_amount.is_some()
             || self.max_amount.is_some()
             || self.amount.is_some()
+            || self.whole_dollar
             || self.orig_payee.is_some()
+            || self.memo.is_some()
             || self.min_date_in_month.is_some()
             || self.max_date_in_month.is_some()
             || self.min_date_in_year.is_some()
             || self.max_date_in_year.is_some()
+            || !self.quarter.is_empty()
+            || !self.months.is_empty()
+            || self.recurrence.is_some()
+            || self.occurrence.is_some()
+    }
+
+    /// The label of the account to which this rule is restricted, if any.
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// Describe the category or memo this rule belongs to, including the rule's note if given.
+    pub fn describe(&self, name: &str) -> String {
+        self.note.describe(name)
+    }
+
+    /// Whether this rule depends on the other transactions in the run.
+    pub fn has_occurrence(&self) -> bool {
+        self.occurrence.is_some()
     }
 
     /// Determine if the given transaction matches this set of rules.
+    ///
+    /// A rule with an Occurrence never matches here, since that can only be
+    /// decided once every transaction has been read (see occurrence_matches).
     pub fn transaction_matches(&self, transaction: &NormalizedBankData) -> bool {
+        self.occurrence.is_none() && self.matches_apart_from_occurrence(transaction)
+    }
+
+    /// Determine if the given transaction, which is the given occurrence of its
+    /// payee in the run (starting from one), matches this rule's Occurrence and
+    /// the rest of its rules. A rule without an Occurrence never matches here.
+    pub fn occurrence_matches(&self, transaction: &NormalizedBankData, ordinal: usize) -> bool {
+        self.occurrence.is_some_and(|o| o.includes(ordinal))
+            && self.matches_apart_from_occurrence(transaction)
+    }
+
+    /// Determine if the given transaction matches every rule except the Occurrence.
+    fn matches_apart_from_occurrence(&self, transaction: &NormalizedBankData) -> bool {
+        // A disabled rule never matches.
+        if !self.enabled {
+            return false;
+        }
+
         // If a payee is provided and it does not match then
         // this transaction does not match.
         if self.payee.as_ref().is_some_and(|p| *p != transaction.payee) {
             return false;
         }
 
+        // If an account is provided and the transaction is not from it then
+        // this transaction does not match.
+        if self
+            .account
+            .as_ref()
+            .is_some_and(|a| *a != transaction.label)
+        {
+            return false;
+        }
+
         // If an original payee pattern is provided and it does not match then
         // this transaction does not match.
         if self
@@ -117,6 +261,16 @@ impl CategoryAndMemoRules {
             return false;
         }
 
+        // If a memo pattern is provided and it does not match then this
+        // transaction does not match. A transaction without a memo cannot match.
+        if self
+            .memo
+            .as_ref()
+            .is_some_and(|m| !transaction.memo.as_ref().is_some_and(|tm| m.is_match(tm)))
+        {
+            return false;
+        }
+
         // If a category pattern is provided and it does not match then
         // this transaction does not match. If no transaction category is provided
         // by default it cannot match.
@@ -124,28 +278,25 @@ impl CategoryAndMemoRules {
         if self
             .category
             .as_ref()
-            .is_some_and(|cc| cat.is_none() || cat.is_some_and(|tc| *tc != *cc))
+            .is_some_and(|cc| !cat.is_some_and(|tc| self.category_equals(cc, tc)))
         {
             return false;
         }
 
-        // If income is not OK but this is income then this transaction does not match.
-        if !self.income_ok && transaction.amount > Decimal::ZERO {
+        // If only uncategorized transactions are wanted but this one
+        // already has a (non-blank) category then this transaction does not match.
+        if self.only_if_uncategorized && cat.is_some_and(|tc| !tc.trim().is_empty()) {
             return false;
         }
 
-        // If a min or max transaction value is provided and is not in the range then
-        // this transaction does not match. Express in absolute value for user ease.
-        let min_amt = self.min_amount.unwrap_or(Decimal::ZERO).abs();
-        let max_amt = self.max_amount.unwrap_or(Decimal::MAX).abs();
-        let amt = transaction.amount.abs();
-        if !(amt >= min_amt && amt <= max_amt) {
+        // If income is not OK but this is income then this transaction does not match.
+        if !self.income_ok && transaction.amount > Decimal::ZERO {
             return false;
         }
 
-        // If the amount is not equal to the target the transcaction does not match.
-        // Express in absolute value for user ease.
-        if self.amount.is_some_and(|x| x.abs() != amt) {
+        // If a min, max, or exact transaction value is provided and is not
+        // satisfied then this transaction does not match.
+        if self.amount_filters().is_outside_range(transaction.amount) {
             return false;
         }
 
@@ -154,24 +305,65 @@ impl CategoryAndMemoRules {
             &transaction.date,
             (self.min_date_in_month, self.max_date_in_month),
             (self.min_date_in_year, self.max_date_in_year),
-        ) {
+        ) || date_is_outside_periods(&transaction.date, &self.quarter, &self.months)
+            || self
+                .recurrence
+                .as_ref()
+                .is_some_and(|r| r.is_off_cadence(&transaction.date))
+        {
             return false;
         }
 
         true
     }
 
+    /// Compare a rule category to a transaction category, ignoring surrounding
+    /// whitespace and (if requested) case.
+    fn category_equals(&self, rule_category: &str, transaction_category: &str) -> bool {
+        let rule_category = rule_category.trim();
+        let transaction_category = transaction_category.trim();
+        if self.category_ignore_case {
+            rule_category.to_lowercase() == transaction_category.to_lowercase()
+        } else {
+            rule_category == transaction_category
+        }
+    }
+
+    /// The amount filters given for this rule.
+    fn amount_filters(&self) -> AmountFilters {
+        AmountFilters {
+            amount: self.amount,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+            min_exclusive: self.min_amount_exclusive,
+            max_exclusive: self.max_amount_exclusive,
+            percent_tolerance: self.amount_percent_tolerance,
+            signed: self.signed_amounts,
+            whole_dollar: self.whole_dollar,
+        }
+    }
+
     /// Ensure the given rules are semantically correct.
     pub fn validate(&self, obj_type: &str, name: &str) -> Result<()> {
+        let name = &self.describe(name);
         if !self.check_at_least_one() {
             return Err(anyhow!("The {obj_type} {name:#?} must implement a rule."));
         }
+        self.amount_filters().validate(obj_type, name)?;
         validate_date_filters(
             obj_type,
             name,
             (self.min_date_in_month, self.max_date_in_month),
             (self.min_date_in_year, self.max_date_in_year),
-        )
+        )?;
+        validate_periods(obj_type, name, &self.quarter, &self.months)?;
+        if let Some(recurrence) = &self.recurrence {
+            recurrence.validate(obj_type, name)?;
+        }
+        if let Some(occurrence) = &self.occurrence {
+            occurrence.validate(obj_type, name)?;
+        }
+        Ok(())
     }
 }
 
@@ -201,7 +393,7 @@ where
     enum Wrapper {
         #[serde(deserialize_with = "vec_cat_memo_rules")]
         VecForm(Vec<CategoryAndMemoRules>),
-        ScalarForm(CategoryAndMemoRules),
+        ScalarForm(Box<CategoryAndMemoRules>),
     }
 
     // Choose the correct deserializer based on the data format.
@@ -210,7 +402,7 @@ where
         v.into_iter()
             .map(|(k, v)| match v {
                 Wrapper::VecForm(seq) => (k, seq),
-                Wrapper::ScalarForm(scalar) => (k, vec![scalar]),
+                Wrapper::ScalarForm(scalar) => (k, vec![*scalar]),
             })
             .collect(),
     ))
@@ -257,6 +449,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_quarter_must_be_valid() {
+        let given = vec![("quarter", "1,5")];
+        let result = CategoryAndMemoRules::new(as_hashmap(given)).validate("category", "test");
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "The category \"test\" specifies a Quarter that is not in [1, 4]."
+        );
+    }
+
+    #[test]
+    fn test_note_is_parsed_and_reported() {
+        let obj = CategoryAndMemoRules::new(as_hashmap(vec![("note", "Lunch")]));
+        assert_eq!(
+            obj.validate("category", "Dining").unwrap_err().to_string(),
+            "The category \"Dining (Lunch)\" must implement a rule."
+        );
+    }
+
     #[test]
     fn test_memos_must_give_at_least_one_rule() {
         let obj = CategoryAndMemoRules::new(HashMap::new());
@@ -273,11 +484,31 @@ mod test {
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
         true,
     )]
+    #[case(
+        vec![("payee", "ACE"), ("note", "Hardware store")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("payee", "ACE"), ("enabled", "false")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
     #[case(
         vec![("payee", "Target")],
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
         false,
     )]
+    #[case(
+        vec![("whole_dollar", "true")],
+        vec![("Payee", "XFER"), ("Date", "2024-04-03"), ("Amount", "-50.00")],
+        true,
+    )]
+    #[case(
+        vec![("whole_dollar", "true")],
+        vec![("Payee", "XFER"), ("Date", "2024-04-03"), ("Amount", "-49.99")],
+        false,
+    )]
     #[case(
         vec![("orig_payee", "^ACE")],
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
@@ -288,6 +519,21 @@ mod test {
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
         false,
     )]
+    #[case(
+        vec![("memo", "(?i)^check")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Memo", "CHECK 1234")],
+        true,
+    )]
+    #[case(
+        vec![("memo", "(?i)^check")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Memo", "Debit card")],
+        false,
+    )]
+    #[case(
+        vec![("memo", ".*")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
     #[case(
         vec![("category", "Hardware")],
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
@@ -303,6 +549,36 @@ mod test {
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Category", "Garden")],
         false,
     )]
+    #[case(
+        vec![("category", "Hardware")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Category", " Hardware  ")],
+        true,
+    )]
+    #[case(
+        vec![("category", "Hardware")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Category", "hardware")],
+        false,
+    )]
+    #[case(
+        vec![("category", "Hardware"), ("category_ignore_case", "true")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Category", " hardware ")],
+        true,
+    )]
+    #[case(
+        vec![("category", "Hardware"), ("category_ignore_case", "true")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Category", "Garden")],
+        false,
+    )]
+    #[case(
+        vec![("payee", "ACE"), ("only_if_uncategorized", "true")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("payee", "ACE"), ("only_if_uncategorized", "true")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Category", "Garden")],
+        false,
+    )]
     #[case(
         vec![("min_amount", "10.00"), ("max_amount", "20.00")],
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
@@ -338,6 +614,16 @@ mod test {
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
         false,
     )]
+    #[case(
+        vec![("amount", "100.00"), ("amount_percent_tolerance", "3")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-102.50")],
+        true,
+    )]
+    #[case(
+        vec![("amount", "100.00"), ("amount_percent_tolerance", "3")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-104.00")],
+        false,
+    )]
     #[case(
         vec![("min_date_in_month", "6")],
         vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
@@ -438,6 +724,36 @@ mod test {
         vec![("Payee", "ACE"), ("Date", "2024-11-24"), ("Amount", "-15.43")],
         false,
     )]
+    #[case(
+        vec![("quarter", "1")],
+        vec![("Payee", "ACE"), ("Date", "2024-02-14"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("quarter", "1")],
+        vec![("Payee", "ACE"), ("Date", "2024-05-14"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("months", "1,2,3,4,5,6")],
+        vec![("Payee", "ACE"), ("Date", "2024-05-14"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("payee", "ACE"), ("account", "testing")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("payee", "ACE"), ("account", "business")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("payee", "ACE"), ("nth", "1")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
     fn test_transaction_matches(
         #[case] given: Vec<(&str, &str)>,
         #[case] txn_data: Vec<(&str, &str)>,
@@ -447,4 +763,26 @@ mod test {
         let result = CategoryAndMemoRules::new(as_hashmap(given)).transaction_matches(&transaction);
         assert_eq!(result, expected);
     }
+
+    #[rstest]
+    #[case(vec![("payee", "ACE"), ("nth", "1")], 1, true)]
+    #[case(vec![("payee", "ACE"), ("nth", "1")], 2, false)]
+    #[case(vec![("payee", "ACE"), ("every", "2")], 4, true)]
+    #[case(vec![("payee", "ACE"), ("every", "2")], 3, false)]
+    #[case(vec![("payee", "Wendy's"), ("nth", "1")], 1, false)]
+    #[case(vec![("payee", "ACE")], 1, false)]
+    fn test_occurrence_matches(
+        #[case] given: Vec<(&str, &str)>,
+        #[case] ordinal: usize,
+        #[case] expected: bool,
+    ) {
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", "ACE"),
+            ("Date", "2024-04-03"),
+            ("Amount", "-15.43"),
+        ]));
+        let result =
+            CategoryAndMemoRules::new(as_hashmap(given)).occurrence_matches(&transaction, ordinal);
+        assert_eq!(result, expected);
+    }
 }
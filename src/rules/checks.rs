@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// How many years from the current date a transaction may be by default.
+const DEFAULT_MAX_DATE_SKEW_YEARS: u32 = 10;
+
+/// Sanity checks on the transactions read during a run.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Checks {
+    /// The most years that a transaction date may be before or after the current date.
+    #[serde(default = "default_max_date_skew_years")]
+    pub max_date_skew_years: u32,
+    /// What to do when a transaction date is further than that from the current date.
+    #[serde(default)]
+    pub date_skew: CheckAction,
+}
+
+/// Allow transactions from up to ten years ago by default.
+fn default_max_date_skew_years() -> u32 {
+    DEFAULT_MAX_DATE_SKEW_YEARS
+}
+
+impl Default for Checks {
+    fn default() -> Self {
+        Checks {
+            max_date_skew_years: DEFAULT_MAX_DATE_SKEW_YEARS,
+            date_skew: CheckAction::default(),
+        }
+    }
+}
+
+impl Checks {
+    /// Ensure the given checks are semantically correct.
+    pub fn validate(&self) -> Result<()> {
+        if self.max_date_skew_years == 0 {
+            return Err(anyhow!("The max_date_skew_years option must be positive."));
+        }
+        Ok(())
+    }
+}
+
+/// What to do when a sanity check fails.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckAction {
+    /// Report the problem and continue.
+    #[default]
+    Warn,
+    /// Stop before any files are written or moved.
+    Error,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("", 10, CheckAction::Warn)]
+    #[case("max_date_skew_years = 2", 2, CheckAction::Warn)]
+    #[case("date_skew = \"error\"", 10, CheckAction::Error)]
+    fn test_checks(#[case] given: &str, #[case] years: u32, #[case] action: CheckAction) {
+        let result: Checks = toml::from_str(given).unwrap();
+        assert_eq!(result.max_date_skew_years, years);
+        assert_eq!(result.date_skew, action);
+        assert!(result.validate().is_ok());
+    }
+
+    #[test]
+    fn test_checks_must_allow_some_skew() {
+        let result: Checks = toml::from_str("max_date_skew_years = 0").unwrap();
+        assert_eq!(
+            result.validate().unwrap_err().to_string(),
+            "The max_date_skew_years option must be positive."
+        );
+    }
+}
@@ -2,6 +2,9 @@ use std::cmp::min;
 
 use anyhow::{anyhow, Result};
 use chrono::{Datelike, NaiveDate};
+use serde::Deserialize;
+
+use crate::timestamps::deserialize_date;
 
 #[cfg(test)]
 use std::collections::HashMap;
@@ -14,6 +17,68 @@ pub fn date_is_outside_range(date: &impl Datelike, month: MonthFilters, year: Ye
     date_is_ouside_range_in_month(date, month) || date_is_ouside_range_in_year(date, year)
 }
 
+/// Assess if the date is outside the given calendar quarters or months.
+/// An empty list of quarters or months does not restrict the date.
+pub fn date_is_outside_periods(date: &impl Datelike, quarters: &[u32], months: &[u32]) -> bool {
+    let month = date.month();
+    let quarter = (month - 1) / 3 + 1;
+    (!quarters.is_empty() && !quarters.contains(&quarter))
+        || (!months.is_empty() && !months.contains(&month))
+}
+
+/// A cadence on which a transaction is expected to recur, such as every other Friday.
+#[derive(Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub struct Recurrence {
+    /// How many weeks there are between each transaction.
+    every_n_weeks: u32,
+    /// A date on which the transaction occurred.
+    #[serde(deserialize_with = "deserialize_date")]
+    anchor: NaiveDate,
+    /// How many days a transaction may be from the cadence and still match.
+    #[serde(default = "default_tolerance_days")]
+    tolerance_days: u32,
+}
+
+/// Allow transactions to post a day early or late by default.
+fn default_tolerance_days() -> u32 {
+    1
+}
+
+impl Recurrence {
+    /// Construct a new object - only needed for testing.
+    #[cfg(test)]
+    pub fn new(every_n_weeks: u32, anchor: NaiveDate, tolerance_days: u32) -> Self {
+        Recurrence {
+            every_n_weeks,
+            anchor,
+            tolerance_days,
+        }
+    }
+
+    /// Assess if the date does not fall on the cadence (within the tolerance).
+    pub fn is_off_cadence(&self, date: &NaiveDate) -> bool {
+        let period = 7 * i64::from(self.every_n_weeks);
+        let offset = (*date - self.anchor).num_days().rem_euclid(period);
+        min(offset, period - offset) > i64::from(self.tolerance_days)
+    }
+
+    /// Ensure the recurrence is semantically correct.
+    pub fn validate(&self, obj_type: &str, name: &str) -> Result<()> {
+        if self.every_n_weeks == 0 {
+            return Err(anyhow!(
+                "The {obj_type} {name:#?} specifies a Recurrence where EveryNWeeks is not positive."
+            ));
+        }
+        if 2 * u64::from(self.tolerance_days) >= 7 * u64::from(self.every_n_weeks) {
+            return Err(anyhow!(
+                "The {obj_type} {name:#?} specifies a Recurrence where ToleranceDays would match every date."
+            ));
+        }
+        Ok(())
+    }
+}
+
 /// Assess if the date is outside the in-month range.
 fn date_is_ouside_range_in_month(date: &impl Datelike, month_filters: MonthFilters) -> bool {
     // Extract the date within the month from the date object.
@@ -106,12 +171,7 @@ fn last_date_in_month_raw(month: u32) -> u32 {
 /// Parse date filter date - for testing only.
 pub fn process_date_filter_mapping(
     mapping: HashMap<String, String>,
-) -> (
-    Option<u32>,
-    Option<u32>,
-    Option<(u32, u32)>,
-    Option<(u32, u32)>,
-) {
+) -> (MonthFilters, YearFilters) {
     let min_date_in_month = mapping
         .get("min_date_in_month")
         .and_then(|x| x.parse().ok());
@@ -120,30 +180,50 @@ pub fn process_date_filter_mapping(
         .and_then(|x| x.parse().ok());
     let min_date_in_year = mapping
         .get("min_date_in_year")
-        .and_then(|x| Some(x.split('/').collect::<Vec<_>>()))
+        .map(|x| x.split('/').collect::<Vec<_>>())
         .map(|x| {
             (
-                x.get(0).unwrap().parse().unwrap(),
+                x.first().unwrap().parse().unwrap(),
                 x.get(1).unwrap().parse().unwrap(),
             )
         });
     let max_date_in_year = mapping
         .get("max_date_in_year")
-        .and_then(|x| Some(x.split('/').collect::<Vec<_>>()))
+        .map(|x| x.split('/').collect::<Vec<_>>())
         .map(|x| {
             (
-                x.get(0).unwrap().parse().unwrap(),
+                x.first().unwrap().parse().unwrap(),
                 x.get(1).unwrap().parse().unwrap(),
             )
         });
     (
-        min_date_in_month,
-        max_date_in_month,
-        min_date_in_year,
-        max_date_in_year,
+        (min_date_in_month, max_date_in_month),
+        (min_date_in_year, max_date_in_year),
     )
 }
 
+#[cfg(test)]
+/// Parse the comma-separated quarters and months - for testing only.
+pub fn process_period_mapping(mapping: &HashMap<String, String>) -> (Vec<u32>, Vec<u32>) {
+    let parse = |key: &str| {
+        mapping.get(key).map_or(vec![], |x| {
+            x.split(',').map(|x| x.trim().parse().unwrap()).collect()
+        })
+    };
+    (parse("quarter"), parse("months"))
+}
+
+#[cfg(test)]
+/// Parse the recurrence - for testing only.
+pub fn process_recurrence_mapping(mapping: &HashMap<String, String>) -> Option<Recurrence> {
+    let every_n_weeks = mapping.get("every_n_weeks")?.parse().unwrap();
+    let anchor = NaiveDate::parse_from_str(mapping.get("anchor")?, crate::DATE_FORMAT).unwrap();
+    let tolerance_days = mapping
+        .get("tolerance_days")
+        .map_or(default_tolerance_days(), |x| x.parse().unwrap());
+    Some(Recurrence::new(every_n_weeks, anchor, tolerance_days))
+}
+
 /// Ensure the given rules are semantically correct.
 pub fn validate_date_filters(
     obj_type: &str,
@@ -198,12 +278,110 @@ pub fn validate_date_filters(
     Ok(())
 }
 
+/// Ensure the given quarters and months exist.
+pub fn validate_periods(
+    obj_type: &str,
+    name: &str,
+    quarters: &[u32],
+    months: &[u32],
+) -> Result<()> {
+    if quarters.iter().any(|x| !(1..=4).contains(x)) {
+        return Err(anyhow!(
+            "The {obj_type} {name:#?} specifies a Quarter that is not in [1, 4]."
+        ));
+    }
+    if months.iter().any(|x| !(1..=12).contains(x)) {
+        return Err(anyhow!(
+            "The {obj_type} {name:#?} specifies a Month that is not in [1, 12]."
+        ));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     use rstest::rstest;
 
+    // Anchored to a Friday, so every other Friday is on the cadence.
+    #[rstest]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(), 2, 1, false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 19).unwrap(), 2, 1, false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 18).unwrap(), 2, 1, false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 20).unwrap(), 2, 1, false)]
+    #[case(NaiveDate::from_ymd_opt(2023, 12, 22).unwrap(), 2, 1, false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 22).unwrap(), 2, 1, true)]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 22).unwrap(), 2, 3, false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 12).unwrap(), 2, 1, true)]
+    #[case(NaiveDate::from_ymd_opt(2023, 12, 29).unwrap(), 2, 1, true)]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 12).unwrap(), 1, 1, false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 1, 9).unwrap(), 1, 1, true)]
+    fn test_is_off_cadence(
+        #[case] given: NaiveDate,
+        #[case] weeks: u32,
+        #[case] tolerance: u32,
+        #[case] expected: bool,
+    ) {
+        let anchor = NaiveDate::from_ymd_opt(2024, 1, 5).unwrap();
+        let recurrence = Recurrence::new(weeks, anchor, tolerance);
+        assert_eq!(recurrence.is_off_cadence(&given), expected);
+    }
+
+    #[rstest]
+    #[case(Recurrence::new(0, NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(), 1), "EveryNWeeks is not positive")]
+    #[case(Recurrence::new(1, NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(), 4), "ToleranceDays would match every date")]
+    #[case(Recurrence::new(2, NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(), 7), "ToleranceDays would match every date")]
+    #[case(Recurrence::new(1, NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(), u32::MAX), "ToleranceDays would match every date")]
+    fn test_validate_recurrence(#[case] given: Recurrence, #[case] expected: &str) {
+        let result = given.validate("test", "test").unwrap_err();
+        assert!(result.to_string().contains(expected));
+    }
+
+    #[rstest]
+    #[case(NaiveDate::from_ymd_opt(2024, 2, 14).unwrap(), vec![1], vec![], false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 5, 14).unwrap(), vec![1], vec![], true)]
+    #[case(NaiveDate::from_ymd_opt(2024, 12, 31).unwrap(), vec![1, 4], vec![], false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(), vec![], vec![1, 2, 3, 4, 5, 6], true)]
+    #[case(NaiveDate::from_ymd_opt(2024, 6, 30).unwrap(), vec![], vec![1, 2, 3, 4, 5, 6], false)]
+    #[case(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), vec![1], vec![2], true)]
+    #[case(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), vec![], vec![], false)]
+    fn test_date_is_outside_periods(
+        #[case] given: NaiveDate,
+        #[case] quarters: Vec<u32>,
+        #[case] months: Vec<u32>,
+        #[case] expected: bool,
+    ) {
+        let result = date_is_outside_periods(&given, &quarters, &months);
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case(vec![0], vec![], Some("Quarter that is not in [1, 4]"))]
+    #[case(vec![5], vec![], Some("Quarter that is not in [1, 4]"))]
+    #[case(vec![], vec![13], Some("Month that is not in [1, 12]"))]
+    #[case(vec![1, 4], vec![1, 12], None)]
+    fn test_validate_periods(
+        #[case] quarters: Vec<u32>,
+        #[case] months: Vec<u32>,
+        #[case] expected: Option<&str>,
+    ) {
+        let result = validate_periods("test", "test", &quarters, &months);
+        match expected {
+            Some(message) => assert!(result.unwrap_err().to_string().contains(message)),
+            None => assert!(result.is_ok()),
+        }
+    }
+
+    #[test]
+    fn test_recurrence_is_parsed() {
+        let result: Recurrence =
+            toml::from_str("EveryNWeeks = 2\nAnchor = \"2024-01-05\"").unwrap();
+        let anchor = NaiveDate::from_ymd_opt(2024, 1, 5).unwrap();
+        assert_eq!(result, Recurrence::new(2, anchor, 1));
+        assert!(result.validate("test", "test").is_ok());
+    }
+
     #[rstest]
     #[case(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(), 31)]
     #[case(NaiveDate::from_ymd_opt(2024, 2, 1).unwrap(), 29)]
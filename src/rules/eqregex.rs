@@ -6,25 +6,39 @@ use serde::{Deserialize, Deserializer};
 
 /// A regex object that can be tested for equality and used as a HashMap key.
 #[derive(Debug)]
-pub struct EqRegex(pub Regex);
+pub struct EqRegex {
+    /// The regex as given.
+    regex: Regex,
+    /// The same regex, anchored so that it only matches the entire text.
+    whole: Regex,
+}
 
 impl Hash for EqRegex {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.0.as_str().hash(state);
+        self.regex.as_str().hash(state);
     }
 }
 
 impl PartialEq for EqRegex {
     fn eq(&self, other: &Self) -> bool {
-        self.0.as_str() == other.0.as_str()
+        self.regex.as_str() == other.regex.as_str()
     }
 }
 
 impl Eq for EqRegex {}
 
-impl From<Regex> for EqRegex {
-    fn from(regex: Regex) -> Self {
-        Self(regex)
+impl EqRegex {
+    /// Compile the given pattern.
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            regex: Regex::new(pattern)?,
+            whole: Regex::new(&format!("^(?:{pattern})$"))?,
+        })
+    }
+
+    /// Determine if the regex matches the entire text rather than just part of it.
+    pub fn is_whole_match(&self, text: &str) -> bool {
+        self.whole.is_match(text)
     }
 }
 
@@ -32,7 +46,7 @@ impl Deref for EqRegex {
     type Target = Regex;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.regex
     }
 }
 
@@ -42,8 +56,7 @@ where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
-    let regex = Regex::new(&s).map_err(serde::de::Error::custom)?;
-    Ok(EqRegex::from(regex))
+    EqRegex::new(&s).map_err(serde::de::Error::custom)
 }
 
 /// Instructions on how to deserialize an option regex object.
@@ -53,10 +66,47 @@ where
 {
     let s = Option::<String>::deserialize(deserializer)?;
     match s {
-        Some(s) => {
-            let regex = Regex::new(&s).map_err(serde::de::Error::custom)?;
-            Ok(Some(EqRegex::from(regex)))
-        }
+        Some(s) => EqRegex::new(&s).map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Instructions on how to deserialize either one regex object or a list of them.
+pub fn deserialize_option_regex_list<'de, D>(
+    deserializer: D,
+) -> Result<Option<Vec<EqRegex>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let patterns = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    };
+    patterns
+        .iter()
+        .map(|s| EqRegex::new(s).map_err(serde::de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+/// Instructions on how to deserialize an option literal string as a regex object.
+/// The string is escaped so that it only ever matches itself.
+pub fn deserialize_option_literal<'de, D>(deserializer: D) -> Result<Option<EqRegex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = Option::<String>::deserialize(deserializer)?;
+    match s {
+        Some(s) => EqRegex::new(&regex::escape(&s))
+            .map(Some)
+            .map_err(serde::de::Error::custom),
         None => Ok(None),
     }
 }
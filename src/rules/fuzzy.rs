@@ -0,0 +1,96 @@
+use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// The similarity required for a fuzzy match when none is given.
+pub const DEFAULT_MIN_SIMILARITY: Decimal = dec!(0.8);
+
+/// Find how similar the closest run of words in the text is to the target.
+/// Each run has as many words as the target, and words are compared ignoring
+/// case. The similarity is one minus the Levenshtein distance divided by the
+/// length of the longer string, so 1 is identical and 0 is nothing in common.
+pub fn best_similarity(target: &str, text: &str) -> Decimal {
+    let target: Vec<String> = target.split_whitespace().map(str::to_uppercase).collect();
+    let words: Vec<String> = text.split_whitespace().map(str::to_uppercase).collect();
+    if target.is_empty() || words.is_empty() {
+        return Decimal::ZERO;
+    }
+    let target = target.join(" ");
+    let size = words.len().min(target.split(' ').count());
+    words
+        .windows(size)
+        .map(|run| similarity(&target, &run.join(" ")))
+        .max()
+        .unwrap_or_default()
+}
+
+/// The similarity of two strings, from 0 to 1.
+fn similarity(a: &str, b: &str) -> Decimal {
+    let longest = a.chars().count().max(b.chars().count());
+    if longest == 0 {
+        return Decimal::ONE;
+    }
+    Decimal::ONE - Decimal::from(levenshtein(a, b)) / Decimal::from(longest)
+}
+
+/// The number of single-character insertions, deletions, or substitutions
+/// needed to turn one string into the other.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Ensure a minimum similarity is in the range that can be reached.
+pub fn validate_min_similarity(obj_type: &str, name: &str, value: Decimal) -> Result<()> {
+    if value <= Decimal::ZERO || value > Decimal::ONE {
+        return Err(anyhow!(
+            "The {obj_type} {name:#?} must have a MinSimilarity greater than 0 and at most 1, found {value}."
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("", "", 0)]
+    #[case("STARBUCKS", "STARBUCKS", 0)]
+    #[case("STARBUCKS", "STARBUCS", 1)]
+    #[case("kitten", "sitting", 3)]
+    #[case("", "abc", 3)]
+    fn test_levenshtein(#[case] a: &str, #[case] b: &str, #[case] expected: usize) {
+        assert_eq!(levenshtein(a, b), expected);
+        assert_eq!(levenshtein(b, a), expected);
+    }
+
+    #[rstest]
+    #[case("STARBUCKS", "STARBUCKS #123", dec!(1))]
+    #[case("starbucks", "STARBUCS #123 SEATTLE", dec!(8) / dec!(9))]
+    #[case("WHOLE FOODS", "PURCHASE WHOLE FOOD MKT", dec!(10) / dec!(11))]
+    #[case("STARBUCKS", "", dec!(0))]
+    fn test_best_similarity(#[case] target: &str, #[case] text: &str, #[case] expected: Decimal) {
+        assert_eq!(best_similarity(target, text), expected);
+    }
+
+    #[rstest]
+    #[case(dec!(0.8), true)]
+    #[case(dec!(1), true)]
+    #[case(dec!(0), false)]
+    #[case(dec!(1.5), false)]
+    fn test_validate_min_similarity(#[case] value: Decimal, #[case] ok: bool) {
+        assert_eq!(validate_min_similarity("payee", "test", value).is_ok(), ok);
+    }
+}
@@ -0,0 +1,29 @@
+use serde::Deserialize;
+
+/// Commands to run at points during a run.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// A shell command to run after the new transactions and timestamps are written.
+    pub post_run: Option<String>,
+    /// Whether or not the run fails if the post_run command fails.
+    #[serde(default)]
+    pub post_run_fatal: bool,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("", None, false)]
+    #[case("post_run = \"sync.sh\"", Some("sync.sh"), false)]
+    #[case("post_run = \"sync.sh\"\npost_run_fatal = true", Some("sync.sh"), true)]
+    fn test_hooks(#[case] given: &str, #[case] post_run: Option<&str>, #[case] fatal: bool) {
+        let result: Hooks = toml::from_str(given).unwrap();
+        assert_eq!(result.post_run.as_deref(), post_run);
+        assert_eq!(result.post_run_fatal, fatal);
+    }
+}
@@ -0,0 +1,253 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use toml::{Table, Value};
+
+use crate::rules::paths::normalize_path;
+
+/// The sections whose entries are lists of rules that may be combined across files.
+const RULE_SECTIONS: [&str; 4] = ["payees", "categories", "memos", "ignore"];
+
+/// Parse rules data, merging in any files listed under the include key.
+///
+/// Included files are resolved relative to the directory of the file that
+/// includes them, and may themselves include other files. Entries of the
+/// rule sections are combined, arrays (such as the account mappings) are
+/// concatenated, and any other key given in more than one file is an error.
+pub fn parse_with_includes<T: DeserializeOwned>(raw_data: &str, dir: &Path) -> Result<T> {
+    let table: Table = toml::from_str(raw_data)?;
+
+    // Without includes, parse directly so that errors report their location.
+    if !table.contains_key("include") {
+        return Ok(toml::from_str(raw_data)?);
+    }
+
+    let merged = resolve_includes(table, dir, &mut vec![])?;
+    Ok(Value::Table(merged).try_into()?)
+}
+
+/// Replace the include key of the table with the contents of the files it lists.
+fn resolve_includes(mut table: Table, dir: &Path, seen: &mut Vec<PathBuf>) -> Result<Table> {
+    let Some(includes) = table.remove("include") else {
+        return Ok(table);
+    };
+    let Value::Array(includes) = includes else {
+        return Err(anyhow!("The include key must be a list of file names."));
+    };
+
+    for include in includes {
+        let Value::String(include) = include else {
+            return Err(anyhow!("The include key must be a list of file names."));
+        };
+        let path = dir.join(normalize_path(&include)?);
+
+        // The same file can be written many ways, so compare resolved paths.
+        let unreadable = |e| anyhow!("Cannot read the included rules file {:#?}: {e}", path);
+        let path = fs::canonicalize(&path).map_err(unreadable)?;
+        if seen.contains(&path) {
+            return Err(anyhow!("The rules file {:#?} includes itself.", path));
+        }
+        let raw_data = fs::read_to_string(&path).map_err(unreadable)?;
+        let fragment: Table = toml::from_str(&raw_data)
+            .map_err(|e| anyhow!("Cannot parse the included rules file {:#?}: {e}", path))?;
+
+        // Resolve any nested includes before merging.
+        seen.push(path.clone());
+        let parent = path.parent().unwrap_or(dir).to_path_buf();
+        let fragment = resolve_includes(fragment, &parent, seen)?;
+        seen.pop();
+
+        merge_tables(&mut table, fragment)?;
+    }
+
+    Ok(table)
+}
+
+/// Merge the sections of one rules table into another.
+fn merge_tables(base: &mut Table, other: Table) -> Result<()> {
+    for (section, value) in other {
+        match (base.get_mut(&section), value) {
+            (None, value) => {
+                base.insert(section, value);
+            }
+            (Some(Value::Table(existing)), Value::Table(new)) => {
+                merge_section(&section, existing, new)?;
+            }
+            _ => {
+                return Err(anyhow!(
+                    "The key {section:#?} is given in more than one rules file."
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Merge the entries of one section into the same section from another file.
+fn merge_section(section: &str, base: &mut Table, other: Table) -> Result<()> {
+    for (key, value) in other {
+        let merged = match base.remove(&key) {
+            None => value,
+            Some(existing) if RULE_SECTIONS.contains(&section) => {
+                Value::Array([as_array(existing), as_array(value)].concat())
+            }
+            Some(Value::Array(mut existing)) if value.is_array() => {
+                existing.extend(as_array(value));
+                Value::Array(existing)
+            }
+            Some(_) => {
+                return Err(anyhow!(
+                    "The key \"{section}.{key}\" is given in more than one rules file."
+                ))
+            }
+        };
+        base.insert(key, merged);
+    }
+    Ok(())
+}
+
+/// Treat a single value as a list of one value.
+fn as_array(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(values) => values,
+        value => vec![value],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// Write the given files into a temporary directory.
+    fn write_files(files: &[(&str, &str)]) -> tempdir::TempDir {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        for (name, contents) in files {
+            let path = temp.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        temp
+    }
+
+    #[test]
+    fn test_without_includes() {
+        let result: Table =
+            parse_with_includes("[payees]\nApple = \"APPLE\"\n", Path::new("")).unwrap();
+        assert_eq!(
+            result,
+            toml::from_str("[payees]\nApple = \"APPLE\"\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_includes_are_merged() {
+        let temp = write_files(&[
+            (
+                "payees.toml",
+                indoc! { r#"
+                [payees]
+                Ace = "ACE HARDWARE"
+                Apple = {Pattern = "APPLE", Amount = 2.99}
+                "# },
+            ),
+            (
+                "accounts/mappings.toml",
+                indoc! { r#"
+                [[mappings.csv]]
+                label = "savings"
+                identify = ["Date", "Payee", "Amount"]
+                "# },
+            ),
+        ]);
+        let base = indoc! { r#"
+        include = ["payees.toml", "accounts/mappings.toml"]
+
+        [payees]
+        Apple = "APPLE.COM"
+
+        [[mappings.csv]]
+        label = "checking"
+        identify = ["Date", "Description", "Amount"]
+        "# };
+
+        let result: Table = parse_with_includes(base, temp.path()).unwrap();
+        let expected: Table = toml::from_str(indoc! { r#"
+        [payees]
+        Ace = "ACE HARDWARE"
+        Apple = ["APPLE.COM", {Pattern = "APPLE", Amount = 2.99}]
+
+        [[mappings.csv]]
+        label = "checking"
+        identify = ["Date", "Description", "Amount"]
+
+        [[mappings.csv]]
+        label = "savings"
+        identify = ["Date", "Payee", "Amount"]
+        "# })
+        .unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_nested_includes_are_relative() {
+        let temp = write_files(&[
+            ("rules/first.toml", "include = [\"second.toml\"]\n"),
+            ("rules/second.toml", "[payees]\nApple = \"APPLE\"\n"),
+        ]);
+        let result: Table =
+            parse_with_includes("include = [\"rules/first.toml\"]\n", temp.path()).unwrap();
+        assert_eq!(
+            result,
+            toml::from_str("[payees]\nApple = \"APPLE\"\n").unwrap()
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "[paths]\nstorage = \"/a\"\n",
+        "[paths]\nstorage = \"/b\"\n",
+        "The key \"paths.storage\" is given in more than one rules file."
+    )]
+    #[case(
+        "output = 5\n",
+        "[output]\nsort_order = \"ascending\"\n",
+        "The key \"output\" is given in more than one rules file."
+    )]
+    fn test_conflicts(#[case] base: &str, #[case] fragment: &str, #[case] expected: &str) {
+        let temp = write_files(&[("fragment.toml", fragment)]);
+        let base = format!("include = [\"fragment.toml\"]\n{base}");
+        let result = parse_with_includes::<Table>(&base, temp.path()).unwrap_err();
+        assert_eq!(result.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case("include = [\"loop.toml\"]\n")]
+    #[case("include = [\"./sub/../loop.toml\"]\n")]
+    #[case("include = [\"second.toml\"]\n")]
+    fn test_cannot_include_self(#[case] contents: &str) {
+        let temp = write_files(&[
+            ("loop.toml", contents),
+            ("second.toml", "include = [\"sub/../loop.toml\"]\n"),
+            ("sub/empty.toml", ""),
+        ]);
+        let result =
+            parse_with_includes::<Table>("include = [\"loop.toml\"]\n", temp.path()).unwrap_err();
+        assert!(result.to_string().ends_with("includes itself."));
+    }
+
+    #[test]
+    fn test_missing_include() {
+        let temp = write_files(&[]);
+        let result = parse_with_includes::<Table>("include = [\"missing.toml\"]\n", temp.path())
+            .unwrap_err();
+        assert!(result
+            .to_string()
+            .starts_with("Cannot read the included rules file"));
+    }
+}
@@ -1,8 +1,14 @@
 use std::collections::HashMap;
+use std::path::Path;
 
 use anyhow::{anyhow, Result};
+use rust_decimal::Decimal;
 use serde::Deserialize;
 
+use crate::columns;
+use crate::rules::eqregex::{deserialize_option_regex, deserialize_regex, EqRegex};
+use crate::TidymoneyError;
+
 /// Rules for how to identify CSV columns to accounts, and how
 /// to map those column names to output column names.
 #[derive(Debug, Deserialize, PartialEq)]
@@ -10,8 +16,20 @@ use serde::Deserialize;
 pub struct MappingRulesCsv {
     /// The label to give this account type.
     pub label: String,
+    /// The name (without the extension) of the output file, if not the label.
+    output_name: Option<String>,
     /// The columns to expect from this account type.
+    #[serde(default)]
     identify: Vec<String>,
+    /// Whether or not the files for this account type begin with a header row.
+    #[serde(default = "default_has_headers")]
+    pub has_headers: bool,
+    /// The name of each column by position, for files without a header row.
+    #[serde(default)]
+    columns: Vec<String>,
+    /// The pattern that names of files without a header row match.
+    #[serde(default, deserialize_with = "deserialize_option_regex")]
+    filename_pattern: Option<EqRegex>,
     /// How to map the given column names to output column names.
     translate: Option<RemapValuesCsv>,
     /// The format string for dates for this rule set.
@@ -20,6 +38,75 @@ pub struct MappingRulesCsv {
     /// Whether or not we need to negate the value of a transaction.
     #[serde(rename = "debit_is_positive", default)]
     pub negate: bool,
+    /// A column value for which the amount of the row is negated.
+    negate_when: Option<NegateWhen>,
+    /// A column from which to extract the amount, for files without an amount column.
+    amount_from: Option<AmountFrom>,
+    /// Whether or not the amounts are given as a whole number of cents.
+    #[serde(default)]
+    pub amount_in_cents: bool,
+    /// A multiplier to apply to amounts (e.g. 1000 if amounts are in thousands).
+    pub amount_scale: Option<Decimal>,
+    /// Whether or not to derive the amount from the change in the Balance column.
+    #[serde(default)]
+    pub amount_from_balance: bool,
+    /// Whether or not to decide which transactions are new by the PostedDate column.
+    #[serde(default)]
+    pub filter_on_posted_date: bool,
+    /// The balance of the account before the earliest transaction in its files.
+    pub starting_balance: Option<Decimal>,
+    /// The category of each transaction that has none after the rules are applied.
+    pub default_category: Option<String>,
+    /// Whether or not to strip surrounding whitespace and quotes from each value.
+    #[serde(default)]
+    trim_values: bool,
+    /// The quote characters to strip from each value when trimming.
+    #[serde(default = "default_quote_chars")]
+    quote_chars: String,
+    /// How the headers of a file are normalized before comparing them to identify.
+    #[serde(default)]
+    header_normalization: HeaderNormalization,
+    /// How rows whose amount is blank are counted when they are skipped.
+    #[serde(default)]
+    pub blank_amounts: BlankAmounts,
+}
+
+/// How to treat rows whose amount is blank rather than zero.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum BlankAmounts {
+    /// Treat a blank amount as zero, so the row is skipped as a zero amount.
+    #[default]
+    Zero,
+    /// Skip the row, counting it separately from genuine zero amounts.
+    Skip,
+}
+
+/// How header names are changed before they are compared to the expected names.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderNormalization {
+    /// Headers must match exactly.
+    #[default]
+    Exact,
+    /// Surrounding whitespace is ignored.
+    Trim,
+    /// Differences in case are ignored.
+    Lowercase,
+    /// Both surrounding whitespace and differences in case are ignored.
+    TrimLowercase,
+}
+
+impl HeaderNormalization {
+    /// Apply this normalization to the given header.
+    pub fn apply(&self, header: &str) -> String {
+        match self {
+            HeaderNormalization::Exact => header.to_owned(),
+            HeaderNormalization::Trim => header.trim().to_owned(),
+            HeaderNormalization::Lowercase => header.to_lowercase(),
+            HeaderNormalization::TrimLowercase => header.trim().to_lowercase(),
+        }
+    }
 }
 
 /// The default format string to use if not specified.
@@ -27,6 +114,16 @@ fn default_fmt_string() -> String {
     "%Y-%m-%d".to_owned()
 }
 
+/// Files are assumed to have a header row if not specified.
+fn default_has_headers() -> bool {
+    true
+}
+
+/// The default quote characters to strip if not specified.
+fn default_quote_chars() -> String {
+    "\"'\u{201C}\u{201D}\u{2018}\u{2019}".to_owned()
+}
+
 impl MappingRulesCsv {
     /// Construct a new object - only needed for testing.
     #[cfg(test)]
@@ -43,29 +140,155 @@ impl MappingRulesCsv {
         let category = translate.get("category");
         let memo = translate.get("memo");
         let check = translate.get("check");
+        let address = translate.get("address");
+        let debit = translate.get("debit");
+        let credit = translate.get("credit");
+        let balance = translate.get("balance");
+        let posted_date = translate.get("posted_date");
         MappingRulesCsv {
             label,
+            output_name: None,
             identify,
+            has_headers: true,
+            columns: vec![],
+            filename_pattern: None,
             translate: (!translate.is_empty()).then_some(RemapValuesCsv {
-                payee: payee.and_then(|x| Some(x.to_owned())),
-                date: date.and_then(|x| Some(x.to_owned())),
-                amount: amount.and_then(|x| Some(x.to_owned())),
-                category: category.and_then(|x| Some(x.to_owned())),
-                memo: memo.and_then(|x| Some(x.to_owned())),
-                check: check.and_then(|x| Some(x.to_owned())),
+                payee: payee.map(|x| x.to_owned()),
+                date: date.map(|x| x.to_owned()),
+                amount: amount.map(|x| x.to_owned()),
+                category: category.map(|x| x.to_owned()),
+                memo: memo.map(|x| x.to_owned()),
+                check: check.map(|x| x.to_owned()),
+                address: address.map(|x| x.to_owned()),
+                debit: debit.map(|x| x.to_owned()),
+                credit: credit.map(|x| x.to_owned()),
+                balance: balance.map(|x| x.to_owned()),
+                posted_date: posted_date.map(|x| x.to_owned()),
             }),
             date_fmt: date_fmt.unwrap_or(default_fmt_string()),
             negate,
+            negate_when: None,
+            amount_from: None,
+            amount_in_cents: false,
+            amount_scale: None,
+            amount_from_balance: false,
+            filter_on_posted_date: false,
+            starting_balance: None,
+            default_category: None,
+            trim_values: false,
+            quote_chars: default_quote_chars(),
+            header_normalization: HeaderNormalization::Exact,
+            blank_amounts: BlankAmounts::Zero,
         }
     }
 
+    /// The name (without the extension) of the files written for this account.
+    pub fn output_name(&self) -> &str {
+        self.output_name.as_deref().unwrap_or(&self.label)
+    }
+
     /// Check if the given header matches these rules.
     pub fn header_matches(&self, headers: &Vec<String>) -> bool {
-        self.identify == *headers
+        let normalize = |x: &String| self.header_normalization.apply(x);
+        self.has_headers
+            && self.identify.len() == headers.len()
+            && self
+                .identify
+                .iter()
+                .zip(headers)
+                .all(|(expected, given)| normalize(expected) == normalize(given))
+    }
+
+    /// Check if the name of the given file matches these rules. Only files
+    /// without a header row are identified by name.
+    pub fn file_name_matches(&self, path: impl AsRef<Path>) -> bool {
+        let name = path.as_ref().file_name().unwrap_or_default();
+        !self.has_headers
+            && self
+                .filename_pattern
+                .as_ref()
+                .is_some_and(|p| p.is_match(&name.to_string_lossy()))
+    }
+
+    /// The names of the columns of the files for this account, in order.
+    pub fn column_names(&self) -> &[String] {
+        if self.has_headers {
+            &self.identify
+        } else {
+            &self.columns
+        }
+    }
+
+    /// The key of the rules file that lists the columns of the files for this account.
+    fn column_names_key(&self) -> &'static str {
+        if self.has_headers {
+            "identify"
+        } else {
+            "columns"
+        }
+    }
+
+    /// The output column name and value for which the amount of a row is negated.
+    pub fn negate_when(&self) -> Option<(&str, &str)> {
+        let cond = self.negate_when.as_ref()?;
+        let column = self
+            .translations()
+            .into_iter()
+            .find(|(_, value)| value.as_deref() == Some(cond.column.as_str()))
+            .map_or(cond.column.as_str(), |(key, _)| key);
+        Some((column, &cond.value))
     }
 
     /// Ensure all mapping keys appear in the identify vector.
     pub fn validate(&self) -> Result<()> {
+        // The output name is used as-is for a file in the output directory.
+        if let Some(name) = &self.output_name {
+            if name.trim().is_empty() || name.contains(['/', '\\']) {
+                return Err(anyhow!(
+                    "The account {} gives the output_name {:#?}, which is not a valid file name",
+                    &self.label,
+                    name,
+                ));
+            }
+        }
+
+        if self
+            .default_category
+            .as_ref()
+            .is_some_and(|x| x.trim().is_empty())
+        {
+            return Err(anyhow!(
+                "The account {} gives an empty default_category",
+                &self.label,
+            ));
+        }
+
+        // Files without headers are named by position and identified by name.
+        if self.has_headers {
+            if !self.columns.is_empty() || self.filename_pattern.is_some() {
+                return Err(anyhow!(
+                    "The account {} gives columns or filename_pattern, {}",
+                    &self.label,
+                    "which are only used when has_headers = false",
+                ));
+            }
+        } else {
+            if !self.identify.is_empty() {
+                return Err(anyhow!(
+                    "The account {} sets has_headers = false, so it cannot use identify - {}",
+                    &self.label,
+                    "name the columns with columns instead",
+                ));
+            }
+            if self.columns.is_empty() || self.filename_pattern.is_none() {
+                return Err(anyhow!(
+                    "The account {} sets has_headers = false, so it must give both {}",
+                    &self.label,
+                    "columns and filename_pattern",
+                ));
+            }
+        }
+
         if let Some(trans) = &self.translate {
             let values = vec![
                 &trans.payee,
@@ -74,43 +297,148 @@ impl MappingRulesCsv {
                 &trans.category,
                 &trans.memo,
                 &trans.check,
+                &trans.address,
+                &trans.debit,
+                &trans.credit,
+                &trans.balance,
+                &trans.posted_date,
             ];
             for value in values {
                 if let Some(val) = &value {
-                    if !self.identify.contains(val) {
+                    if !self.column_names().contains(val) {
                         return Err(anyhow!(
-                            "The account {} lists {} for translation {}",
-                            "but it is not listed in identify",
+                            "The account {} lists {} for translation but it is not listed in {}",
                             &self.label,
                             val,
+                            self.column_names_key(),
                         ));
                     }
                 }
             }
         }
 
+        // The column deciding negation must be in the file.
+        if let Some(cond) = &self.negate_when {
+            if !self.column_names().contains(&cond.column) {
+                return Err(anyhow!(
+                    "The account {} sets negate_when on the column {:#?}, which is not listed in {}",
+                    &self.label,
+                    &cond.column,
+                    self.column_names_key(),
+                ));
+            }
+        }
+
+        // The amount must be extracted from a column in the file with a capture group,
+        // and only when there is no other source for the amount.
+        if let Some(from) = &self.amount_from {
+            if !self.column_names().contains(&from.column) {
+                return Err(anyhow!(
+                    "The account {} sets amount_from on the column {:#?}, which is not listed in {}",
+                    &self.label,
+                    &from.column,
+                    self.column_names_key(),
+                ));
+            }
+            if from.pattern.captures_len() < 2 {
+                return Err(anyhow!(
+                    "The account {} sets amount_from with the pattern {:#?}, which has no capture group for the amount",
+                    &self.label,
+                    from.pattern.as_str(),
+                ));
+            }
+            let amount = self
+                .translate
+                .as_ref()
+                .and_then(|trans| trans.amount.as_deref())
+                .unwrap_or(columns::AMOUNT);
+            if self.column_names().iter().any(|x| x == amount) || self.amount_from_balance {
+                return Err(anyhow!(
+                    "The account {} sets amount_from but already has an amount",
+                    &self.label,
+                ));
+            }
+        }
+
+        // A scale must be usable and cannot be combined with cents.
+        if let Some(scale) = self.amount_scale {
+            if scale.is_zero() {
+                return Err(anyhow!(
+                    "The account {} sets an amount_scale of zero",
+                    &self.label,
+                ));
+            }
+            if self.amount_in_cents {
+                return Err(anyhow!(
+                    "The account {} cannot set both amount_scale and amount_in_cents",
+                    &self.label,
+                ));
+            }
+        }
+
+        // Deriving the amount from the balance requires a balance column.
+        if self.amount_from_balance {
+            let balance = self
+                .translate
+                .as_ref()
+                .and_then(|trans| trans.balance.as_deref())
+                .unwrap_or(columns::BALANCE);
+            if !self.column_names().iter().any(|x| x == balance) {
+                return Err(anyhow!(
+                    "The account {} sets amount_from_balance but does not identify a {} column",
+                    &self.label,
+                    balance,
+                ));
+            }
+        }
+
+        // Filtering on the posted date requires a posted date column.
+        if self.filter_on_posted_date {
+            let posted_date = self
+                .translate
+                .as_ref()
+                .and_then(|trans| trans.posted_date.as_deref())
+                .unwrap_or(columns::POSTED_DATE);
+            if !self.column_names().iter().any(|x| x == posted_date) {
+                return Err(anyhow!(
+                    "The account {} sets filter_on_posted_date but does not identify a {} column",
+                    &self.label,
+                    posted_date,
+                ));
+            }
+        }
+
         Ok(())
     }
 
+    /// Strip surrounding whitespace and quotes from each value if requested.
+    pub fn trim(&self, mut mapping: HashMap<String, String>) -> HashMap<String, String> {
+        if self.trim_values {
+            let strip = |c: char| c.is_whitespace() || self.quote_chars.contains(c);
+            for value in mapping.values_mut() {
+                *value = value.trim_matches(strip).to_owned();
+            }
+        }
+        mapping
+    }
+
     /// Remap the columns in a mapping to what is desired on output.
-    pub fn remap(&self, mut mapping: HashMap<String, String>) -> HashMap<String, String> {
-        // If this account does not define remappings we can just exist early.
-        let Some(maps) = &self.translate else {
-            return mapping;
-        };
+    pub fn remap(
+        &self,
+        mapping: HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, TidymoneyError> {
+        let mut mapping = self.rename_headers(mapping);
 
-        // Pair up each field with a key to which to map in the mapping.
-        let pairs = vec![
-            ("Payee", &maps.payee),
-            ("Date", &maps.date),
-            ("Amount", &maps.amount),
-            ("Category", &maps.category),
-            ("Memo", &maps.memo),
-            ("Check#", &maps.check),
-        ];
+        // The amount is extracted using the column name as given in the file.
+        let amount = self
+            .amount_from
+            .as_ref()
+            .map(|from| from.extract(&mapping, &self.label))
+            .transpose()?
+            .flatten();
 
         // Remap each column name if the remapping is defined.
-        for (key, value) in pairs {
+        for (key, value) in self.translations() {
             if let Some(k) = value {
                 if let Some(val) = mapping.remove(k) {
                     mapping.insert(key.to_owned(), val);
@@ -118,7 +446,81 @@ impl MappingRulesCsv {
             }
         }
 
+        if let Some(amount) = amount {
+            mapping.insert(columns::AMOUNT.to_owned(), amount);
+        }
+        Ok(mapping)
+    }
+
+    /// Rename each column to the name given in identify, if they only differ
+    /// by the header normalization.
+    fn rename_headers(&self, mapping: HashMap<String, String>) -> HashMap<String, String> {
+        if self.header_normalization == HeaderNormalization::Exact {
+            return mapping;
+        }
         mapping
+            .into_iter()
+            .map(|(key, value)| {
+                let normalized = self.header_normalization.apply(&key);
+                let key = self
+                    .identify
+                    .iter()
+                    .find(|x| self.header_normalization.apply(x) == normalized)
+                    .map_or(key, |x| x.to_owned());
+                (key, value)
+            })
+            .collect()
+    }
+
+    /// Each output column name paired with the input column name that maps to it.
+    fn translations(&self) -> Vec<(&'static str, &Option<String>)> {
+        self.translate
+            .as_ref()
+            .map_or_else(Vec::new, |maps| maps.pairs())
+    }
+}
+
+/// A column value for which the amount of a row is negated.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct NegateWhen {
+    /// The column to check, as named in the CSV file.
+    column: String,
+    /// The value of the column for which to negate.
+    value: String,
+}
+
+/// A column of a CSV file from which to extract the amount of a row.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct AmountFrom {
+    /// The column to search, as named in the CSV file.
+    column: String,
+    /// The pattern whose first capture group is the amount.
+    #[serde(deserialize_with = "deserialize_regex")]
+    pattern: EqRegex,
+}
+
+impl AmountFrom {
+    /// Extract the amount from the column of the row, if present.
+    /// It is an error for the pattern not to match the column.
+    fn extract(
+        &self,
+        row: &HashMap<String, String>,
+        account: &str,
+    ) -> Result<Option<String>, TidymoneyError> {
+        let Some(value) = row.get(&self.column) else {
+            return Ok(None);
+        };
+        match self.pattern.captures(value).and_then(|x| x.get(1)) {
+            Some(amount) => Ok(Some(amount.as_str().to_owned())),
+            None => Err(TidymoneyError::UnmatchedAmount {
+                account: account.to_owned(),
+                column: self.column.to_owned(),
+                pattern: self.pattern.as_str().to_owned(),
+                value: value.to_owned(),
+            }),
+        }
     }
 }
 
@@ -139,14 +541,45 @@ struct RemapValuesCsv {
     /// The Check# column.
     #[serde(rename = "Check#")]
     check: Option<String>,
+    /// The Address column.
+    address: Option<String>,
+    /// The Debit column (used if there is no Amount column).
+    debit: Option<String>,
+    /// The Credit column (used if there is no Amount column).
+    credit: Option<String>,
+    /// The Balance column (used if the amount is derived from the balance).
+    balance: Option<String>,
+    /// The PostedDate column.
+    posted_date: Option<String>,
+}
+
+impl RemapValuesCsv {
+    /// Pair up each field with a key to which to map in the mapping.
+    fn pairs(&self) -> Vec<(&'static str, &Option<String>)> {
+        vec![
+            (columns::PAYEE, &self.payee),
+            (columns::DATE, &self.date),
+            (columns::AMOUNT, &self.amount),
+            (columns::CATEGORY, &self.category),
+            (columns::MEMO, &self.memo),
+            (columns::CHECK, &self.check),
+            (columns::ADDRESS, &self.address),
+            (columns::DEBIT, &self.debit),
+            (columns::CREDIT, &self.credit),
+            (columns::BALANCE, &self.balance),
+            (columns::POSTED_DATE, &self.posted_date),
+        ]
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use indoc::indoc;
     use pretty_assertions::assert_eq;
     use rstest::{fixture, rstest};
+    use rust_decimal_macros::dec;
 
     use crate::as_hashmap;
 
@@ -240,6 +673,9 @@ mod test {
     #[case(vec![("amount", "Value")], false)]
     #[case(vec![("memo", "Note")], false)]
     #[case(vec![("category", "Column")], false)]
+    #[case(vec![("address", "Location")], false)]
+    #[case(vec![("debit", "Withdrawal")], false)]
+    #[case(vec![("credit", "Deposit")], false)]
     #[case(
         vec![
             ("date", "Posting Date"),
@@ -252,6 +688,7 @@ mod test {
     #[case(
         vec![
             ("memo", "Memo"),
+            ("address", "Extended Description"),
             ("amount", "Amount"),
             ("date", "Posting Date"),
             ("payee", "Description"),
@@ -284,6 +721,7 @@ mod test {
             ("Description", "ACE"),
             ("Transaction Category", "Hardware"),
             ("Memo", "Things"),
+            ("Extended Description", "Vancouver WA"),
         ]
     )]
     #[case(
@@ -301,6 +739,20 @@ mod test {
             ("Payee", "ACE"),
             ("Category", "Hardware"),
             ("Memo", "Things"),
+            ("Extended Description", "Vancouver WA"),
+        ]
+    )]
+    #[case(
+        vec![("address", "Extended Description")],
+        vec![
+            ("Transaction ID", "12345"),
+            ("Posting Date", "2022-03-04"),
+            ("Amount", "-14.53"),
+            ("Check Number", "1234"),
+            ("Description", "ACE"),
+            ("Transaction Category", "Hardware"),
+            ("Memo", "Things"),
+            ("Address", "Vancouver WA"),
         ]
     )]
     fn test_remap(
@@ -317,6 +769,7 @@ mod test {
             ("Description", "ACE"),
             ("Transaction Category", "Hardware"),
             ("Memo", "Things"),
+            ("Extended Description", "Vancouver WA"),
         ];
         let obj = MappingRulesCsv::new(
             label.to_string(),
@@ -325,6 +778,345 @@ mod test {
             None,
             false,
         );
-        assert_eq!(obj.remap(as_hashmap(mapping)), as_hashmap(expected));
+        assert_eq!(
+            obj.remap(as_hashmap(mapping)).unwrap(),
+            as_hashmap(expected)
+        );
+    }
+
+    #[rstest]
+    #[case("has_headers = false\ncolumns = [\"Date\", \"Payee\", \"Amount\"]\nfilename_pattern = \"x\"", true)]
+    #[case(
+        "has_headers = false\ncolumns = [\"Date\", \"Payee\", \"Amount\"]",
+        false
+    )]
+    #[case("has_headers = false\nfilename_pattern = \"x\"", false)]
+    #[case("has_headers = false\nidentify = [\"Date\"]\ncolumns = [\"Date\"]\nfilename_pattern = \"x\"", false)]
+    #[case(
+        "identify = [\"Date\", \"Payee\", \"Amount\"]\nfilename_pattern = \"x\"",
+        false
+    )]
+    #[case(
+        "identify = [\"Date\", \"Payee\", \"Amount\"]\ncolumns = [\"Date\"]",
+        false
+    )]
+    #[case("has_headers = false\ncolumns = [\"When\", \"Payee\"]\nfilename_pattern = \"x\"\ntranslate = {Date = \"When\"}", true)]
+    #[case("has_headers = false\ncolumns = [\"Payee\"]\nfilename_pattern = \"x\"\ntranslate = {Date = \"When\"}", false)]
+    fn test_validate_headerless(#[case] given: &str, #[case] expected: bool) {
+        let obj: MappingRulesCsv =
+            toml::from_str(&format!("label = \"testing\"\n{given}")).unwrap();
+        assert_eq!(obj.validate().is_ok(), expected);
+    }
+
+    #[test]
+    fn test_file_name_matches() {
+        let obj: MappingRulesCsv = toml::from_str(indoc! {r#"
+            label = "testing"
+            has_headers = false
+            columns = ["Date", "Payee", "Amount"]
+            filename_pattern = '^export-\d+\.csv'
+            "#})
+        .unwrap();
+        assert!(obj.file_name_matches("/downloads/export-0412.csv"));
+        assert!(!obj.file_name_matches("/export-0412/other.csv"));
+        assert!(!obj.header_matches(&vec!["Date".into(), "Payee".into(), "Amount".into()]));
+    }
+
+    #[rstest]
+    #[case(vec!["Date", "Payee", "Balance"], vec![], true)]
+    #[case(vec!["Date", "Payee", "Running"], vec![("balance", "Running")], true)]
+    #[case(vec!["Date", "Payee", "Amount"], vec![], false)]
+    fn test_validate_amount_from_balance(
+        #[case] identify: Vec<&str>,
+        #[case] translate: Vec<(&str, &str)>,
+        #[case] expected: bool,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            identify.into_iter().map(|x| x.to_string()).collect(),
+            as_hashmap(translate),
+            None,
+            false,
+        );
+        obj.amount_from_balance = true;
+        assert_eq!(obj.validate().is_ok(), expected);
+    }
+
+    #[rstest]
+    #[case(vec![], vec!["Date", "Payee", "Amount"], false)]
+    #[case(vec![], vec!["Date", "PostedDate", "Payee", "Amount"], true)]
+    #[case(vec![("posted_date", "Posted")], vec!["Date", "Posted", "Payee", "Amount"], true)]
+    fn test_validate_filter_on_posted_date(
+        #[case] translate: Vec<(&str, &str)>,
+        #[case] identify: Vec<&str>,
+        #[case] expected: bool,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            identify.into_iter().map(|x| x.to_string()).collect(),
+            as_hashmap(translate),
+            None,
+            false,
+        );
+        obj.filter_on_posted_date = true;
+        assert_eq!(obj.validate().is_ok(), expected);
+    }
+
+    #[rstest]
+    #[case(None, "testing", true)]
+    #[case(Some("Checking Export"), "Checking Export", true)]
+    #[case(Some(""), "", false)]
+    #[case(Some("exports/checking"), "exports/checking", false)]
+    fn test_output_name(
+        #[case] output_name: Option<&str>,
+        #[case] expected: &str,
+        #[case] valid: bool,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            vec![
+                "Date".to_string(),
+                "Payee".to_string(),
+                "Amount".to_string(),
+            ],
+            HashMap::new(),
+            None,
+            false,
+        );
+        obj.output_name = output_name.map(|x| x.to_string());
+        assert_eq!(obj.output_name(), expected);
+        assert_eq!(obj.validate().is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case("default_category = \"Savings\"", true)]
+    #[case("default_category = \" \"", false)]
+    fn test_validate_default_category(#[case] given: &str, #[case] expected: bool) {
+        let obj: MappingRulesCsv =
+            toml::from_str(&format!("label = \"testing\"\n{given}")).unwrap();
+        assert_eq!(obj.validate().is_ok(), expected);
+    }
+
+    #[rstest]
+    #[case(Some(dec!(1000)), false, true)]
+    #[case(Some(dec!(0.01)), false, true)]
+    #[case(Some(dec!(0)), false, false)]
+    #[case(Some(dec!(1000)), true, false)]
+    #[case(None, true, true)]
+    fn test_validate_amount_scale(
+        #[case] amount_scale: Option<Decimal>,
+        #[case] amount_in_cents: bool,
+        #[case] expected: bool,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            vec![
+                "Date".to_string(),
+                "Payee".to_string(),
+                "Amount".to_string(),
+            ],
+            HashMap::new(),
+            None,
+            false,
+        );
+        obj.amount_scale = amount_scale;
+        obj.amount_in_cents = amount_in_cents;
+        assert_eq!(obj.validate().is_ok(), expected);
+    }
+
+    #[rstest]
+    #[case("Type", vec![], Some(("Type", "Debit")))]
+    #[case("Transaction Type", vec![], Some(("Transaction Type", "Debit")))]
+    #[case("Transaction Type", vec![("category", "Transaction Type")], Some(("Category", "Debit")))]
+    #[case("Kind", vec![], None)]
+    fn test_negate_when(
+        #[case] column: &str,
+        #[case] translate: Vec<(&str, &str)>,
+        #[case] expected: Option<(&str, &str)>,
+        identify: Vec<String>,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            identify,
+            as_hashmap(translate),
+            None,
+            false,
+        );
+        obj.negate_when = Some(NegateWhen {
+            column: column.to_string(),
+            value: "Debit".to_string(),
+        });
+        // An unknown column is invalid.
+        match expected {
+            Some(expected) => assert_eq!(obj.negate_when(), Some(expected)),
+            None => assert!(obj.validate().is_err()),
+        }
+    }
+
+    #[rstest]
+    #[case(vec![], "POS ACE HARDWARE AMT 12.34 REF 99", "12.34")]
+    #[case(vec![("payee", "Description")], "POS ACE AMT -12.34", "-12.34")]
+    fn test_amount_from(
+        #[case] translate: Vec<(&str, &str)>,
+        #[case] description: &str,
+        #[case] expected: &str,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            vec!["Date".to_string(), "Description".to_string()],
+            as_hashmap(translate),
+            None,
+            false,
+        );
+        obj.amount_from = Some(AmountFrom {
+            column: "Description".to_string(),
+            pattern: EqRegex::new(r"AMT (-?\d+\.\d+)").unwrap(),
+        });
+        assert!(obj.validate().is_ok());
+        let result = obj
+            .remap(as_hashmap(vec![
+                ("Date", "2024-01-05"),
+                ("Description", description),
+            ]))
+            .unwrap();
+        assert_eq!(result["Amount"], expected);
+    }
+
+    #[test]
+    fn test_amount_from_unmatched() {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            vec!["Date".to_string(), "Description".to_string()],
+            HashMap::new(),
+            None,
+            false,
+        );
+        obj.amount_from = Some(AmountFrom {
+            column: "Description".to_string(),
+            pattern: EqRegex::new(r"AMT (-?\d+\.\d+)").unwrap(),
+        });
+        let result = obj
+            .remap(as_hashmap(vec![
+                ("Date", "2024-01-05"),
+                ("Description", "POS ACE HARDWARE"),
+            ]))
+            .unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            r#"The account 'testing' has a Description value "POS ACE HARDWARE" that does not match the amount_from pattern "AMT (-?\\d+\\.\\d+)""#
+        );
+        assert!(matches!(result, TidymoneyError::UnmatchedAmount { .. }));
+    }
+
+    #[rstest]
+    #[case("Description", r"AMT (\d+\.\d+)", vec!["Date", "Description"], true)]
+    #[case("Description", r"AMT \d+\.\d+", vec!["Date", "Description"], false)]
+    #[case("Details", r"AMT (\d+\.\d+)", vec!["Date", "Description"], false)]
+    #[case("Description", r"AMT (\d+\.\d+)", vec!["Date", "Description", "Amount"], false)]
+    fn test_validate_amount_from(
+        #[case] column: &str,
+        #[case] pattern: &str,
+        #[case] identify: Vec<&str>,
+        #[case] expected: bool,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            identify.into_iter().map(|x| x.to_string()).collect(),
+            HashMap::new(),
+            None,
+            false,
+        );
+        obj.amount_from = Some(AmountFrom {
+            column: column.to_string(),
+            pattern: EqRegex::new(pattern).unwrap(),
+        });
+        assert_eq!(obj.validate().is_ok(), expected);
+    }
+
+    #[rstest]
+    #[case(HeaderNormalization::Exact, vec!["Date", "Description", "Amount"], true)]
+    #[case(HeaderNormalization::Exact, vec![" DATE", "description ", "Amount"], false)]
+    #[case(HeaderNormalization::Trim, vec![" Date", "Description ", "Amount"], true)]
+    #[case(HeaderNormalization::Trim, vec![" DATE", "description ", "Amount"], false)]
+    #[case(HeaderNormalization::Lowercase, vec!["DATE", "description", "Amount"], true)]
+    #[case(HeaderNormalization::Lowercase, vec![" DATE", "description ", "Amount"], false)]
+    #[case(HeaderNormalization::TrimLowercase, vec![" DATE", "description ", "Amount"], true)]
+    #[case(HeaderNormalization::TrimLowercase, vec![" DATE", "description ", "Total"], false)]
+    fn test_header_normalization(
+        #[case] header_normalization: HeaderNormalization,
+        #[case] given: Vec<&str>,
+        #[case] expected: bool,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            vec!["Date", "Description", "Amount"]
+                .into_iter()
+                .map(|x| x.to_string())
+                .collect(),
+            as_hashmap(vec![("payee", "Description")]),
+            None,
+            false,
+        );
+        obj.header_normalization = header_normalization;
+        let headers = given.iter().map(|x| x.to_string()).collect();
+        assert_eq!(obj.header_matches(&headers), expected);
+
+        // The columns of matching files are found by the names given in identify.
+        if expected {
+            let row = given
+                .into_iter()
+                .zip(["2024-01-05", "ACE", "-1.00"])
+                .collect();
+            assert_eq!(
+                obj.remap(as_hashmap(row)).unwrap(),
+                as_hashmap(vec![
+                    ("Date", "2024-01-05"),
+                    ("Payee", "ACE"),
+                    ("Amount", "-1.00")
+                ])
+            );
+        }
+    }
+
+    #[test]
+    fn test_header_normalization_is_parsed() {
+        let obj: MappingRulesCsv = toml::from_str("label = \"testing\"").unwrap();
+        assert_eq!(obj.header_normalization, HeaderNormalization::Exact);
+        let obj: MappingRulesCsv =
+            toml::from_str("label = \"testing\"\nheader_normalization = \"trim_lowercase\"")
+                .unwrap();
+        assert_eq!(obj.header_normalization, HeaderNormalization::TrimLowercase);
+        assert!(toml::from_str::<MappingRulesCsv>(
+            "label = \"testing\"\nheader_normalization = \"fold\""
+        )
+        .is_err());
+    }
+
+    #[rstest]
+    #[case(false, None, " \"ACE HARDWARE\" ", " \"ACE HARDWARE\" ")]
+    #[case(true, None, " \"ACE HARDWARE\" ", "ACE HARDWARE")]
+    #[case(true, None, "\u{201C}ACE HARDWARE\u{201D}  ", "ACE HARDWARE")]
+    #[case(true, None, "  -15.43 ", "-15.43")]
+    #[case(true, Some("'"), "'ACE's'", "ACE's")]
+    #[case(true, Some("'"), " \"ACE HARDWARE\" ", "\"ACE HARDWARE\"")]
+    fn test_trim(
+        #[case] trim_values: bool,
+        #[case] quote_chars: Option<&str>,
+        #[case] given: &str,
+        #[case] expected: &str,
+    ) {
+        let mut obj = MappingRulesCsv::new(
+            "testing".to_string(),
+            vec!["Payee".to_string()],
+            HashMap::new(),
+            None,
+            false,
+        );
+        obj.trim_values = trim_values;
+        if let Some(quote_chars) = quote_chars {
+            obj.quote_chars = quote_chars.to_string();
+        }
+        let result = obj.trim(as_hashmap(vec![("Payee", given)]));
+        assert_eq!(result, as_hashmap(vec![("Payee", expected)]));
     }
 }
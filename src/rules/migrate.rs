@@ -0,0 +1,84 @@
+use anyhow::Result;
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::rules::check_version;
+
+/// A rules file upgraded to the current version of the rules format.
+#[derive(Debug, PartialEq)]
+pub struct Migration {
+    /// The upgraded contents of the rules file.
+    pub rules: String,
+    /// A description of each change that was made.
+    pub changes: Vec<String>,
+}
+
+/// Upgrade raw rules file data to the current version of the rules format,
+/// keeping comments and formatting of everything that is unchanged.
+///
+/// No keys have changed since the first version of the format, so the only
+/// upgrade is to record the version in a file that does not give one.
+pub fn migrate(raw_data: &str) -> Result<Migration> {
+    let version = check_version(raw_data)?;
+    let mut doc: DocumentMut = raw_data.parse()?;
+    let mut changes = vec![];
+    if !doc.contains_key("version") {
+        doc.insert("version", Item::Value(Value::from(i64::from(version))));
+        changes.push(format!("Set the version to {version}"));
+    }
+
+    Ok(Migration {
+        rules: doc.to_string(),
+        changes,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use crate::rules::RuleFileData;
+
+    #[test]
+    fn test_migrate_unversioned_rules() {
+        let given = indoc! {r#"
+            # My rules
+            [payees]
+            Apple = "APPLE"
+
+            [categories]
+            Income = {Payee = "Employer", IncomeOK = true}
+
+            [[mappings.csv]]
+            label = "checking"
+            identify = ["Date", "Payee", "Amount"]
+            debit_is_positive = true  # my bank is odd
+
+            [paths]
+            storage = "."
+        "#};
+        let result = migrate(given).unwrap();
+        assert_eq!(result.rules, format!("version = 1\n{given}"));
+        assert_eq!(result.changes, vec!["Set the version to 1"]);
+        assert_eq!(
+            RuleFileData::new(&result.rules).unwrap(),
+            RuleFileData::new(given).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_migrate_current_rules_is_unchanged() {
+        let given = "version = 1\n\n[payees]\nApple = \"APPLE\"\n";
+        let result = migrate(given).unwrap();
+        assert_eq!(result.rules, given);
+        assert!(result.changes.is_empty());
+    }
+
+    #[test]
+    fn test_migrate_newer_rules_fails() {
+        let result = migrate("version = 99\n");
+        assert!(result.unwrap_err().to_string().contains("please upgrade"));
+    }
+}
@@ -0,0 +1,53 @@
+use std::hash::{Hash, Hasher};
+
+use serde::Deserialize;
+
+/// A human-readable note attached to a rule for traceability.
+///
+/// Notes never take part in matching, and are ignored for equality and
+/// hashing so that two rules differing only by their note are still
+/// detected as duplicates.
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Note(pub Option<String>);
+
+impl Note {
+    /// Decorate the name of the object owning the rule with this note, if given.
+    pub fn describe(&self, name: &str) -> String {
+        match &self.0 {
+            Some(note) => format!("{name} ({note})"),
+            None => name.to_owned(),
+        }
+    }
+}
+
+impl PartialEq for Note {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Note {}
+
+impl Hash for Note {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(Note(None).describe("Apple"), "Apple");
+        assert_eq!(
+            Note(Some("iCloud".to_string())).describe("Apple"),
+            "Apple (iCloud)"
+        );
+    }
+
+    #[test]
+    fn test_note_is_ignored_for_equality() {
+        assert_eq!(Note(None), Note(Some("iCloud".to_string())));
+    }
+}
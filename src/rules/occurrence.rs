@@ -0,0 +1,69 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// Which occurrences of a payee within a run a rule matches.
+///
+/// Transactions are counted separately for each payee (after renaming) in
+/// each account, in chronological order and keeping the original order for
+/// the same date, starting from one.
+#[derive(Debug, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+#[serde(deny_unknown_fields, rename_all = "PascalCase")]
+pub enum Occurrence {
+    /// Only the given occurrence, such as the first.
+    Nth(usize),
+    /// Every given occurrence, such as every third.
+    Every(usize),
+}
+
+impl Occurrence {
+    /// Assess if the transaction with the given ordinal (starting from one) matches.
+    pub fn includes(&self, ordinal: usize) -> bool {
+        match *self {
+            Occurrence::Nth(n) => ordinal == n,
+            Occurrence::Every(n) => ordinal.is_multiple_of(n),
+        }
+    }
+
+    /// Ensure the occurrence is semantically correct.
+    pub fn validate(&self, obj_type: &str, name: &str) -> Result<()> {
+        let (Occurrence::Nth(n) | Occurrence::Every(n)) = *self;
+        if n == 0 {
+            return Err(anyhow!(
+                "The {obj_type} {name:#?} specifies an Occurrence that is not positive."
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Occurrence::Nth(1), vec![1])]
+    #[case(Occurrence::Nth(3), vec![3])]
+    #[case(Occurrence::Every(1), vec![1, 2, 3, 4, 5, 6])]
+    #[case(Occurrence::Every(2), vec![2, 4, 6])]
+    #[case(Occurrence::Every(0), vec![])]
+    fn test_includes(#[case] occurrence: Occurrence, #[case] expected: Vec<usize>) {
+        let result: Vec<_> = (1..=6).filter(|&x| occurrence.includes(x)).collect();
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case(Occurrence::Nth(0))]
+    #[case(Occurrence::Every(0))]
+    fn test_must_be_positive(#[case] occurrence: Occurrence) {
+        assert_eq!(
+            occurrence
+                .validate("category", "Dining")
+                .err()
+                .unwrap()
+                .to_string(),
+            "The category \"Dining\" specifies an Occurrence that is not positive."
+        );
+    }
+}
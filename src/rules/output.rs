@@ -0,0 +1,342 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::format::{Item, StrftimeItems};
+use serde::Deserialize;
+
+/// The date format expected by YNAB if not specified.
+const DEFAULT_YNAB_DATE_FMT: &str = "%m/%d/%Y";
+
+/// Options controlling how normalized transactions are written.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OutputOptions {
+    /// The column layout of the normalized CSV files.
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// The format string for dates when writing in the YNAB format.
+    pub ynab_date_fmt: Option<String>,
+    /// The order in which each account's transactions are sorted by date.
+    /// If not given, transactions are written in the order they were read.
+    pub sort_order: Option<SortOrder>,
+    /// How transactions on the same date are ordered when sorting.
+    #[serde(default)]
+    pub tie_break: TieBreak,
+    /// Whether or not to add a column naming the file each transaction was read from.
+    #[serde(default)]
+    pub include_source: bool,
+    /// Whether or not to write a separate file for each month of each account.
+    #[serde(default)]
+    pub split_by_month: bool,
+    /// How to change the case of payees that were not renamed by a rule.
+    #[serde(default)]
+    pub payee_case: PayeeCase,
+    /// Whether or not uncategorized transactions take the category of an
+    /// earlier transaction with the same payee.
+    #[serde(default)]
+    pub carry_forward_categories: bool,
+    /// If given, every matching memo rule contributes to the memo, joined by
+    /// this separator. Otherwise only one matching memo rule is used.
+    pub memo_separator: Option<String>,
+    /// How to handle transactions that are cancelled out by a later one.
+    pub reversals: Option<ReversalOptions>,
+    /// Which fields are quoted in the written CSV files.
+    #[serde(default)]
+    pub quote_style: QuoteStyle,
+    /// Write one row per group of transactions instead of one row per transaction.
+    pub aggregate: Option<Aggregate>,
+    /// Whether or not to add a column with the balance of the account after each transaction.
+    #[serde(default)]
+    pub running_balance: bool,
+}
+
+/// Options controlling how matched charges and refunds are handled.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct ReversalOptions {
+    /// The most days that may separate a transaction from its reversal.
+    pub window_days: u32,
+    /// Whether or not to remove reversed transactions instead of flagging them.
+    #[serde(default)]
+    pub remove: bool,
+}
+
+impl OutputOptions {
+    /// The format string for dates when writing in the YNAB format.
+    pub fn ynab_date_fmt(&self) -> &str {
+        self.ynab_date_fmt
+            .as_deref()
+            .unwrap_or(DEFAULT_YNAB_DATE_FMT)
+    }
+
+    /// Ensure the given options are semantically correct.
+    pub fn validate(&self) -> Result<()> {
+        let fmt = self.ynab_date_fmt();
+        if StrftimeItems::new(fmt).any(|item| item == Item::Error) {
+            return Err(anyhow!(
+                "The ynab_date_fmt {fmt:#?} is not a valid date format string."
+            ));
+        }
+        if self.memo_separator.as_ref().is_some_and(|x| x.is_empty()) {
+            return Err(anyhow!("The memo_separator option must not be empty."));
+        }
+        if self.running_balance && self.format == OutputFormat::Ynab {
+            return Err(anyhow!(
+                "The running_balance option cannot be used with the ynab format."
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The column layout in which to write transactions.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// The Date, Payee, Category, Memo, Amount, and Check# columns.
+    #[default]
+    Standard,
+    /// The Date, Payee, Memo, Outflow, and Inflow columns that YNAB imports.
+    Ynab,
+    /// One JSON object per line (JSON Lines) with the standard columns as keys.
+    Ndjson,
+}
+
+impl OutputFormat {
+    /// The extension of the files written in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Standard | OutputFormat::Ynab => "csv",
+            OutputFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// How to group transactions into summary rows.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    /// One row per payee with the number and total of its transactions.
+    Payee,
+}
+
+impl FromStr for Aggregate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "payee" => Ok(Aggregate::Payee),
+            _ => Err(format!("unknown aggregation {s:#?}, expected \"payee\"")),
+        }
+    }
+}
+
+/// Which fields to quote when writing CSV files.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStyle {
+    /// Only quote fields that contain a delimiter, quote, or newline.
+    #[default]
+    Necessary,
+    /// Quote every field.
+    Always,
+    /// Never quote fields, even if that produces invalid CSV.
+    Never,
+    /// Quote every field that is not a number.
+    NonNumeric,
+}
+
+impl QuoteStyle {
+    /// The equivalent style for the CSV writer.
+    pub fn as_csv(&self) -> csv::QuoteStyle {
+        match self {
+            QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+            QuoteStyle::Always => csv::QuoteStyle::Always,
+            QuoteStyle::Never => csv::QuoteStyle::Never,
+            QuoteStyle::NonNumeric => csv::QuoteStyle::NonNumeric,
+        }
+    }
+}
+
+/// The chronological order in which to sort transactions.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Oldest transactions first.
+    Ascending,
+    /// Newest transactions first.
+    Descending,
+}
+
+/// The secondary key used to order transactions on the same date.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TieBreak {
+    /// Keep the order in which the transactions were read.
+    #[default]
+    Input,
+    /// Smallest (most negative) amount first.
+    Amount,
+    /// Alphabetically by payee.
+    Payee,
+}
+
+/// A transformation of the case of a payee name.
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PayeeCase {
+    /// Leave the payee as-is.
+    #[default]
+    None,
+    /// Capitalize the first letter of each word and lowercase the rest.
+    Title,
+    /// Lowercase the entire payee.
+    Lower,
+    /// Uppercase the entire payee.
+    Upper,
+}
+
+impl PayeeCase {
+    /// Apply this case transformation to the given payee.
+    pub fn apply(&self, payee: &str) -> String {
+        match self {
+            PayeeCase::None => payee.to_owned(),
+            PayeeCase::Title => payee
+                .split(' ')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        Some(first) => first
+                            .to_uppercase()
+                            .chain(chars.flat_map(char::to_lowercase))
+                            .collect(),
+                        None => String::new(),
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(" "),
+            PayeeCase::Lower => payee.to_lowercase(),
+            PayeeCase::Upper => payee.to_uppercase(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("", None)]
+    #[case("sort_order = \"ascending\"", Some(SortOrder::Ascending))]
+    #[case("sort_order = \"descending\"", Some(SortOrder::Descending))]
+    fn test_sort_order(#[case] given: &str, #[case] expected: Option<SortOrder>) {
+        let result: OutputOptions = toml::from_str(given).unwrap();
+        assert_eq!(result.sort_order, expected);
+    }
+
+    #[rstest]
+    #[case("", TieBreak::Input)]
+    #[case("tie_break = \"input\"", TieBreak::Input)]
+    #[case("tie_break = \"amount\"", TieBreak::Amount)]
+    #[case("tie_break = \"payee\"", TieBreak::Payee)]
+    fn test_tie_break(#[case] given: &str, #[case] expected: TieBreak) {
+        let result: OutputOptions = toml::from_str(given).unwrap();
+        assert_eq!(result.tie_break, expected);
+        assert!(toml::from_str::<OutputOptions>("tie_break = \"date\"").is_err());
+    }
+
+    #[rstest]
+    #[case("", false)]
+    #[case("include_source = true", true)]
+    fn test_include_source(#[case] given: &str, #[case] expected: bool) {
+        let result: OutputOptions = toml::from_str(given).unwrap();
+        assert_eq!(result.include_source, expected);
+    }
+
+    #[rstest]
+    #[case(PayeeCase::None, "WENDY'S  old fashioned")]
+    #[case(PayeeCase::Title, "Wendy's  Old Fashioned")]
+    #[case(PayeeCase::Lower, "wendy's  old fashioned")]
+    #[case(PayeeCase::Upper, "WENDY'S  OLD FASHIONED")]
+    fn test_payee_case(#[case] case: PayeeCase, #[case] expected: &str) {
+        assert_eq!(case.apply("WENDY'S  old fashioned"), expected);
+    }
+
+    #[rstest]
+    #[case("", PayeeCase::None)]
+    #[case("payee_case = \"none\"", PayeeCase::None)]
+    #[case("payee_case = \"title\"", PayeeCase::Title)]
+    fn test_payee_case_is_parsed(#[case] given: &str, #[case] expected: PayeeCase) {
+        let result: OutputOptions = toml::from_str(given).unwrap();
+        assert_eq!(result.payee_case, expected);
+    }
+
+    #[rstest]
+    #[case("", OutputFormat::Standard, "%m/%d/%Y")]
+    #[case("format = \"ynab\"", OutputFormat::Ynab, "%m/%d/%Y")]
+    #[case(
+        "format = \"ynab\"\nynab_date_fmt = \"%Y-%m-%d\"",
+        OutputFormat::Ynab,
+        "%Y-%m-%d"
+    )]
+    fn test_format(#[case] given: &str, #[case] format: OutputFormat, #[case] date_fmt: &str) {
+        let result: OutputOptions = toml::from_str(given).unwrap();
+        assert_eq!(result.format, format);
+        assert_eq!(result.ynab_date_fmt(), date_fmt);
+        assert!(result.validate().is_ok());
+    }
+
+    #[test]
+    fn test_ynab_date_fmt_must_be_valid() {
+        let result: OutputOptions = toml::from_str("ynab_date_fmt = \"%m/%Q\"").unwrap();
+        assert_eq!(
+            result.validate().unwrap_err().to_string(),
+            "The ynab_date_fmt \"%m/%Q\" is not a valid date format string."
+        );
+    }
+
+    #[test]
+    fn test_running_balance_requires_standard_format() {
+        let result: OutputOptions = toml::from_str("running_balance = true").unwrap();
+        assert!(result.validate().is_ok());
+        let result: OutputOptions =
+            toml::from_str("running_balance = true\nformat = \"ynab\"").unwrap();
+        assert_eq!(
+            result.validate().unwrap_err().to_string(),
+            "The running_balance option cannot be used with the ynab format."
+        );
+    }
+
+    #[rstest]
+    #[case("", QuoteStyle::Necessary)]
+    #[case("quote_style = \"always\"", QuoteStyle::Always)]
+    #[case("quote_style = \"never\"", QuoteStyle::Never)]
+    #[case("quote_style = \"non_numeric\"", QuoteStyle::NonNumeric)]
+    fn test_quote_style(#[case] given: &str, #[case] expected: QuoteStyle) {
+        let result: OutputOptions = toml::from_str(given).unwrap();
+        assert_eq!(result.quote_style, expected);
+    }
+
+    #[rstest]
+    #[case("", None)]
+    #[case("aggregate = \"payee\"", Some(Aggregate::Payee))]
+    fn test_aggregate(#[case] given: &str, #[case] expected: Option<Aggregate>) {
+        let result: OutputOptions = toml::from_str(given).unwrap();
+        assert_eq!(result.aggregate, expected);
+    }
+
+    #[test]
+    fn test_aggregate_must_be_known() {
+        assert!(toml::from_str::<OutputOptions>("aggregate = \"date\"").is_err());
+        assert_eq!("payee".parse(), Ok(Aggregate::Payee));
+        assert!("date".parse::<Aggregate>().is_err());
+    }
+
+    #[test]
+    fn test_sort_order_must_be_known() {
+        let result: Result<OutputOptions, _> = toml::from_str("sort_order = \"sideways\"");
+        assert!(result.is_err());
+    }
+}
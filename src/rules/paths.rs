@@ -1,15 +1,49 @@
+use std::fs;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Deserializer};
 use simple_expand_tilde::expand_tilde;
 
+use crate::TidymoneyError;
+
 #[cfg(test)]
 use std::convert::Into;
 
-/// Expand '~' and cannoicalize the given path.
-pub fn normalize_path(path: impl AsRef<Path>) -> Result<PathBuf> {
-    expand_tilde(path.as_ref()).ok_or_else(|| anyhow!("Cannot expand ~ to a home directory"))
+/// Expand environment variables and '~' and cannoicalize the given path.
+pub fn normalize_path(path: impl AsRef<Path>) -> Result<PathBuf, TidymoneyError> {
+    let path = match path.as_ref().to_str() {
+        Some(text) => PathBuf::from(
+            shellexpand::env(text)
+                .map_err(|e| {
+                    TidymoneyError::InvalidPath(format!(
+                        "The environment variable {:#?} used in the path {:#?} is not set",
+                        e.var_name, text
+                    ))
+                })?
+                .as_ref(),
+        ),
+        None => path.as_ref().to_path_buf(),
+    };
+    expand_tilde(path)
+        .ok_or_else(|| TidymoneyError::InvalidPath("Cannot expand ~ to a home directory".into()))
+}
+
+/// Normalize the given path and then make it absolute, resolving any ".."
+/// segments and symbolic links. Unlike normalize_path, the path must exist.
+pub fn canonicalize_path(path: impl AsRef<Path>) -> Result<PathBuf, TidymoneyError> {
+    canonicalize(&normalize_path(path)?)
+}
+
+/// Make the given path absolute, resolving any ".." segments and symbolic links.
+fn canonicalize(path: &Path) -> Result<PathBuf, TidymoneyError> {
+    fs::canonicalize(path).map_err(|e| {
+        TidymoneyError::InvalidPath(match e.kind() {
+            ErrorKind::NotFound => format!("The path {:#?} does not exist", path),
+            _ => format!("The path {:#?} cannot be resolved: {e}", path),
+        })
+    })
 }
 
 /// Paths used by the program for various purposes.
@@ -17,8 +51,30 @@ pub fn normalize_path(path: impl AsRef<Path>) -> Result<PathBuf> {
 #[serde(deny_unknown_fields)]
 pub struct AuxillaryPaths {
     /// The path to the directory where old and new CSV files will be stored.
-    #[serde(deserialize_with = "deserialize_path")]
+    #[serde(deserialize_with = "deserialize_storage_path")]
     pub storage: PathBuf,
+    /// The name of the directory in storage where new CSV files will be written.
+    #[serde(default = "default_output_dir")]
+    pub output_dir: String,
+    /// The name of the directory in storage where old CSV files will be moved.
+    #[serde(default = "default_archive_dir")]
+    pub archive_dir: String,
+    /// Additional directories that receive a copy of everything written to storage.
+    #[serde(default, deserialize_with = "deserialize_paths")]
+    pub mirrors: Vec<PathBuf>,
+    /// The path to the timestamps file, if not next to the rules file.
+    #[serde(default, deserialize_with = "deserialize_optional_path")]
+    pub timestamps: Option<PathBuf>,
+}
+
+/// The default output directory name if not specified.
+fn default_output_dir() -> String {
+    "new".to_owned()
+}
+
+/// The default archive directory name if not specified.
+fn default_archive_dir() -> String {
+    "old".to_owned()
 }
 
 impl AuxillaryPaths {
@@ -27,16 +83,41 @@ impl AuxillaryPaths {
     pub fn new(storage: impl Into<PathBuf>) -> Self {
         AuxillaryPaths {
             storage: storage.into(),
+            output_dir: default_output_dir(),
+            archive_dir: default_archive_dir(),
+            mirrors: vec![],
+            timestamps: None,
         }
     }
 
+    /// The directory in storage where the new CSV files for the given run are written.
+    pub fn output_path(&self, now: impl AsRef<str>) -> PathBuf {
+        self.storage.join(&self.output_dir).join(now.as_ref())
+    }
+
+    /// The storage directory followed by each mirror directory.
+    pub fn destinations(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.storage).chain(&self.mirrors)
+    }
+
     // Ensure the contained data is correct.
     pub fn validate(&self) -> Result<()> {
-        // The storage directory must be a directory.
-        if !self.storage.is_dir() {
+        // The storage and mirror directories must exist, be directories, and be readable.
+        validate_directory("storage", &self.storage)?;
+        for mirror in &self.mirrors {
+            validate_directory("mirror", mirror)?;
+        }
+
+        // The output and archive directories must be distinct and non-empty.
+        if self.output_dir.is_empty() || self.archive_dir.is_empty() {
+            return Err(anyhow!(
+                "The output_dir and archive_dir paths must not be empty."
+            ));
+        }
+        if self.output_dir == self.archive_dir {
             return Err(anyhow!(
-                "The storage path {:#?} is not a directory.",
-                self.storage
+                "The output_dir and archive_dir paths must be different, both are {:#?}.",
+                self.output_dir
             ));
         }
 
@@ -44,6 +125,48 @@ impl AuxillaryPaths {
     }
 }
 
+/// Ensure the given directory exists, is a directory, and is readable.
+fn validate_directory(kind: &str, path: &Path) -> Result<()> {
+    let permission_error = || {
+        anyhow!(
+            "The {kind} path {:#?} cannot be accessed - check its permissions.",
+            path
+        )
+    };
+    match fs::metadata(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(anyhow!(
+                "The {kind} path {:#?} does not exist - please create it.",
+                path
+            ));
+        }
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => return Err(permission_error()),
+        Err(e) => return Err(anyhow!("The {kind} path {:#?} cannot be read: {e}", path)),
+        Ok(meta) if !meta.is_dir() => {
+            return Err(anyhow!(
+                "The {kind} path {:#?} is a file, not a directory.",
+                path
+            ));
+        }
+        Ok(_) => {}
+    }
+    if fs::read_dir(path).is_err() {
+        return Err(permission_error());
+    }
+    Ok(())
+}
+
+/// Instructions on how to deserialize a list of path objects.
+fn deserialize_paths<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|s| normalize_path(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
 /// Instructions on how to deserialize a path object.
 fn deserialize_path<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
 where
@@ -53,14 +176,36 @@ where
     normalize_path(s).map_err(serde::de::Error::custom)
 }
 
+/// Instructions on how to deserialize the storage path, which is canonicalized
+/// so that a nonsensical path is reported early. A path that does not exist is
+/// kept as given, so that it can be created or reported when validating.
+fn deserialize_storage_path<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let path = deserialize_path(deserializer)?;
+    match path.try_exists() {
+        Ok(false) => Ok(path),
+        _ => canonicalize(&path).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Instructions on how to deserialize an optional path object.
+fn deserialize_optional_path<'de, D>(deserializer: D) -> Result<Option<PathBuf>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_path(deserializer).map(Some)
+}
+
 #[cfg(test)]
 mod test {
-    use std::fs;
-
     use super::*;
 
+    use rstest::rstest;
+
     fn parse_toml(storage: &str) -> Result<AuxillaryPaths, toml::de::Error> {
-        return toml::from_str(&format! {"storage = {:#?}\n", storage });
+        toml::from_str(&format! {"storage = {:#?}\n", storage })
     }
 
     #[test]
@@ -70,16 +215,74 @@ mod test {
         assert_ne!(result, PathBuf::from(given));
     }
 
+    #[rstest]
+    #[case("$TIDYMONEY_TEST_FINANCE_DIR/money", "/finance/money")]
+    #[case("${TIDYMONEY_TEST_FINANCE_DIR}/money", "/finance/money")]
+    #[case("/plain/money", "/plain/money")]
+    fn test_normalize_path_expands_variables(#[case] given: &str, #[case] expected: &str) {
+        std::env::set_var("TIDYMONEY_TEST_FINANCE_DIR", "/finance");
+        assert_eq!(normalize_path(given).unwrap(), PathBuf::from(expected));
+    }
+
+    #[test]
+    fn test_normalize_path_unset_variable() {
+        let result = normalize_path("$TIDYMONEY_TEST_UNSET_DIR/money").unwrap_err();
+        assert_eq!(
+            result.to_string(),
+            "The environment variable \"TIDYMONEY_TEST_UNSET_DIR\" used in the path \"$TIDYMONEY_TEST_UNSET_DIR/money\" is not set"
+        );
+        assert!(matches!(result, TidymoneyError::InvalidPath(_)));
+    }
+
+    #[test]
+    fn test_canonicalize_path() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        fs::create_dir_all(temp.path().join("a").join("b")).unwrap();
+        let given = temp.path().join("a").join("..").join("a").join("b");
+
+        // The ".." segments are resolved, which normalize_path leaves alone.
+        let expected = fs::canonicalize(temp.path()).unwrap().join("a").join("b");
+        assert_eq!(canonicalize_path(&given).unwrap(), expected);
+        assert_eq!(normalize_path(&given).unwrap(), given);
+
+        let missing = temp.path().join("a").join("..").join("missing");
+        assert_eq!(
+            canonicalize_path(&missing).unwrap_err().to_string(),
+            format!("The path {:#?} does not exist", missing)
+        );
+    }
+
+    #[test]
+    fn test_storage_is_canonicalized() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        fs::create_dir(temp.path().join("storage")).unwrap();
+        let given = temp.path().join("storage").join("..").join("storage");
+        let parsed = parse_toml(given.to_str().unwrap()).unwrap();
+        assert_eq!(
+            parsed.storage,
+            fs::canonicalize(temp.path()).unwrap().join("storage")
+        );
+    }
+
+    #[test]
+    fn test_storage_through_a_file_is_reported_early() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let file = temp.path().join("file.json");
+        fs::write(&file, "{}").unwrap();
+        let given = file.join("storage");
+        let result = parse_toml(given.to_str().unwrap()).unwrap_err();
+        assert!(result
+            .to_string()
+            .contains(&format!("The path {:#?} cannot be resolved", given)));
+    }
+
     #[test]
     fn test_storage_must_exist() {
         let parsed = parse_toml("/does/not/exist");
-        assert!(parsed
-            .unwrap()
-            .validate()
-            .err()
-            .unwrap()
-            .to_string()
-            .contains("is not a directory"));
+        assert_eq!(
+            parsed.unwrap().validate().err().unwrap().to_string(),
+            "The storage path \"/does/not/exist\" does not exist - please create it."
+        );
     }
 
     #[test]
@@ -88,13 +291,86 @@ mod test {
         let storage = temp.path().join("file.json");
         fs::write(&storage, "{}").unwrap();
         let path = storage.as_os_str().to_str().unwrap();
-        let parsed = parse_toml(&path);
+        let parsed = parse_toml(path);
         assert!(parsed
             .unwrap()
             .validate()
             .err()
             .unwrap()
             .to_string()
-            .contains("is not a directory"));
+            .ends_with("is a file, not a directory."));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_storage_must_be_accessible() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let locked = temp.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Permissions are not enforced for privileged users, so only check
+        // the message if the directory really cannot be read.
+        if fs::read_dir(&locked).is_err() {
+            let parsed = parse_toml(locked.to_str().unwrap());
+            let result = parsed.unwrap().validate().err().unwrap().to_string();
+            assert!(result.ends_with("cannot be accessed - check its permissions."));
+        }
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_default_directory_names() {
+        let parsed = parse_toml("/tmp").unwrap();
+        assert_eq!(parsed.output_dir, "new");
+        assert_eq!(parsed.archive_dir, "old");
+    }
+
+    #[test]
+    fn test_custom_directory_names() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let parsed: AuxillaryPaths = toml::from_str(&format!(
+            "storage = {:#?}\noutput_dir = \"to-import\"\narchive_dir = \"downloaded\"\n",
+            temp.path()
+        ))
+        .unwrap();
+        assert_eq!(parsed.output_dir, "to-import");
+        assert_eq!(parsed.archive_dir, "downloaded");
+        assert!(parsed.validate().is_ok());
+    }
+
+    #[test]
+    fn test_mirrors_must_exist() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let parsed: AuxillaryPaths = toml::from_str(&format!(
+            "storage = {:#?}\nmirrors = [{:#?}, \"/does/not/exist\"]\n",
+            temp.path(),
+            temp.path()
+        ))
+        .unwrap();
+        assert_eq!(parsed.mirrors.len(), 2);
+        assert_eq!(
+            parsed.validate().unwrap_err().to_string(),
+            "The mirror path \"/does/not/exist\" does not exist - please create it."
+        );
+    }
+
+    #[rstest]
+    #[case("", "old", "must not be empty")]
+    #[case("new", "", "must not be empty")]
+    #[case("same", "same", "must be different")]
+    fn test_directory_names_must_be_valid(
+        #[case] output_dir: &str,
+        #[case] archive_dir: &str,
+        #[case] expected: &str,
+    ) {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let mut paths = AuxillaryPaths::new(temp.path());
+        paths.output_dir = output_dir.to_string();
+        paths.archive_dir = archive_dir.to_string();
+        let result = paths.validate().unwrap_err();
+        assert!(result.to_string().contains(expected));
     }
 }
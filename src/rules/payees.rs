@@ -1,13 +1,22 @@
 use std::{collections::HashMap, fmt, marker::PhantomData, str::FromStr};
 
-use anyhow::Result;
-use regex::Regex;
+use anyhow::{anyhow, Result};
 use rust_decimal::Decimal;
-use serde::de::{MapAccess, Visitor};
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{IntoDeserializer, MapAccess, SeqAccess, Visitor};
 use serde::{Deserialize, Deserializer};
 
-use crate::rules::date_filter::{date_is_outside_range, validate_date_filters};
-use crate::rules::eqregex::{deserialize_regex, EqRegex};
+use crate::rules::amount_filter::AmountFilters;
+use crate::rules::category_and_memo::true_value;
+use crate::rules::date_filter::{
+    date_is_outside_periods, date_is_outside_range, validate_date_filters, validate_periods,
+    Recurrence,
+};
+use crate::rules::eqregex::{
+    deserialize_option_literal, deserialize_option_regex, deserialize_option_regex_list, EqRegex,
+};
+use crate::rules::fuzzy::{best_similarity, validate_min_similarity, DEFAULT_MIN_SIMILARITY};
+use crate::rules::note::Note;
 use crate::NormalizedBankData;
 
 /// Rules for specifying how to map a payee pattern to a specific payee.
@@ -15,15 +24,45 @@ use crate::NormalizedBankData;
 #[derive(Debug, Deserialize, PartialEq, Eq, Hash)]
 #[serde(deny_unknown_fields, rename_all = "PascalCase")]
 pub struct PayeeRules {
-    /// The pattern to use to identify a payee.
-    #[serde(deserialize_with = "deserialize_regex")]
-    pattern: EqRegex,
+    /// The patterns to use to identify a payee - any one of them may match.
+    #[serde(default, deserialize_with = "deserialize_option_regex_list")]
+    pattern: Option<Vec<EqRegex>>,
+    /// Patterns that must all match the payee.
+    #[serde(default, deserialize_with = "deserialize_option_regex_list")]
+    all_patterns: Option<Vec<EqRegex>>,
+    /// Literal text (not a regular expression) the payee must contain.
+    #[serde(default, deserialize_with = "deserialize_option_literal")]
+    contains: Option<EqRegex>,
+    /// Whether the Pattern or Contains must match the entire payee rather than part of it.
+    #[serde(default)]
+    whole_match: bool,
+    /// Text that a run of words in the payee must be similar to, allowing for typos.
+    fuzzy_contains: Option<String>,
+    /// How similar (from 0 to 1) the payee must be to the FuzzyContains text.
+    min_similarity: Option<Decimal>,
+    /// The pattern to use to identify a payee by the address of the transaction.
+    #[serde(default, deserialize_with = "deserialize_option_regex")]
+    address: Option<EqRegex>,
     /// The minimum range that a transaction must be for to identify as this payee.
     min_amount: Option<Decimal>,
     /// The maximum range that a transaction must be for to identify as this payee.
     max_amount: Option<Decimal>,
+    /// Whether a transaction must be strictly greater than the minimum range.
+    #[serde(default)]
+    min_amount_exclusive: bool,
+    /// Whether a transaction must be strictly less than the maximum range.
+    #[serde(default)]
+    max_amount_exclusive: bool,
     /// The exact amount that a transaction must be for to identify as this payee.
     amount: Option<Decimal>,
+    /// How far (as a percentage of the amount) a transaction may be from the amount.
+    amount_percent_tolerance: Option<Decimal>,
+    /// Whether to compare amounts with their sign rather than in absolute value.
+    #[serde(default)]
+    signed_amounts: bool,
+    /// Whether the amount must be a whole number of dollars (i.e. have no cents).
+    #[serde(default)]
+    whole_dollar: bool,
     /// The lowest date in the month that a transaction can have to identify as this payee.
     min_date_in_month: Option<u32>,
     /// The highest date in the month that a transaction can have to identify as this payee.
@@ -32,56 +71,182 @@ pub struct PayeeRules {
     min_date_in_year: Option<(u32, u32)>,
     /// The highest date in the year that a transaction can have to identify as this payee.
     max_date_in_year: Option<(u32, u32)>,
+    /// The calendar quarters (1 to 4) that a transaction must be in to match.
+    #[serde(default)]
+    quarter: Vec<u32>,
+    /// The months (1 to 12) that a transaction must be in to match.
+    #[serde(default)]
+    months: Vec<u32>,
+    /// The cadence on which a transaction must recur to identify as this payee.
+    recurrence: Option<Recurrence>,
+    /// The label of the account that a transaction must be from to identify as this payee.
+    account: Option<String>,
+    /// A human-readable note describing this rule - not used for matching.
+    #[serde(default)]
+    note: Note,
+    /// Whether or not this rule is used - a disabled rule never matches.
+    #[serde(default = "true_value")]
+    enabled: bool,
 }
 
 impl PayeeRules {
     /// Construct a new object - only needed for testing.
     #[cfg(test)]
     pub fn new(mapping: HashMap<String, String>) -> Self {
-        let pattern = mapping
-            .get("pattern")
-            .and_then(|x| Some(EqRegex(Regex::new(x).unwrap())))
-            .unwrap();
+        let pattern = mapping.get("pattern").map(|x| {
+            x.split(',')
+                .filter(|x| !x.is_empty())
+                .map(|x| EqRegex::new(x).unwrap())
+                .collect()
+        });
+        let all_patterns = mapping.get("all_patterns").map(|x| {
+            x.split(',')
+                .filter(|x| !x.is_empty())
+                .map(|x| EqRegex::new(x).unwrap())
+                .collect()
+        });
+        let contains = mapping
+            .get("contains")
+            .map(|x| EqRegex::new(&regex::escape(x)).unwrap());
+        let whole_match = mapping.get("whole_match").is_some_and(|x| x == "true");
+        let fuzzy_contains = mapping.get("fuzzy_contains").map(|x| x.to_owned());
+        let min_similarity = mapping
+            .get("min_similarity")
+            .and_then(|x| Decimal::from_str_exact(x).ok());
+        let address = mapping.get("address").map(|x| EqRegex::new(x).unwrap());
         let min_amount = mapping
             .get("min_amount")
             .and_then(|x| Decimal::from_str_exact(x).ok());
         let max_amount = mapping
             .get("max_amount")
             .and_then(|x| Decimal::from_str_exact(x).ok());
+        let min_amount_exclusive = mapping
+            .get("min_amount_exclusive")
+            .is_some_and(|x| x.to_lowercase() == "true");
+        let max_amount_exclusive = mapping
+            .get("max_amount_exclusive")
+            .is_some_and(|x| x.to_lowercase() == "true");
         let amount = mapping
             .get("amount")
             .and_then(|x| Decimal::from_str_exact(x).ok());
-        let (min_date_in_month, max_date_in_month, min_date_in_year, max_date_in_year) =
+        let amount_percent_tolerance = mapping
+            .get("amount_percent_tolerance")
+            .and_then(|x| Decimal::from_str_exact(x).ok());
+        let signed_amounts = mapping
+            .get("signed_amounts")
+            .is_some_and(|x| x.to_lowercase() == "true");
+        let whole_dollar = mapping
+            .get("whole_dollar")
+            .is_some_and(|x| x.to_lowercase() == "true");
+        let account = mapping.get("account").map(|x| x.to_owned());
+        let note = Note(mapping.get("note").map(|x| x.to_owned()));
+        let enabled = mapping
+            .get("enabled")
+            .is_none_or(|x| x.to_lowercase() == "true");
+        let (quarter, months) = crate::rules::date_filter::process_period_mapping(&mapping);
+        let recurrence = crate::rules::date_filter::process_recurrence_mapping(&mapping);
+        let ((min_date_in_month, max_date_in_month), (min_date_in_year, max_date_in_year)) =
             crate::rules::date_filter::process_date_filter_mapping(mapping);
         PayeeRules {
             pattern,
+            all_patterns,
+            contains,
+            whole_match,
+            fuzzy_contains,
+            min_similarity,
+            address,
             min_amount,
             max_amount,
+            min_amount_exclusive,
+            max_amount_exclusive,
             amount,
+            amount_percent_tolerance,
+            signed_amounts,
+            whole_dollar,
             min_date_in_month,
             max_date_in_month,
             min_date_in_year,
             max_date_in_year,
+            quarter,
+            months,
+            recurrence,
+            account,
+            note,
+            enabled,
         }
     }
 
+    /// The label of the account to which this rule is restricted, if any.
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// Whether or not this rule is used.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
     /// Determine if the given transaction matches this set of rules.
     pub fn transaction_matches(&self, transaction: &NormalizedBankData) -> bool {
+        // A disabled rule never matches.
+        if !self.enabled {
+            return false;
+        }
+
+        // If the transaction is not from the required account it cannot be a match.
+        if self
+            .account
+            .as_ref()
+            .is_some_and(|a| *a != transaction.label)
+        {
+            return false;
+        }
+
         // If the amount does not fall in the value ranges it cannot be a match.
-        let min_amt = self.min_amount.unwrap_or(Decimal::ZERO).abs();
-        let max_amt = self.max_amount.unwrap_or(Decimal::MAX).abs();
-        let amt = transaction.amount.abs();
-        if !(amt >= min_amt && amt <= max_amt) {
+        if self.amount_filters().is_outside_range(transaction.amount) {
             return false;
         }
 
-        // If the amount is not equal to the target it cannot be a match.
-        if self.amount.is_some_and(|x| x.abs() != amt) {
+        // If the payee does not match the pattern or literal text it cannot be a match.
+        let payee_matches = |regex: &EqRegex| {
+            if self.whole_match {
+                regex.is_whole_match(&transaction.orig_payee)
+            } else {
+                regex.is_match(&transaction.orig_payee)
+            }
+        };
+        if self
+            .pattern
+            .as_ref()
+            .is_some_and(|p| !p.iter().any(payee_matches))
+        {
+            return false;
+        }
+        if self
+            .all_patterns
+            .as_ref()
+            .is_some_and(|p| !p.iter().all(payee_matches))
+        {
+            return false;
+        }
+        if self.contains.as_ref().is_some_and(|c| !payee_matches(c)) {
+            return false;
+        }
+        if self.fuzzy_contains.as_ref().is_some_and(|f| {
+            best_similarity(f, &transaction.orig_payee)
+                < self.min_similarity.unwrap_or(DEFAULT_MIN_SIMILARITY)
+        }) {
             return false;
         }
 
-        // If the payee does not match the pattern it cannot be a match.
-        if !self.pattern.is_match(&transaction.orig_payee) {
+        // If an address pattern is provided and the address does not match it
+        // cannot be a match. A transaction without an address cannot match.
+        if self.address.as_ref().is_some_and(|a| {
+            !transaction
+                .address
+                .as_ref()
+                .is_some_and(|ta| a.is_match(ta))
+        }) {
             return false;
         }
 
@@ -90,40 +255,121 @@ impl PayeeRules {
             &transaction.date,
             (self.min_date_in_month, self.max_date_in_month),
             (self.min_date_in_year, self.max_date_in_year),
-        ) {
+        ) || date_is_outside_periods(&transaction.date, &self.quarter, &self.months)
+            || self
+                .recurrence
+                .as_ref()
+                .is_some_and(|r| r.is_off_cadence(&transaction.date))
+        {
             return false;
         }
 
         true
     }
 
+    /// The amount filters given for this rule.
+    fn amount_filters(&self) -> AmountFilters {
+        AmountFilters {
+            amount: self.amount,
+            min_amount: self.min_amount,
+            max_amount: self.max_amount,
+            min_exclusive: self.min_amount_exclusive,
+            max_exclusive: self.max_amount_exclusive,
+            percent_tolerance: self.amount_percent_tolerance,
+            signed: self.signed_amounts,
+            whole_dollar: self.whole_dollar,
+        }
+    }
+
+    /// Describe the payee this rule belongs to, including the rule's note if given.
+    pub fn describe(&self, name: &str) -> String {
+        self.note.describe(name)
+    }
+
     /// Ensure the given rules are semantically correct.
     pub fn validate(&self, name: &str) -> Result<()> {
+        let name = &self.describe(name);
+        if self.pattern.is_none()
+            && self.all_patterns.is_none()
+            && self.contains.is_none()
+            && self.fuzzy_contains.is_none()
+        {
+            return Err(anyhow!(
+                "The payee {name:#?} must specify at least one of Pattern, AllPatterns, Contains, or FuzzyContains."
+            ));
+        }
+        if self.pattern.as_ref().is_some_and(Vec::is_empty) {
+            return Err(anyhow!("The payee {name:#?} gives an empty Pattern list."));
+        }
+        if self.all_patterns.as_ref().is_some_and(Vec::is_empty) {
+            return Err(anyhow!(
+                "The payee {name:#?} gives an empty AllPatterns list."
+            ));
+        }
+        if self
+            .fuzzy_contains
+            .as_deref()
+            .is_some_and(|x| x.trim().is_empty())
+        {
+            return Err(anyhow!("The payee {name:#?} gives an empty FuzzyContains."));
+        }
+        if let Some(min_similarity) = self.min_similarity {
+            if self.fuzzy_contains.is_none() {
+                return Err(anyhow!(
+                    "The payee {name:#?} gives MinSimilarity without FuzzyContains."
+                ));
+            }
+            validate_min_similarity("payee", name, min_similarity)?;
+        }
+        self.amount_filters().validate("payee", name)?;
         validate_date_filters(
             "payee",
             name,
             (self.min_date_in_month, self.max_date_in_month),
             (self.min_date_in_year, self.max_date_in_year),
-        )
+        )?;
+        validate_periods("payee", name, &self.quarter, &self.months)?;
+        if let Some(recurrence) = &self.recurrence {
+            recurrence.validate("payee", name)?;
+        }
+        Ok(())
     }
 }
 
 /// Create this PayeeRules from a string.
 impl FromStr for PayeeRules {
-    type Err = void::Void;
+    type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = EqRegex::new(s).map_err(|e| {
+            anyhow!("Could not parse the string {s:#?} as a regular expression: {e}")
+        })?;
         Ok(PayeeRules {
-            pattern: EqRegex(Regex::new(s).unwrap_or_else(|_| {
-                panic!("Could not parse the string {s} as a regular expression")
-            })),
+            pattern: Some(vec![pattern]),
+            all_patterns: None,
+            contains: None,
+            whole_match: false,
+            fuzzy_contains: None,
+            min_similarity: None,
+            address: None,
             min_amount: None,
             max_amount: None,
+            min_amount_exclusive: false,
+            max_amount_exclusive: false,
             amount: None,
+            amount_percent_tolerance: None,
+            signed_amounts: false,
+            whole_dollar: false,
             min_date_in_month: None,
             max_date_in_month: None,
             min_date_in_year: None,
             max_date_in_year: None,
+            quarter: vec![],
+            months: vec![],
+            recurrence: None,
+            account: None,
+            note: Note::default(),
+            enabled: true,
         })
     }
 }
@@ -132,7 +378,8 @@ impl FromStr for PayeeRules {
 /// Lifted from https://serde.rs/string-or-struct.html
 fn string_or_struct<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
-    T: Deserialize<'de> + FromStr<Err = void::Void>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: fmt::Display,
     D: Deserializer<'de>,
 {
     // This is a Visitor that forwards string types to T's `FromStr` impl and
@@ -144,7 +391,8 @@ where
 
     impl<'de, T> Visitor<'de> for StringOrStruct<T>
     where
-        T: Deserialize<'de> + FromStr<Err = void::Void>,
+        T: Deserialize<'de> + FromStr,
+        T::Err: fmt::Display,
     {
         type Value = T;
 
@@ -156,7 +404,7 @@ where
         where
             E: serde::de::Error,
         {
-            Ok(FromStr::from_str(value).unwrap())
+            FromStr::from_str(value).map_err(E::custom)
         }
 
         fn visit_map<M>(self, map: M) -> Result<T, M::Error>
@@ -167,7 +415,7 @@ where
             // into a `Deserializer`, allowing it to be used as the input to T's
             // `Deserialize` implementation. T then deserializes itself using
             // the entries from the map visitor.
-            Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+            Deserialize::deserialize(MapAccessDeserializer::new(map))
         }
     }
 
@@ -182,43 +430,66 @@ pub fn hashmap_payee_rules<'de, D>(
 where
     D: Deserializer<'de>,
 {
-    /// Function to tell how to deserialize PayeeRules from a sequence.
-    fn vec_payee_rules<'de, D>(deserializer: D) -> Result<Vec<PayeeRules>, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        #[derive(Deserialize)]
-        struct Wrapper(#[serde(deserialize_with = "string_or_struct")] PayeeRules);
+    /// One or more PayeeRules. This is not an untagged enum so that
+    /// errors from within a rule are reported rather than swallowed.
+    struct OneOrMany(Vec<PayeeRules>);
 
-        let v = Vec::deserialize(deserializer)?;
-        Ok(v.into_iter().map(|Wrapper(a)| a).collect())
-    }
+    impl<'de> Deserialize<'de> for OneOrMany {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            /// Forward strings and maps to a single rule and sequences to many.
+            struct OneOrManyVisitor;
+
+            impl<'de> Visitor<'de> for OneOrManyVisitor {
+                type Value = OneOrMany;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    formatter.write_str("string, map, or sequence of strings or maps")
+                }
+
+                fn visit_str<E>(self, value: &str) -> Result<OneOrMany, E>
+                where
+                    E: serde::de::Error,
+                {
+                    string_or_struct(value.into_deserializer()).map(|rule| OneOrMany(vec![rule]))
+                }
+
+                fn visit_map<M>(self, map: M) -> Result<OneOrMany, M::Error>
+                where
+                    M: MapAccess<'de>,
+                {
+                    string_or_struct(MapAccessDeserializer::new(map))
+                        .map(|rule| OneOrMany(vec![rule]))
+                }
 
-    // Deserializer in either sequence or a scalar.
-    #[derive(Deserialize)]
-    #[serde(untagged)]
-    enum PayeeValue {
-        #[serde(deserialize_with = "vec_payee_rules")]
-        VecForm(Vec<PayeeRules>),
-        #[serde(deserialize_with = "string_or_struct")]
-        ScalarForm(PayeeRules),
+                fn visit_seq<S>(self, seq: S) -> Result<OneOrMany, S::Error>
+                where
+                    S: SeqAccess<'de>,
+                {
+                    #[derive(Deserialize)]
+                    struct Wrapper(#[serde(deserialize_with = "string_or_struct")] PayeeRules);
+
+                    let v = Vec::deserialize(SeqAccessDeserializer::new(seq))?;
+                    Ok(OneOrMany(v.into_iter().map(|Wrapper(a)| a).collect()))
+                }
+            }
+
+            deserializer.deserialize_any(OneOrManyVisitor)
+        }
     }
 
     // Choose the correct deserializer based on the data format.
-    let v =
-        HashMap::<String, PayeeValue>::deserialize(deserializer)?;
-    Ok(v.into_iter()
-        .map(|(k, v)| match v {
-            PayeeValue::VecForm(seq) => (k, seq),
-            PayeeValue::ScalarForm(scalar) => (k, vec![scalar]),
-        })
-        .collect())
+    let v = HashMap::<String, OneOrMany>::deserialize(deserializer)?;
+    Ok(v.into_iter().map(|(k, OneOrMany(v))| (k, v)).collect())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use indoc::indoc;
     use pretty_assertions::assert_eq;
     use rstest::rstest;
 
@@ -355,6 +626,141 @@ mod test {
         vec![("Payee", "ACE"), ("Date", "2024-11-24"), ("Amount", "-15.43")],
         false,
     )]
+    #[case(
+        vec![("pattern", "ACE"), ("quarter", "1")],
+        vec![("Payee", "ACE"), ("Date", "2024-03-31"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "ACE"), ("quarter", "1")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-01"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "ACE"), ("months", "11,12")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-01"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "PAYPAL"), ("address", "CA")],
+        vec![("Payee", "PAYPAL INST XFER"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Address", "402-935-7733  CA ")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "PAYPAL"), ("address", "CA")],
+        vec![("Payee", "PAYPAL INST XFER"), ("Date", "2024-04-03"), ("Amount", "-15.43"), ("Address", "402-935-7733  NY ")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "PAYPAL"), ("address", "CA")],
+        vec![("Payee", "PAYPAL INST XFER"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("contains", "Visa.")],
+        vec![("Payee", "Visa. Payment"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("contains", "Visa.")],
+        vec![("Payee", "VisaX Payment"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "Visa.")],
+        vec![("Payee", "VisaX Payment"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "^Visa"), ("contains", "(ONLINE)")],
+        vec![("Payee", "Visa Payment (ONLINE)"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "^Visa"), ("contains", "(ONLINE)")],
+        vec![("Payee", "Visa Payment ONLINE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "Subway"), ("whole_match", "true")],
+        vec![("Payee", "Subway"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "Subway"), ("whole_match", "true")],
+        vec![("Payee", "Subway Surfers Refund"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "Subway"), ("whole_match", "false")],
+        vec![("Payee", "Subway Surfers Refund"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "Sub|Subway"), ("whole_match", "true")],
+        vec![("Payee", "Subway"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("contains", "Visa."), ("whole_match", "true")],
+        vec![("Payee", "Visa."), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("contains", "Visa."), ("whole_match", "true")],
+        vec![("Payee", "Visa. Payment"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "Sub,Wendy"), ("whole_match", "true")],
+        vec![("Payee", "Wendy"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "Sub,Wendy"), ("whole_match", "true")],
+        vec![("Payee", "Wendy's"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("all_patterns", "PAYPAL,XFER")],
+        vec![("Payee", "PAYPAL INST XFER"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("all_patterns", "PAYPAL,XFER")],
+        vec![("Payee", "PAYPAL INST PMT"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("all_patterns", "PAYPAL,XFER")],
+        vec![("Payee", "BANK XFER"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "INST"), ("all_patterns", "PAYPAL,XFER")],
+        vec![("Payee", "PAYPAL XFER"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "ACE"), ("min_amount", "15.43")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "ACE"), ("min_amount", "15.43"), ("min_amount_exclusive", "true")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
+    #[case(
+        vec![("pattern", "ACE"), ("account", "testing")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        true,
+    )]
+    #[case(
+        vec![("pattern", "ACE"), ("account", "business")],
+        vec![("Payee", "ACE"), ("Date", "2024-04-03"), ("Amount", "-15.43")],
+        false,
+    )]
     fn test_transaction_matches(
         #[case] given: Vec<(&str, &str)>,
         #[case] txn_data: Vec<(&str, &str)>,
@@ -365,10 +771,253 @@ mod test {
         assert_eq!(result, expected);
     }
 
+    #[rstest]
+    #[case("102.50", true)]
+    #[case("-97.00", true)]
+    #[case("104.00", false)]
+    fn test_amount_percent_tolerance(#[case] amount: &str, #[case] expected: bool) {
+        let given = vec![
+            ("pattern", "CARD FEE"),
+            ("amount", "100.00"),
+            ("amount_percent_tolerance", "3"),
+        ];
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", "CARD FEE"),
+            ("Date", "2024-04-03"),
+            ("Amount", amount),
+        ]));
+        let result = PayeeRules::new(as_hashmap(given)).transaction_matches(&transaction);
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case("Amount = 15.430", "-15.43", true)]
+    #[case("Amount = 15.43", "-15.4300", true)]
+    #[case("Amount = 15", "-15.00", true)]
+    #[case("Amount = 15.43", "-15.431", false)]
+    fn test_amount_with_different_scales(
+        #[case] option: &str,
+        #[case] amount: &str,
+        #[case] expected: bool,
+    ) {
+        let rule: PayeeRules =
+            toml::from_str(&format!("Pattern = \"CARD FEE\"\n{option}")).unwrap();
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", "CARD FEE"),
+            ("Date", "2024-04-03"),
+            ("Amount", amount),
+        ]));
+        assert_eq!(rule.transaction_matches(&transaction), expected);
+    }
+
+    #[rstest]
+    #[case("", "-75.00", true)]
+    #[case("", "-25.00", false)]
+    #[case("SignedAmounts = true", "-75.00", false)]
+    #[case("SignedAmounts = true", "-25.00", true)]
+    #[case("SignedAmounts = true", "25.00", true)]
+    fn test_signed_amounts(#[case] option: &str, #[case] amount: &str, #[case] expected: bool) {
+        let rule: PayeeRules = toml::from_str(&format!(
+            "Pattern = \"REFUND\"\nMinAmount = -50.00\n{option}"
+        ))
+        .unwrap();
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", "REFUND"),
+            ("Date", "2024-04-03"),
+            ("Amount", amount),
+        ]));
+        assert_eq!(rule.transaction_matches(&transaction), expected);
+    }
+
+    #[rstest]
+    #[case("2024-01-19", true)]
+    #[case("2024-01-20", true)]
+    #[case("2024-01-12", false)]
+    fn test_recurrence(#[case] date: &str, #[case] expected: bool) {
+        let rule: PayeeRules = toml::from_str(indoc! { r#"
+        Pattern = "PAYROLL"
+        Recurrence = {EveryNWeeks = 2, Anchor = "2024-01-05"}
+        "# })
+        .unwrap();
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", "ACME PAYROLL"),
+            ("Date", date),
+            ("Amount", "1000.00"),
+        ]));
+        assert_eq!(rule.transaction_matches(&transaction), expected);
+    }
+
+    #[test]
+    fn test_from_str_invalid_regex() {
+        let result = PayeeRules::from_str("APPLE(").unwrap_err();
+        assert!(result
+            .to_string()
+            .starts_with("Could not parse the string \"APPLE(\" as a regular expression"));
+    }
+
     #[test]
     fn test_validate_works() {
         let given = vec![("pattern", "ACE"), ("max_date_in_year", "3/40")];
         let result = PayeeRules::new(as_hashmap(given)).validate("test");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_note_is_parsed_and_ignored_for_matching() {
+        let rule: PayeeRules = toml::from_str(indoc! { r#"
+        Pattern = "ACE"
+        Note = "Hardware store"
+        "# })
+        .unwrap();
+        assert_eq!(rule.describe("Ace"), "Ace (Hardware store)");
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", "ACE"),
+            ("Date", "2024-04-03"),
+            ("Amount", "-15.43"),
+        ]));
+        assert!(rule.transaction_matches(&transaction));
+    }
+
+    #[rstest]
+    #[case("", true)]
+    #[case("Enabled = true", true)]
+    #[case("Enabled = false", false)]
+    fn test_enabled(#[case] option: &str, #[case] expected: bool) {
+        let rule: PayeeRules = toml::from_str(&format!("Pattern = \"ACE\"\n{option}")).unwrap();
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", "ACE"),
+            ("Date", "2024-04-03"),
+            ("Amount", "-15.43"),
+        ]));
+        assert_eq!(rule.transaction_matches(&transaction), expected);
+    }
+
+    #[test]
+    fn test_validate_reports_note() {
+        let given = vec![
+            ("pattern", "ACE"),
+            ("amount_percent_tolerance", "3"),
+            ("note", "Hardware store"),
+        ];
+        let result = PayeeRules::new(as_hashmap(given)).validate("test");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("The payee \"test (Hardware store)\""));
+    }
+
+    #[rstest]
+    #[case("AMAZON MKTPL", "-25.00", true)]
+    #[case("AMZN Mktp US", "-25.00", true)]
+    #[case("AMAZON MKTPL", "-0.50", false)]
+    #[case("AMZN Mktp US", "-0.50", false)]
+    #[case("WALMART", "-25.00", false)]
+    fn test_pattern_list(#[case] payee: &str, #[case] amount: &str, #[case] expected: bool) {
+        // The amount applies whichever of the patterns matched.
+        let rule: PayeeRules =
+            toml::from_str("Pattern = [\"AMAZON\", \"AMZN\"]\nMinAmount = 1.00").unwrap();
+        assert!(rule.validate("Amazon").is_ok());
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", payee),
+            ("Date", "2024-04-03"),
+            ("Amount", amount),
+        ]));
+        assert_eq!(rule.transaction_matches(&transaction), expected);
+    }
+
+    #[rstest]
+    #[case("Pattern = []")]
+    #[case("Pattern = []\nContains = \"AMAZON\"")]
+    fn test_validate_rejects_empty_pattern_list(#[case] given: &str) {
+        let rule: PayeeRules = toml::from_str(given).unwrap();
+        assert_eq!(
+            rule.validate("Amazon").unwrap_err().to_string(),
+            "The payee \"Amazon\" gives an empty Pattern list."
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_empty_all_patterns_list() {
+        let rule: PayeeRules = toml::from_str("AllPatterns = []").unwrap();
+        assert_eq!(
+            rule.validate("Transfer").unwrap_err().to_string(),
+            "The payee \"Transfer\" gives an empty AllPatterns list."
+        );
+        let rule: PayeeRules = toml::from_str("AllPatterns = [\"PAYPAL\", \"XFER\"]").unwrap();
+        assert!(rule.validate("Transfer").is_ok());
+    }
+
+    #[test]
+    fn test_validate_requires_pattern_or_contains() {
+        let given = vec![("amount", "15.43")];
+        let result = PayeeRules::new(as_hashmap(given)).validate("test");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The payee \"test\" must specify at least one of Pattern, AllPatterns, Contains, or FuzzyContains."
+        );
+        let given = vec![("contains", "ACE")];
+        assert!(PayeeRules::new(as_hashmap(given)).validate("test").is_ok());
+    }
+
+    #[rstest]
+    #[case("", "STARBUCS #123", true)]
+    #[case("", "STARBUCKS COFFEE SEATTLE WA", true)]
+    #[case("", "SHELL OIL 57442", false)]
+    #[case("", "STARBS", false)]
+    #[case("MinSimilarity = 0.6", "STARBS", true)]
+    #[case("MinSimilarity = 1", "STARBUCS #123", false)]
+    fn test_fuzzy_contains(#[case] option: &str, #[case] payee: &str, #[case] expected: bool) {
+        let rule: PayeeRules =
+            toml::from_str(&format!("FuzzyContains = \"STARBUCKS\"\n{option}")).unwrap();
+        assert!(rule.validate("Starbucks").is_ok());
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", payee),
+            ("Date", "2024-04-03"),
+            ("Amount", "-5.75"),
+        ]));
+        assert_eq!(rule.transaction_matches(&transaction), expected);
+    }
+
+    #[rstest]
+    #[case(
+        vec![("fuzzy_contains", "STARBUCKS"), ("min_similarity", "1.2")],
+        "The payee \"test\" must have a MinSimilarity greater than 0 and at most 1, found 1.2."
+    )]
+    #[case(
+        vec![("pattern", "STARBUCKS"), ("min_similarity", "0.9")],
+        "The payee \"test\" gives MinSimilarity without FuzzyContains."
+    )]
+    #[case(
+        vec![("fuzzy_contains", "")],
+        "The payee \"test\" gives an empty FuzzyContains."
+    )]
+    #[case(
+        vec![("fuzzy_contains", "  ")],
+        "The payee \"test\" gives an empty FuzzyContains."
+    )]
+    fn test_validate_fuzzy_contains(#[case] given: Vec<(&str, &str)>, #[case] expected: &str) {
+        let result = PayeeRules::new(as_hashmap(given)).validate("test");
+        assert_eq!(result.unwrap_err().to_string(), expected);
+    }
+
+    #[test]
+    fn test_contains_is_parsed_literally() {
+        let rule: PayeeRules = toml::from_str("Contains = \"A+B (C)\"").unwrap();
+        let transaction = NormalizedBankData::new(as_hashmap(vec![
+            ("Payee", "SHOP A+B (C) INC"),
+            ("Date", "2024-04-03"),
+            ("Amount", "-15.43"),
+        ]));
+        assert!(rule.transaction_matches(&transaction));
+    }
+
+    #[test]
+    fn test_validate_amount_filters() {
+        let given = vec![("pattern", "ACE"), ("amount_percent_tolerance", "3")];
+        let result = PayeeRules::new(as_hashmap(given)).validate("test");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The payee \"test\" specifies an AmountPercentTolerance without an Amount."
+        );
+    }
 }
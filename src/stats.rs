@@ -0,0 +1,138 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::process::TransactionProcessor;
+use crate::rules::RuleFileData;
+
+/// The category under which transactions without a category are totaled.
+const UNCATEGORIZED: &str = "(uncategorized)";
+
+/// Summary statistics for the transactions of every account.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Stats {
+    /// The statistics for each account, keyed by account label.
+    pub accounts: BTreeMap<String, AccountStats>,
+}
+
+/// Summary statistics for the transactions of a single account.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct AccountStats {
+    /// The number of transactions seen.
+    pub transactions: usize,
+    /// The sum of all transaction amounts.
+    pub total: Decimal,
+    /// The sum of transaction amounts for each category.
+    pub categories: BTreeMap<String, Decimal>,
+    /// The original payees of transactions that no payee rule matched.
+    pub unmatched_payees: BTreeSet<String>,
+}
+
+impl Stats {
+    /// Compute the statistics for the processed transactions of each account.
+    pub fn new(processors: &HashMap<String, TransactionProcessor>, rules: &RuleFileData) -> Self {
+        let mut stats = Stats::default();
+        for (label, processor) in processors {
+            let account = stats.accounts.entry(label.to_owned()).or_default();
+            for transaction in processor.transactions() {
+                account.transactions += 1;
+                account.total += transaction.amount;
+                let category = transaction.category.as_deref().unwrap_or(UNCATEGORIZED);
+                *account.categories.entry(category.to_owned()).or_default() += transaction.amount;
+                if !rules.has_payee_rule_for(transaction) {
+                    account
+                        .unmatched_payees
+                        .insert(transaction.orig_payee.to_owned());
+                }
+            }
+        }
+        stats
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (label, account) in &self.accounts {
+            writeln!(
+                f,
+                "{label}: {} transactions totaling {}",
+                account.transactions, account.total
+            )?;
+            for (category, total) in &account.categories {
+                writeln!(f, "    {category}: {total}")?;
+            }
+            if !account.unmatched_payees.is_empty() {
+                writeln!(f, "    Payees without a matching rule:")?;
+                for payee in &account.unmatched_payees {
+                    writeln!(f, "        {payee}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::fs;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+    use rust_decimal_macros::dec;
+
+    use crate::process_csv_files;
+
+    #[test]
+    fn test_stats() {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rules = RuleFileData::new(format!(
+            indoc! { r#"
+            [payees]
+            Ace = "ACE HARDWARE"
+
+            [categories]
+            Hardware = {{Payee = "Ace"}}
+
+            [[mappings.csv]]
+            label = "checking"
+            identify = ["Date", "Payee", "Amount"]
+
+            [paths]
+            storage = {:#?}
+            "# },
+            temp.path()
+        ))
+        .unwrap();
+        let csv = temp.path().join("checking.csv");
+        fs::write(
+            &csv,
+            indoc! { r#"
+            Date,Payee,Amount
+            2024-01-05,ACE HARDWARE,-15.43
+            2024-01-07,ACE HARDWARE,-4.57
+            2024-01-09,Wendy's,-12.54
+            "# },
+        )
+        .unwrap();
+
+        let processors = process_csv_files(&vec![csv], &rules).unwrap();
+        let stats = Stats::new(&processors, &rules);
+        let expected = AccountStats {
+            transactions: 3,
+            total: dec!(-32.54),
+            categories: BTreeMap::from([
+                ("(uncategorized)".to_string(), dec!(-12.54)),
+                ("Hardware".to_string(), dec!(-20.00)),
+            ]),
+            unmatched_payees: BTreeSet::from(["Wendy's".to_string()]),
+        };
+        assert_eq!(
+            stats.accounts,
+            BTreeMap::from([("checking".to_string(), expected)])
+        );
+    }
+}
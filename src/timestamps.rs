@@ -1,19 +1,28 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use anyhow::{anyhow, Result};
 use chrono::NaiveDate;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::TidymoneyError;
 
 pub const DATE_FORMAT: &str = "%Y-%m-%d";
 
-/// Based on the rules file path, return where the timestamps path should be.
+/// Based on the rules file path, return where the timestamps path should be,
+/// unless the rules file configures a different location.
 /// The file need not yet exist, so do no validation on that front.
-pub fn timestamps_path(rules_path: impl AsRef<Path>) -> Result<PathBuf> {
+pub fn timestamps_path(
+    rules_path: impl AsRef<Path>,
+    configured: Option<&Path>,
+) -> Result<PathBuf, TidymoneyError> {
+    if let Some(stamps) = configured {
+        return Ok(stamps.to_path_buf());
+    }
     let rules = rules_path.as_ref();
-    let parent = rules
-        .parent()
-        .ok_or_else(|| anyhow!("Cannot get the directory name of {:#?}", rules))?;
+    let parent = rules.parent().ok_or_else(|| {
+        TidymoneyError::InvalidPath(format!("Cannot get the directory name of {:#?}", rules))
+    })?;
     let stamps = parent.join("timestamps.json");
     Ok(stamps)
 }
@@ -45,6 +54,9 @@ struct AccountDate {
         deserialize_with = "deserialize_date"
     )]
     date: NaiveDate,
+    /// Any other fields (such as a hand-written note), kept as-is.
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
 /// The TimestampKeeper keeps track of the most recent timestamps
@@ -53,33 +65,64 @@ struct AccountDate {
 #[derive(Debug, PartialEq)]
 pub struct TimestampKeeper {
     dates: HashMap<String, NaiveDate>,
+    /// Fields other than the account and date, by account.
+    extra: HashMap<String, Map<String, Value>>,
 }
 
 impl TimestampKeeper {
     /// Create a new TimestampKeeper by reading a raw JSON string.
-    pub fn new(raw_data: impl AsRef<str>) -> Result<Self> {
-        let dates_as_vec: Vec<AccountDate> = serde_json::from_str(raw_data.as_ref())?;
+    pub fn new(raw_data: impl AsRef<str>) -> Result<Self, TidymoneyError> {
+        let dates_as_vec: Vec<AccountDate> = serde_json::from_str(raw_data.as_ref())
+            .map_err(|e| TidymoneyError::InvalidTimestamps(e.to_string()))?;
         let dates: HashMap<String, NaiveDate> = dates_as_vec
             .iter()
             .map(|element| (element.account.to_owned(), element.date))
             .collect();
-        Ok(TimestampKeeper { dates })
+        let extra = dates_as_vec
+            .into_iter()
+            .filter(|element| !element.extra.is_empty())
+            .map(|element| (element.account, element.extra))
+            .collect();
+        Ok(TimestampKeeper { dates, extra })
+    }
+
+    /// Create a new TimestampKeeper by reading a JSON file.
+    /// A file that does not yet exist is treated as having no timestamps.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, TidymoneyError> {
+        if !path.as_ref().exists() {
+            return Ok(TimestampKeeper {
+                dates: HashMap::new(),
+                extra: HashMap::new(),
+            });
+        }
+        Self::new(std::fs::read_to_string(path)?)
     }
 
     /// Retrieve the updated timestamps as a (sorted) JSON string.
     pub fn get_updated_stamps(&self) -> Result<String, serde_json::Error> {
-        let mut dates_as_vec: Vec<AccountDate> = self
-            .dates
-            .iter()
+        let dates_as_vec: Vec<AccountDate> = self
+            .sorted_dates()
+            .into_iter()
             .map(|(key, value)| AccountDate {
                 account: key.to_owned(),
-                date: *value,
+                date: value,
+                extra: self.extra.get(key).cloned().unwrap_or_default(),
             })
             .collect();
-        dates_as_vec.sort_by(|x1, x2| x1.account.cmp(&x2.account));
         serde_json::to_string_pretty(&dates_as_vec)
     }
 
+    /// Each account with its stored date, sorted by account.
+    pub fn sorted_dates(&self) -> Vec<(&str, NaiveDate)> {
+        let mut dates: Vec<(&str, NaiveDate)> = self
+            .dates
+            .iter()
+            .map(|(key, value)| (key.as_str(), *value))
+            .collect();
+        dates.sort_by(|x1, x2| x1.0.cmp(x2.0));
+        dates
+    }
+
     /// Update the date stored for a given account if it is later than the stored date.
     pub fn update_date(&mut self, account: impl AsRef<str>, date: &NaiveDate) {
         let this_date = self
@@ -113,12 +156,18 @@ mod test {
     use super::*;
 
     use indoc::indoc;
+    use rstest::rstest;
 
-    #[test]
-    fn test_timestamps_path() {
+    #[rstest]
+    #[case(None, "/this/is/my/path/to/timestamps.json")]
+    #[case(
+        Some("/var/lib/tidymoney/stamps.json"),
+        "/var/lib/tidymoney/stamps.json"
+    )]
+    fn test_timestamps_path(#[case] configured: Option<&str>, #[case] expected: &str) {
         let given = "/this/is/my/path/to/rules.toml";
-        let expected = PathBuf::from_str("/this/is/my/path/to/timestamps.json").unwrap();
-        let result = timestamps_path(given).unwrap();
+        let expected = PathBuf::from_str(expected).unwrap();
+        let result = timestamps_path(given, configured.map(Path::new)).unwrap();
         assert_eq!(result, expected);
     }
 
@@ -162,4 +211,46 @@ mod test {
         let result = stamps.get_updated_stamps().unwrap();
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_invalid_timestamps() {
+        let result = TimestampKeeper::new(r#"[{"account": "VISA"}]"#).unwrap_err();
+        assert!(matches!(result, TidymoneyError::InvalidTimestamps(_)));
+    }
+
+    #[test]
+    fn test_extra_fields_are_kept() {
+        let given = indoc! { r#"
+        [
+            {
+                "account": "PNC",
+                "date": "2024-01-04",
+                "note": "Re-imported after fixing the Ace rule"
+            },
+            {
+                "account": "VISA",
+                "date": "2023-03-15"
+            }
+        ]
+        "#};
+        let expected = indoc! {r#"
+        [
+          {
+            "account": "PNC",
+            "date": "2024-02-01",
+            "note": "Re-imported after fixing the Ace rule"
+          },
+          {
+            "account": "VISA",
+            "date": "2023-03-15"
+          }
+        ]"#};
+        let mut stamps = TimestampKeeper::new(given).unwrap();
+        stamps.update_date("PNC", &NaiveDate::from_ymd_opt(2024, 2, 1).unwrap());
+        let result = stamps.get_updated_stamps().unwrap();
+        assert_eq!(result, expected);
+
+        // Reading the result back gives the same stamps.
+        assert_eq!(TimestampKeeper::new(&result).unwrap(), stamps);
+    }
 }
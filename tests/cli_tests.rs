@@ -0,0 +1,867 @@
+// The location of the rules file is controlled with XDG_CONFIG_HOME,
+// which is not used on Windows.
+#![cfg(not(windows))]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use indoc::indoc;
+use pretty_assertions::assert_eq;
+
+use tidymoney::RuleFileData;
+
+/// Create a configuration directory containing a rules file and a timestamps
+/// file, along with a storage directory. Return the configuration directory.
+fn setup_config(temp: impl AsRef<Path>, stamps: &str) -> PathBuf {
+    let config = temp.as_ref().join("config");
+    let storage = temp.as_ref().join("storage");
+    fs::create_dir_all(config.join("tidymoney")).unwrap();
+    fs::create_dir(&storage).unwrap();
+    fs::write(
+        config.join("tidymoney").join("rules.toml"),
+        format!(
+            indoc! { r#"
+            [payees]
+            Apple = "APPLE"
+
+            [[mappings.csv]]
+            label = "checking"
+            identify = ["Date", "Payee", "Amount"]
+
+            [paths]
+            storage = {:#?}
+            "# },
+            storage
+        ),
+    )
+    .unwrap();
+    fs::write(config.join("tidymoney").join("timestamps.json"), stamps).unwrap();
+    config
+}
+
+/// Create a CSV file of transactions to process and return its path.
+fn setup_csv(temp: impl AsRef<Path>) -> PathBuf {
+    let csv = temp.as_ref().join("checking.csv");
+    fs::write(
+        &csv,
+        indoc! { r#"
+        Date,Payee,Amount
+        2024-10-23,APPLE.COM,-2.99
+        2024-10-21,Wendy's,-12.54
+        "# },
+    )
+    .unwrap();
+    csv
+}
+
+/// Run the tidymoney executable with the given configuration directory.
+fn tidymoney(config: impl AsRef<Path>, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tidymoney"))
+        .args(args)
+        .env("XDG_CONFIG_HOME", config.as_ref())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_run_with_new_transactions() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+
+    let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_run_without_new_transactions() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, r#"[{"account": "checking", "date": "2024-12-31"}]"#);
+    let csv = setup_csv(&temp);
+
+    let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_run_with_directory() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+
+    // Place a CSV file amongst other file types in a directory.
+    let statements = temp.path().join("statements");
+    fs::create_dir(&statements).unwrap();
+    fs::rename(setup_csv(&temp), statements.join("checking.csv")).unwrap();
+    fs::write(statements.join("notes.txt"), "not a CSV").unwrap();
+
+    let output = tidymoney(&config, &["run", statements.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+
+    // Only the CSV file was processed and moved to storage.
+    assert!(!statements.join("checking.csv").exists());
+    assert!(statements.join("notes.txt").exists());
+}
+
+#[test]
+fn test_run_without_files() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let stamps = r#"[{"account": "checking", "date": "2024-10-22"}]"#;
+    let config = setup_config(&temp, stamps);
+    let statements = temp.path().join("statements");
+    fs::create_dir(&statements).unwrap();
+
+    // Giving no paths or an empty directory leaves nothing to process.
+    for args in [vec!["run"], vec!["run", statements.to_str().unwrap()]] {
+        let output = tidymoney(&config, &args);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("No CSV files"));
+    }
+
+    // The timestamps were not touched.
+    assert_eq!(
+        fs::read_to_string(config.join("tidymoney").join("timestamps.json")).unwrap(),
+        stamps
+    );
+}
+
+#[test]
+fn test_run_with_timestamps_location() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+
+    // Keep the timestamps somewhere other than next to the rules file.
+    let state = temp.path().join("state");
+    fs::create_dir(&state).unwrap();
+    let stamps = state.join("stamps.json");
+    fs::write(&stamps, "[]").unwrap();
+    let rules = config.join("tidymoney").join("rules.toml");
+    let mut data = fs::read_to_string(&rules).unwrap();
+    data.push_str(&format!("timestamps = {:#?}\n", stamps));
+    fs::write(&rules, data).unwrap();
+
+    let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_ne!(fs::read_to_string(&stamps).unwrap(), "[]");
+    assert_eq!(
+        fs::read_to_string(config.join("tidymoney").join("timestamps.json")).unwrap(),
+        "[]"
+    );
+}
+
+#[test]
+fn test_run_with_skewed_dates() {
+    for action in ["warn", "error"] {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let config = setup_config(&temp, "[]");
+        let csv = temp.path().join("checking.csv");
+        fs::write(&csv, "Date,Payee,Amount\n0024-10-23,APPLE.COM,-2.99\n").unwrap();
+        let rules = config.join("tidymoney").join("rules.toml");
+        let mut data = fs::read_to_string(&rules).unwrap();
+        data.push_str(&format!("\n[checks]\ndate_skew = {action:#?}\n"));
+        fs::write(&rules, data).unwrap();
+
+        let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if action == "warn" {
+            // The transaction is reported, then dropped as being before the timestamp.
+            assert_eq!(output.status.code(), Some(2));
+            assert!(stderr.contains("more than 10 years from today"));
+            assert!(stderr.contains("    checking: 0024-10-23 APPLE.COM"));
+            assert!(!csv.exists());
+        } else {
+            assert_eq!(output.status.code(), Some(1));
+            assert!(stderr.contains("has a transaction dated 0024-10-23"));
+            assert!(csv.exists());
+        }
+    }
+}
+
+#[test]
+fn test_run_with_post_run_hook() {
+    for fatal in [false, true] {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let config = setup_config(&temp, "[]");
+        let csv = setup_csv(&temp);
+
+        // The hook is given the run date and output directory.
+        let sentinel = temp.path().join("sentinel");
+        let rules = config.join("tidymoney").join("rules.toml");
+        let mut data = fs::read_to_string(&rules).unwrap();
+        data.push_str(&format!(
+            "\n[hooks]\npost_run = 'echo \"$1 $TIDYMONEY_OUTPUT_DIR\" > {}'\n",
+            sentinel.display()
+        ));
+        fs::write(&rules, data).unwrap();
+
+        let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+        assert_eq!(output.status.code(), Some(0));
+        let (date, output_dir) = fs::read_to_string(&sentinel)
+            .unwrap()
+            .trim()
+            .split_once(' ')
+            .map(|(x, y)| (x.to_string(), PathBuf::from(y)))
+            .unwrap();
+        assert_eq!(
+            output_dir,
+            temp.path().join("storage").join("new").join(&date)
+        );
+        assert!(output_dir.is_dir());
+
+        // A failing hook is only an error if it is marked as fatal.
+        let mut data = fs::read_to_string(&rules).unwrap();
+        data.push_str(&format!("post_run_fatal = {fatal}\n"));
+        fs::write(&rules, data.replace(" > ", " && false > ")).unwrap();
+        let csv = setup_csv(&temp);
+        let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+        assert_eq!(output.status.code(), Some(if fatal { 1 } else { 2 }));
+        assert!(!output.stderr.is_empty());
+    }
+}
+
+#[test]
+fn test_run_aggregate_by_payee() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+
+    let output = tidymoney(
+        &config,
+        &["run", "--aggregate", "payee", csv.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let written = glob::glob(
+        temp.path()
+            .join("storage/new/*/checking.csv")
+            .to_str()
+            .unwrap(),
+    )
+    .unwrap()
+    .next()
+    .unwrap()
+    .unwrap();
+    assert_eq!(
+        fs::read_to_string(written).unwrap(),
+        "Payee,Count,Total\nWendy's,1,-12.54\nApple,1,-2.99\n"
+    );
+
+    // Only known aggregations are accepted.
+    let csv = setup_csv(&temp);
+    let output = tidymoney(
+        &config,
+        &["run", "--aggregate", "date", csv.to_str().unwrap()],
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown aggregation"));
+    assert!(csv.exists());
+}
+
+#[test]
+fn test_run_with_output_dir() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let export = temp.path().join("export").join("here");
+    let stamps = config.join("tidymoney").join("timestamps.json");
+    let original_stamps = fs::read_to_string(&stamps).unwrap();
+    let mirror = temp.path().join("mirror");
+    fs::create_dir(&mirror).unwrap();
+    let rule_file = config.join("tidymoney").join("rules.toml");
+    let rules = fs::read_to_string(&rule_file).unwrap();
+    fs::write(&rule_file, format!("{rules}mirrors = [{mirror:#?}]\n")).unwrap();
+
+    // Only showing the differences does not create the directory.
+    let args = ["run", "--output-dir", export.to_str().unwrap()];
+    let output = tidymoney(
+        &config,
+        &[&args[..], &["--diff", csv.to_str().unwrap()]].concat(),
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!export.exists());
+
+    let output = tidymoney(&config, &[&args[..], &[csv.to_str().unwrap()]].concat());
+    assert_eq!(output.status.code(), Some(0));
+
+    // The new files were written to the given directory instead of storage
+    // or the mirror, and the raw file was left for the next regular run.
+    let found = |pattern: &str| {
+        glob::glob(temp.path().join(pattern).to_str().unwrap())
+            .unwrap()
+            .count()
+    };
+    assert_eq!(found("export/here/new/*/checking.csv"), 1);
+    assert_eq!(found("export/here/old/*"), 0);
+    for untouched in ["storage", "mirror"] {
+        assert_eq!(
+            fs::read_dir(temp.path().join(untouched)).unwrap().count(),
+            0
+        );
+    }
+    assert!(csv.exists());
+
+    // The timestamps are left alone.
+    assert_eq!(fs::read_to_string(&stamps).unwrap(), original_stamps);
+}
+
+#[test]
+fn test_run_with_now() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+
+    let output = tidymoney(
+        &config,
+        &["run", "--now", "2024-10-22", csv.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    // The output and archive are dated by --now, and later transactions are left out.
+    let storage = temp.path().join("storage");
+    assert_eq!(
+        fs::read_to_string(storage.join("new/2024-10-22/checking.csv")).unwrap(),
+        "Date,Payee,Category,Memo,Amount,Check#\n2024-10-21,Wendy's,,,-12.54,\n"
+    );
+    assert!(storage.join("old/2024-10-22/checking.csv").is_file());
+
+    // Invalid dates are rejected.
+    let csv = setup_csv(&temp);
+    let output = tidymoney(
+        &config,
+        &["run", "--now", "10/22/2024", csv.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_run_with_profile() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+
+    let output = tidymoney(&config, &["run", "--profile", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+
+    // The time of each stage is reported.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for stage in ["reading", "rules", "filtering", "writing", "archiving"] {
+        assert!(stderr.contains(&format!("    {stage}: ")), "{stderr}");
+    }
+
+    // The run itself is unaffected.
+    let written: Vec<_> = glob::glob(
+        temp.path()
+            .join("storage/new/*/checking.csv")
+            .to_str()
+            .unwrap(),
+    )
+    .unwrap()
+    .collect();
+    assert_eq!(written.len(), 1);
+    assert_eq!(
+        fs::read_to_string(written[0].as_ref().unwrap()).unwrap(),
+        "Date,Payee,Category,Memo,Amount,Check#\n2024-10-23,Apple,,,-2.99,\n2024-10-21,Wendy's,,,-12.54,\n"
+    );
+}
+
+#[test]
+fn test_run_with_limit() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+
+    let output = tidymoney(&config, &["run", "--limit", "1", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "checking:\nDate,Payee,Category,Memo,Amount,Check#\n2024-10-23,Apple,,,-2.99,\n"
+    );
+
+    // Nothing was written or moved, and the timestamps did not advance.
+    assert!(csv.exists());
+    assert_eq!(
+        fs::read_dir(temp.path().join("storage")).unwrap().count(),
+        0
+    );
+    assert_eq!(
+        fs::read_to_string(config.join("tidymoney").join("timestamps.json")).unwrap(),
+        "[]"
+    );
+}
+
+#[test]
+fn test_run_only_and_exclude() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let csv = csv.to_str().unwrap();
+
+    // Unknown accounts and runs with nothing left to process are errors.
+    for args in [
+        ["run", "--only", "savings", csv],
+        ["run", "--exclude", "checking", csv],
+    ] {
+        let output = tidymoney(&config, &args);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(Path::new(csv).exists());
+    }
+
+    let output = tidymoney(&config, &["run", "--only", "checking", csv]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!Path::new(csv).exists());
+}
+
+#[test]
+fn test_run_on_unmatched() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let unknown = temp.path().join("unknown.csv");
+    fs::write(&unknown, "When,Who,HowMuch\n2024-10-22,ACME,-1.00\n").unwrap();
+    let args = |mode: &'static str| {
+        vec![
+            "run".to_string(),
+            "--on-unmatched".to_string(),
+            mode.to_string(),
+            csv.to_str().unwrap().to_string(),
+            unknown.to_str().unwrap().to_string(),
+        ]
+    };
+
+    // By default the run stops before anything is moved.
+    let error = args("error");
+    let output = tidymoney(
+        &config,
+        &error.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown.csv"));
+    assert!(csv.exists());
+    assert!(unknown.exists());
+
+    // Skipping processes and archives the matching file, and lists the others.
+    let skip = args("skip");
+    let output = tidymoney(
+        &config,
+        &skip.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("skipped because they do not match any account"));
+    assert!(stderr.ends_with(&format!("    {}\n", unknown.display())));
+    assert!(!csv.exists());
+    assert!(unknown.exists());
+
+    // A run with nothing that matches is an error.
+    let output = tidymoney(
+        &config,
+        &["run", "--on-unmatched", "skip", unknown.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(unknown.exists());
+}
+
+#[test]
+fn test_run_with_empty_files() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let headers_only = temp.path().join("headers-only.csv");
+    let blank = temp.path().join("blank.csv");
+    fs::write(&headers_only, "Date,Payee,Amount\n").unwrap();
+    fs::write(&blank, "").unwrap();
+
+    // Empty files are reported but do not stop the run, and are stored as usual.
+    let output = tidymoney(
+        &config,
+        &[
+            "run",
+            csv.to_str().unwrap(),
+            headers_only.to_str().unwrap(),
+            blank.to_str().unwrap(),
+        ],
+    );
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("These files have no transactions"));
+    assert!(stderr.contains("headers-only.csv"));
+    assert!(stderr.contains("blank.csv"));
+    assert!(!csv.exists());
+    assert!(!headers_only.exists());
+    assert!(!blank.exists());
+
+    // Empty files may be left where they are instead.
+    fs::write(&blank, "").unwrap();
+    let output = tidymoney(&config, &["run", "--skip-empty", blank.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("left in place because they have no transactions"));
+    assert!(blank.exists());
+}
+
+#[test]
+fn test_run_diff() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    let stamps = fs::read_to_string(config.join("tidymoney").join("timestamps.json")).unwrap();
+
+    // The same transactions give no change.
+    let csv = setup_csv(&temp);
+    let args = [
+        "run",
+        "--diff",
+        "--since",
+        "2024-01-01",
+        csv.to_str().unwrap(),
+    ];
+    let output = tidymoney(&config, &args);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("checking.csv: no changes\n"));
+
+    // One row is replaced by another.
+    fs::write(
+        &csv,
+        "Date,Payee,Amount\n2024-10-23,APPLE.COM,-2.99\n2024-10-22,Subway,-8.10\n",
+    )
+    .unwrap();
+    let output = tidymoney(&config, &args);
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.ends_with(
+        "checking.csv:\n- 2024-10-21,Wendy's,,,-12.54,\n+ 2024-10-22,Subway,,,-8.10,\n"
+    ));
+
+    // Nothing was written or moved.
+    assert!(csv.exists());
+    assert_eq!(
+        fs::read_to_string(config.join("tidymoney").join("timestamps.json")).unwrap(),
+        stamps
+    );
+}
+
+#[test]
+fn test_run_twice_in_one_day() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+
+    // The files from the first run are reported but kept.
+    let new = fs::read_dir(temp.path().join("storage").join("new"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let stale = new.join("stale.csv");
+    fs::write(&stale, "").unwrap();
+    let csv = setup_csv(&temp);
+    let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("earlier run"));
+    assert!(stderr.contains(stale.to_str().unwrap()));
+    assert!(stale.exists());
+
+    // With --fresh they are removed instead.
+    let csv = setup_csv(&temp);
+    let output = tidymoney(&config, &["run", "--fresh", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stderr.is_empty());
+    assert!(!stale.exists());
+}
+
+#[test]
+fn test_run_with_missing_storage() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let storage = temp.path().join("storage");
+    fs::remove_dir(&storage).unwrap();
+
+    // Without the flag the missing directory is an error.
+    let output = tidymoney(&config, &["run", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!storage.exists());
+
+    // With the flag the directory is created and the run proceeds.
+    let output = tidymoney(&config, &["run", "--create-storage", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(storage.join("new").is_dir());
+    assert!(!csv.exists());
+}
+
+#[test]
+fn test_run_quiet() {
+    for quiet in [false, true] {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let config = setup_config(&temp, r#"[{"account": "checking", "date": "2024-10-22"}]"#);
+        let csv = setup_csv(&temp);
+        fs::remove_dir(temp.path().join("storage")).unwrap();
+
+        // Creating storage and skipping a transaction are normally reported.
+        let mut args = vec!["run", "--create-storage", csv.to_str().unwrap()];
+        if quiet {
+            args.push("--quiet");
+        }
+        let output = tidymoney(&config, &args);
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(output.stdout.is_empty(), quiet);
+    }
+}
+
+#[test]
+fn test_create_full_config() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = temp.path().join("config");
+    let storage = temp.path().join("storage");
+    fs::create_dir(&storage).unwrap();
+
+    let output = tidymoney(&config, &["create-config", "--full"]);
+    assert_eq!(output.status.code(), Some(0));
+    let template = fs::read_to_string(config.join("tidymoney").join("rules.toml")).unwrap();
+    let template = template.replace("\"/path/to/storage/directory\"", &format!("{:#?}", storage));
+
+    // The template is valid both as written and with every optional key in use.
+    let uncommented = template
+        .lines()
+        .map(|line| match line.strip_prefix('#') {
+            Some(rest) if !rest.starts_with([' ', '#']) && !rest.is_empty() => rest,
+            _ => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert_ne!(template, uncommented);
+    for rules in [template, uncommented] {
+        if let Err(e) = RuleFileData::new(&rules) {
+            panic!("{e}\n{rules}");
+        }
+    }
+}
+
+#[test]
+fn test_identify() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let other = temp.path().join("other.csv");
+    fs::write(&other, "Date,Description,Amount\n").unwrap();
+
+    let output = tidymoney(&config, &["identify", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}: checking\n", csv.display())
+    );
+
+    let output = tidymoney(
+        &config,
+        &["identify", csv.to_str().unwrap(), other.to_str().unwrap()],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{}: checking\n{}: no match for headers [\"Date\", \"Description\", \"Amount\"]\n",
+            csv.display(),
+            other.display()
+        )
+    );
+
+    // Nothing was moved.
+    assert!(csv.exists());
+}
+
+#[test]
+fn test_color() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let csv = csv.to_str().unwrap();
+
+    // The output is not a terminal, so it is only colored if forced.
+    for (color, expected) in [
+        (None, false),
+        (Some("never"), false),
+        (Some("always"), true),
+    ] {
+        for args in [vec!["validate"], vec!["stats", csv], vec!["identify", csv]] {
+            let mut args = args;
+            if let Some(color) = color {
+                args.extend(["--color", color]);
+            }
+            let output = tidymoney(&config, &args);
+            assert_eq!(output.status.code(), Some(0));
+            assert!(!output.stdout.is_empty());
+            assert_eq!(output.stdout.contains(&b'\x1b'), expected, "{args:?}");
+        }
+    }
+}
+
+#[test]
+fn test_validate_json() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+
+    let output = tidymoney(&config, &["validate", "--json"]);
+    assert_eq!(output.status.code(), Some(0));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result, serde_json::json!({"ok": true, "errors": []}));
+
+    // Break the rules file by removing the storage directory.
+    fs::remove_dir(temp.path().join("storage")).unwrap();
+    let output = tidymoney(&config, &["validate", "--json"]);
+    assert_eq!(output.status.code(), Some(1));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["ok"], false);
+    assert_eq!(result["errors"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_migrate_config() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let rule_file = config.join("tidymoney").join("rules.toml");
+    let original = fs::read_to_string(&rule_file).unwrap();
+    assert!(!original.contains("version"));
+    assert_eq!(tidymoney(&config, &["validate"]).status.code(), Some(0));
+
+    let output = tidymoney(&config, &["migrate-config"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Set the version to 1"));
+    assert!(String::from_utf8_lossy(&output.stderr).is_empty());
+    assert_eq!(
+        fs::read_to_string(config.join("tidymoney").join("rules.toml.bak")).unwrap(),
+        original
+    );
+    assert_eq!(tidymoney(&config, &["validate"]).status.code(), Some(0));
+
+    // Migrating again has nothing to do.
+    let output = tidymoney(&config, &["migrate-config"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("is already up to date"));
+}
+
+#[test]
+fn test_stats_json() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+
+    let output = tidymoney(&config, &["stats", "--json", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        result,
+        serde_json::json!({
+            "accounts": {
+                "checking": {
+                    "transactions": 2,
+                    "total": "-15.53",
+                    "categories": {"(uncategorized)": "-15.53"},
+                    "unmatched_payees": ["Wendy's"],
+                }
+            }
+        })
+    );
+
+    // Nothing was moved.
+    assert!(csv.exists());
+}
+
+#[test]
+fn test_explain_json() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+
+    let output = tidymoney(&config, &["explain", "--json", csv.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let result = result.as_array().unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0]["account"], "checking");
+    assert_eq!(result[0]["payee"], "Apple");
+    assert_eq!(result[0]["payee_rule"], "Apple");
+    assert_eq!(result[1]["payee"], "Wendy's");
+    assert_eq!(result[1]["payee_rule"], serde_json::Value::Null);
+
+    // Nothing was moved.
+    assert!(csv.exists());
+}
+
+#[test]
+fn test_show_timestamps() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(
+        &temp,
+        r#"[{"account": "savings", "date": "2024-03-01"}, {"account": "checking", "date": "2024-10-20", "note": "by hand"}]"#,
+    );
+
+    let output = tidymoney(&config, &["show-timestamps"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "checking: 2024-10-20\nsavings: 2024-03-01\n"
+    );
+
+    let output = tidymoney(&config, &["show-timestamps", "--account", "savings"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "savings: 2024-03-01\n"
+    );
+
+    let output = tidymoney(&config, &["show-timestamps", "--account", "visa"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No timestamp is stored"));
+}
+
+#[test]
+fn test_rules_from_stdin() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let config = setup_config(&temp, "[]");
+    let csv = setup_csv(&temp);
+    let rules = fs::read_to_string(config.join("tidymoney").join("rules.toml")).unwrap();
+
+    // Use an empty configuration directory so the default rules file is not found.
+    let work = temp.path().join("work");
+    fs::create_dir(&work).unwrap();
+    let run = |rules: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_tidymoney"))
+            .args(["run", "--config", "-", csv.to_str().unwrap()])
+            .env("XDG_CONFIG_HOME", temp.path().join("empty"))
+            .current_dir(&work)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(rules.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    // The timestamps are not kept in the current directory by default.
+    let output = run(&rules);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("must give the timestamps path"));
+    assert!(csv.exists());
+    assert!(!work.join("timestamps.json").exists());
+
+    // They are kept wherever the rules say.
+    let stamps = temp.path().join("stamps.json");
+    let output = run(&format!("{rules}timestamps = {stamps:#?}\n"));
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!csv.exists());
+    assert_ne!(fs::read_to_string(&stamps).unwrap(), "[]");
+}
@@ -7,8 +7,9 @@ use pretty_assertions::assert_eq;
 use rstest::{fixture, rstest};
 
 use tidymoney::{
-    account_for_dates_in_transactions, as_hashmap, process_csv_files, write_transactions_to_file,
-    RuleFileData, TimestampKeeper, DATE_FORMAT,
+    account_for_dates_in_transactions, as_hashmap, process_csv_files, process_in_memory,
+    select_csv_files, write_transactions_to_file, AccountSelection, DateOverrides, RuleFileData,
+    TimestampKeeper, DATE_FORMAT,
 };
 
 #[rstest]
@@ -35,15 +36,17 @@ fn test_full_integration(
 
     // Filter transactions according to the dates from the timestamps file.
     let now = NaiveDate::from_ymd_opt(2024, 10, 25).unwrap();
-    account_for_dates_in_transactions(&now, &mut processed, &mut stamps);
+    account_for_dates_in_transactions(&now, &DateOverrides::default(), &mut processed, &mut stamps);
 
     // Write the new transactions to file
     let now = now.format(DATE_FORMAT).to_string();
-    write_transactions_to_file(&now, &temp, &processed).unwrap();
+    write_transactions_to_file(&now, &rules.paths, &processed, false).unwrap();
 
     // Ensure the written transactions appear as expected.
     let created_files = glob::glob(
-        temp.path()
+        rules
+            .paths
+            .storage
             .join("new")
             .join(now)
             .join("*.csv")
@@ -79,6 +82,220 @@ fn test_full_integration(
     assert_eq!(stamps, new_stamps);
 }
 
+#[rstest]
+#[case(vec!["ally"], vec![], vec!["ally"])]
+#[case(vec!["ally", "discover"], vec![], vec!["ally", "discover"])]
+#[case(vec![], vec!["ally"], vec!["bank_of_america", "discover"])]
+fn test_account_selection(
+    #[case] only: Vec<&str>,
+    #[case] exclude: Vec<&str>,
+    #[case] expected: Vec<&str>,
+    sample_csv_files: Vec<String>,
+) {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let rules = RuleFileData::new(sample_rule_file(&temp)).unwrap();
+    let mut sample_csv = vec![];
+    for (i, data) in sample_csv_files.iter().enumerate() {
+        sample_csv.push(temp.path().join(format!("{i}.csv")));
+        fs::write(sample_csv.last().unwrap(), data).unwrap();
+    }
+
+    // Only the files for the selected accounts are processed.
+    let selection = AccountSelection {
+        only: only.into_iter().map(|x| x.to_string()).collect(),
+        exclude: exclude.into_iter().map(|x| x.to_string()).collect(),
+    };
+    selection.validate(&rules).unwrap();
+    let selected = select_csv_files(sample_csv, &rules, &selection).unwrap();
+    let processed = process_csv_files(&selected, &rules).unwrap();
+    let mut labels: Vec<_> = processed.keys().map(|x| x.as_str()).collect();
+    labels.sort();
+    assert_eq!(labels, expected);
+}
+
+#[test]
+fn test_memo_from_assigned_category() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let rule_data = format!(
+        indoc! { r#"
+        [payees]
+        Transfer = "XFER"
+
+        [categories]
+        Savings = {{Payee = "Transfer"}}
+
+        [memos]
+        Transfer = {{Category = "Savings"}}
+
+        [[mappings.csv]]
+        label = "checking"
+        identify = ["Date", "Payee", "Amount"]
+
+        [paths]
+        storage = {:#?}
+        "# },
+        temp.path()
+    );
+    let stamps_data = r#"[{"account": "checking", "date": "2024-01-01"}]"#;
+    let csv = temp.path().join("checking.csv");
+    fs::write(
+        &csv,
+        indoc! { r#"
+        Date,Payee,Amount
+        2024-01-05,ONLINE XFER TO SAVINGS,-100.00
+        2024-01-07,ACE HARDWARE,-12.54
+        "# },
+    )
+    .unwrap();
+
+    // The memo rule sees the category assigned by the category rule.
+    let now = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+    let result = process_in_memory(
+        rule_data,
+        stamps_data,
+        &vec![&csv],
+        &now,
+        &DateOverrides::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        result.transactions,
+        as_hashmap(vec![(
+            "checking",
+            indoc! {
+                r#"
+                Date,Payee,Category,Memo,Amount,Check#
+                2024-01-05,Transfer,Savings,Transfer,-100.00,
+                2024-01-07,ACE HARDWARE,,,-12.54,
+                "#
+            }
+        )])
+    );
+}
+
+#[test]
+fn test_categories_by_account() {
+    let temp = tempdir::TempDir::new("test").unwrap();
+    let rule_data = format!(
+        indoc! { r#"
+        [payees]
+        Staples = "STAPLES"
+
+        [categories]
+        "Office Supplies" = {{Payee = "Staples", Account = "business"}}
+        "School Supplies" = {{Payee = "Staples", Account = "personal"}}
+
+        [[mappings.csv]]
+        label = "personal"
+        identify = ["Date", "Payee", "Amount"]
+
+        [[mappings.csv]]
+        label = "business"
+        identify = ["Posted Date", "Payee", "Amount"]
+        translate = {{Date = "Posted Date"}}
+
+        [paths]
+        storage = {:#?}
+        "# },
+        temp.path()
+    );
+    let personal = temp.path().join("personal.csv");
+    fs::write(
+        &personal,
+        "Date,Payee,Amount\n2024-01-05,STAPLES #123,-20.00\n",
+    )
+    .unwrap();
+    let business = temp.path().join("business.csv");
+    fs::write(
+        &business,
+        "Posted Date,Payee,Amount\n2024-01-06,STAPLES #123,-45.00\n",
+    )
+    .unwrap();
+
+    // The same payee is categorized according to the account it came from.
+    let now = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+    let result = process_in_memory(
+        rule_data,
+        "[]",
+        &vec![&personal, &business],
+        &now,
+        &DateOverrides::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        result.transactions,
+        as_hashmap(vec![
+            (
+                "personal",
+                "Date,Payee,Category,Memo,Amount,Check#\n2024-01-05,Staples,School Supplies,,-20.00,\n"
+            ),
+            (
+                "business",
+                "Date,Payee,Category,Memo,Amount,Check#\n2024-01-06,Staples,Office Supplies,,-45.00,\n"
+            ),
+        ])
+    );
+}
+
+#[test]
+fn test_filter_on_transaction_or_posted_date() {
+    for (filter_on_posted_date, expected) in [
+        (
+            false,
+            "Date,Payee,Category,Memo,Amount,Check#\n2024-01-06,WENDY'S,,,-12.54,\n",
+        ),
+        (
+            true,
+            "Date,Payee,Category,Memo,Amount,Check#\n2024-01-03,ACE,,,-15.43,\n2024-01-06,WENDY'S,,,-12.54,\n",
+        ),
+    ] {
+        let temp = tempdir::TempDir::new("test").unwrap();
+        let rule_data = format!(
+            indoc! { r#"
+            [payees]
+            Apple = "APPLE"
+
+            [[mappings.csv]]
+            label = "checking"
+            identify = ["Trans Date", "Post Date", "Payee", "Amount"]
+            translate = {{Date = "Trans Date", PostedDate = "Post Date"}}
+            filter_on_posted_date = {}
+
+            [paths]
+            storage = {:#?}
+            "# },
+            filter_on_posted_date,
+            temp.path()
+        );
+        let csv = temp.path().join("checking.csv");
+        fs::write(
+            &csv,
+            indoc! { r#"
+            Trans Date,Post Date,Payee,Amount
+            2024-01-03,2024-01-05,ACE,-15.43
+            2024-01-06,2024-01-07,WENDY'S,-12.54
+            "# },
+        )
+        .unwrap();
+
+        // The first transaction was made before the last run but posted after it,
+        // so it is only new when filtering on the posted date.
+        let now = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let result = process_in_memory(
+            rule_data,
+            r#"[{"account": "checking", "date": "2024-01-04"}]"#,
+            &vec![&csv],
+            &now,
+            &DateOverrides::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            result.transactions,
+            as_hashmap(vec![("checking", expected)])
+        );
+    }
+}
+
 fn sample_rule_file(tempdir: impl AsRef<Path>) -> String {
     let transactions = tempdir.as_ref().join("transactions");
     fs::create_dir(&transactions).unwrap();
@@ -180,7 +397,7 @@ fn sample_timestamps() -> String {
 
 #[fixture]
 fn sample_csv_files() -> Vec<String> {
-    vec![
+    [
         indoc!{ r#"
             Trans. Date,Post Date,Description,Amount,Category
             09/14/2024,09/14/2024,"AMAZON.COM*1234567",29.99,"Merchandise"